@@ -0,0 +1,159 @@
+//! Packs every folder under `assets/textures/rpg` into an atlas PNG plus a
+//! `.atlas.ron` file describing where each source image ended up.
+//!
+//! ```text
+//! cargo run --bin pack_atlases [input] [output]
+//! ```
+//!
+//! Both paths are relative to `assets/` and default to `textures/rpg` and
+//! `textures/rpg/packed`. Frames keep the order of their file names, so
+//! `rock02` always follows `rock01`.
+
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use image::{GenericImage, RgbaImage};
+use serde::Serialize;
+
+const ASSETS: &str = "assets";
+const PADDING: u32 = 1;
+const MAX_SIZE: u32 = 4096;
+
+#[derive(Serialize)]
+struct PackedAtlas {
+    image: String,
+    size: (f32, f32),
+    frames: Vec<PackedFrame>,
+}
+
+#[derive(Serialize)]
+struct PackedFrame {
+    name: String,
+    source: String,
+    position: (f32, f32),
+    size: (f32, f32),
+    pivot: (f32, f32),
+}
+
+/// Atlas size and each source's position.
+type Packing = ((u32, u32), Vec<(u32, u32)>);
+
+struct Source {
+    name: String,
+    asset_path: String,
+    image: RgbaImage,
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = std::env::args().skip(1);
+    let input = args.next().unwrap_or_else(|| "textures/rpg".to_string());
+    let output = args.next().unwrap_or_else(|| "textures/rpg/packed".to_string());
+
+    let input_dir = Path::new(ASSETS).join(&input);
+    let output_dir = Path::new(ASSETS).join(&output);
+    fs::create_dir_all(&output_dir)?;
+
+    let mut folders: Vec<PathBuf> = fs::read_dir(&input_dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_dir() && *path != output_dir)
+        .collect();
+    folders.sort();
+
+    for folder in folders {
+        let folder_name = folder.file_name().unwrap().to_string_lossy().to_string();
+        let mut sources = Vec::new();
+        collect_sources(&folder, &mut sources)?;
+        if sources.is_empty() {
+            continue;
+        }
+        sources.sort_by(|a, b| a.asset_path.cmp(&b.asset_path));
+
+        let (size, positions) = pack(&sources)?;
+        let mut atlas_image = RgbaImage::new(size.0, size.1);
+        for (source, position) in sources.iter().zip(&positions) {
+            atlas_image.copy_from(&source.image, position.0, position.1)?;
+        }
+
+        let image_path = format!("{output}/{folder_name}.png");
+        atlas_image.save(Path::new(ASSETS).join(&image_path))?;
+
+        let atlas = PackedAtlas {
+            image: image_path,
+            size: (size.0 as f32, size.1 as f32),
+            frames: sources
+                .iter()
+                .zip(&positions)
+                .map(|(source, position)| PackedFrame {
+                    name: source.name.clone(),
+                    source: source.asset_path.clone(),
+                    position: (position.0 as f32, position.1 as f32),
+                    size: (source.image.width() as f32, source.image.height() as f32),
+                    pivot: (0.5, 0.5),
+                })
+                .collect(),
+        };
+        let ron = ron::ser::to_string_pretty(&atlas, ron::ser::PrettyConfig::default())?;
+        fs::write(output_dir.join(format!("{folder_name}.atlas.ron")), ron)?;
+
+        println!(
+            "{folder_name}: {} frames into {}x{}",
+            atlas.frames.len(),
+            size.0,
+            size.1
+        );
+    }
+    Ok(())
+}
+
+fn collect_sources(dir: &Path, sources: &mut Vec<Source>) -> Result<(), Box<dyn Error>> {
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_sources(&path, sources)?;
+        } else if path.extension().is_some_and(|ext| ext == "png") {
+            let asset_path = path
+                .strip_prefix(ASSETS)?
+                .to_string_lossy()
+                .replace('\\', "/");
+            sources.push(Source {
+                name: path.file_stem().unwrap().to_string_lossy().to_string(),
+                asset_path,
+                image: image::open(&path)?.to_rgba8(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Shelf packing: tallest images first, left to right, growing the square
+/// atlas until everything fits. Positions are in the same order as `sources`.
+fn pack(sources: &[Source]) -> Result<Packing, Box<dyn Error>> {
+    let mut order: Vec<usize> = (0..sources.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(sources[i].image.height()));
+
+    let mut side = 64;
+    'grow: while side <= MAX_SIZE {
+        let mut positions = vec![(0, 0); sources.len()];
+        let (mut x, mut y, mut shelf_height) = (0, 0, 0);
+        for &i in &order {
+            let (width, height) = sources[i].image.dimensions();
+            if x + width > side {
+                x = 0;
+                y += shelf_height + PADDING;
+                shelf_height = 0;
+            }
+            if x + width > side || y + height > side {
+                side *= 2;
+                continue 'grow;
+            }
+            positions[i] = (x, y);
+            x += width + PADDING;
+            shelf_height = shelf_height.max(height);
+        }
+        return Ok(((side, side), positions));
+    }
+    Err(format!("images don't fit in a {MAX_SIZE}x{MAX_SIZE} atlas").into())
+}
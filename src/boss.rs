@@ -0,0 +1,610 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashMap},
+};
+use bevy_inspector_egui::{egui, Context, Inspectable};
+use serde::Deserialize;
+
+use crate::{
+    character::{character_size, collides, AnimationTimer, Motion},
+    combat::{Died, Health, Hitbox, Hurtbox, Knockback, Team},
+    dialogue::GameFlags,
+    inspector::Fields,
+    inventory::spawn_toast,
+    loading::LoadingAssets,
+    mob::{spawn_mob, MobDatabase, MobDatabaseHandle},
+    player::Player,
+    projectile::{Pattern, ProjectileDef, Shoot},
+    sprites::{Mobs, NamedAtlas},
+    tilemap::{MapData, MapLayer, MapObject, TileCollider, TileEdit},
+    AppState, GameConfig,
+};
+
+pub struct BossPlugin;
+
+impl Plugin for BossPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<BossDatabase>()
+            .init_asset_loader::<BossDatabaseLoader>()
+            .add_system_set(SystemSet::on_enter(AppState::Loading).with_system(load_bosses))
+            .add_system_set(SystemSet::on_enter(AppState::InGame).with_system(spawn_bosses))
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(engage_bosses.label("engage_bosses").after("player_movement"))
+                    .with_system(boss_phases.label("boss_phases").after("apply_damage"))
+                    .with_system(
+                        run_boss_scripts
+                            .label("boss_scripts")
+                            .after("engage_bosses")
+                            .after("boss_phases"),
+                    )
+                    .with_system(update_boss_bar.after("boss_phases"))
+                    .with_system(defeat_bosses.after("apply_damage")),
+            );
+    }
+}
+
+pub const BOSSES_PATH: &str = "game.bosses.ron";
+
+/// Seconds a boss stands still after shooting or summoning.
+const VOLLEY_RECOVERY: f32 = 0.6;
+const SUMMON_RECOVERY: f32 = 1.;
+
+/// Every boss, by id, as read from `game.bosses.ron`.
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "e6c0a4f2-7d15-4b3e-8f9a-0b2d6c8e4a71"]
+pub struct BossDatabase(pub HashMap<String, BossDef>);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BossDef {
+    pub name: String,
+    /// Name of an atlas in the `mobs` section of the sprite manifest.
+    pub sprite: String,
+    pub health: u32,
+    /// Flying speed in tiles per second when chasing.
+    pub speed: f32,
+    /// Damage dealt to the player on touch.
+    pub damage: u32,
+    /// What the boss shoots in a `Volley`.
+    pub projectile: ProjectileDef,
+    /// Set in `GameFlags` once the boss is beaten; beaten bosses stay gone.
+    pub defeated_flag: String,
+    /// In order of falling health; the first has to start at full health.
+    pub phases: Vec<BossPhase>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BossPhase {
+    /// Fraction of health at or under which the phase starts.
+    #[serde(default = "default_below")]
+    pub below: f32,
+    /// Shown when the phase starts.
+    #[serde(default)]
+    pub announce: Option<String>,
+    /// Played in a loop for as long as the phase lasts.
+    pub script: Vec<BossAction>,
+}
+
+impl BossDef {
+    /// Catches values the boss's timers and movement can't work with.
+    fn validate(&self) -> Result<(), String> {
+        if !self.speed.is_finite() || self.speed <= 0. {
+            return Err(format!("`speed` has to be above 0, not {}", self.speed));
+        }
+        self.projectile.validate().map_err(|err| format!("Projectile: {err}"))?;
+        for action in self.phases.iter().flat_map(|phase| &phase.script) {
+            let seconds = action.duration();
+            if !seconds.is_finite() || seconds < 0. {
+                return Err(format!("{action:?} can't last {seconds} seconds"));
+            }
+            if let BossAction::Charge { speed, .. } = action {
+                if !speed.is_finite() || *speed <= 0. {
+                    return Err(format!("{action:?} has to have a speed above 0"));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn default_below() -> f32 {
+    1.
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum BossAction {
+    /// Stands still, in seconds.
+    Wait(f32),
+    /// Flies after the player.
+    Chase { seconds: f32 },
+    /// Dashes at where the player was when the charge started, in tiles per second.
+    Charge { speed: f32, seconds: f32 },
+    /// Shoots the boss's projectile at the player.
+    Volley(Pattern),
+    /// Calls in mobs from `game.mobs.ron` until `count` of them are about.
+    Summon { mob: String, count: u32 },
+}
+
+impl BossAction {
+    /// How long the action takes, in seconds.
+    fn duration(&self) -> f32 {
+        match self {
+            BossAction::Wait(seconds)
+            | BossAction::Chase { seconds }
+            | BossAction::Charge { seconds, .. } => *seconds,
+            BossAction::Volley(_) => VOLLEY_RECOVERY,
+            BossAction::Summon { .. } => SUMMON_RECOVERY,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct BossDatabaseLoader;
+
+impl AssetLoader for BossDatabaseLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let database: BossDatabase = ron::de::from_bytes(bytes)?;
+            for (id, def) in &database.0 {
+                def.validate()
+                    .map_err(|err| bevy::asset::Error::msg(format!("Boss `{id}`: {err}")))?;
+            }
+            load_context.set_default_asset(LoadedAsset::new(database));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["bosses.ron"]
+    }
+}
+
+/// A boss and its arena, as placed in a map's objects file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BossSpawn {
+    /// Id of the boss in `game.bosses.ron`.
+    pub boss: String,
+    pub position: (usize, usize),
+    /// Top left and bottom right tiles of the arena. The fight starts when the
+    /// player steps inside.
+    pub arena: ((usize, usize), (usize, usize)),
+    /// Tiles fenced off for as long as the fight lasts.
+    pub exits: Vec<(usize, usize)>,
+}
+
+#[derive(Component, Debug)]
+pub struct Boss {
+    pub def: BossDef,
+    pub phase: usize,
+    home: Vec2,
+    arena: ((usize, usize), (usize, usize)),
+    exits: Vec<(usize, usize)>,
+    /// What the exits held before the arena closed, while the fight is on.
+    closed: Option<Vec<((usize, usize), char)>>,
+    step: usize,
+    timer: Timer,
+    started: bool,
+    charge: Vec2,
+}
+
+impl Inspectable for Boss {
+    type Attributes = ();
+
+    fn ui(&mut self, ui: &mut egui::Ui, _: Self::Attributes, context: &mut Context) -> bool {
+        Fields::show(ui, context, |fields| {
+            fields
+                .show_debug("boss", &self.def.name)
+                .show_debug("phase", &self.phase)
+                .show_debug("action", &self.action())
+                .edit("home", &mut self.home)
+                .show_debug("engaged", &self.is_engaged())
+                .timer("timer", &mut self.timer)
+                .edit("charge", &mut self.charge);
+        })
+    }
+}
+
+impl Boss {
+    pub fn is_engaged(&self) -> bool {
+        self.closed.is_some()
+    }
+
+    fn action(&self) -> Option<&BossAction> {
+        self.def.phases.get(self.phase)?.script.get(self.step)
+    }
+
+    fn start_phase(&mut self, phase: usize) {
+        self.phase = phase;
+        self.step = 0;
+        self.started = false;
+    }
+
+    fn in_arena(&self, (x, y): (usize, usize)) -> bool {
+        let ((left, top), (right, bottom)) = self.arena;
+        (left..=right).contains(&x) && (top..=bottom).contains(&y)
+    }
+}
+
+/// Mobs a boss called in, sent away with it.
+#[derive(Component)]
+struct Summoned;
+
+#[derive(Component)]
+struct BossBar;
+
+#[derive(Component)]
+struct BossBarFill;
+
+struct BossDatabaseHandle(Handle<BossDatabase>);
+
+fn load_bosses(
+    mut commands: Commands,
+    mut loading: ResMut<LoadingAssets>,
+    asset_server: Res<AssetServer>,
+) {
+    let handle = asset_server.load(BOSSES_PATH);
+    loading.add(BOSSES_PATH, &handle);
+    commands.insert_resource(BossDatabaseHandle(handle));
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_bosses(
+    mut commands: Commands,
+    map: Res<MapData>,
+    flags: Res<GameFlags>,
+    database: Res<Assets<BossDatabase>>,
+    database_handle: Res<BossDatabaseHandle>,
+    mobs: Res<Mobs>,
+    named_atlases: Res<Assets<NamedAtlas>>,
+    texture_atlases: Res<Assets<TextureAtlas>>,
+) {
+    let database = match database.get(&database_handle.0) {
+        Some(database) => database,
+        None => return,
+    };
+    for object in &map.objects {
+        let spawn = match object {
+            MapObject::Boss(spawn) => spawn,
+            _ => continue,
+        };
+        let def = match database.0.get(&spawn.boss) {
+            Some(def) => def,
+            None => {
+                warn!("Map places unknown boss `{}`", spawn.boss);
+                continue;
+            }
+        };
+        if flags.contains(&def.defeated_flag) {
+            continue;
+        }
+        let atlas_handle = match mobs.texture_atlas(&named_atlases, &def.sprite) {
+            Some(handle) => handle,
+            None => {
+                warn!("Boss `{}` uses unknown sprite `{}`", spawn.boss, def.sprite);
+                continue;
+            }
+        };
+        let size = texture_atlases
+            .get(&atlas_handle)
+            .map_or(Vec2::splat(map.tile_size), |atlas| character_size(atlas, 0, map.tile_size));
+        let home = map.tile_to_world(spawn.position.0, spawn.position.1);
+
+        commands
+            .spawn_bundle(SpriteSheetBundle {
+                sprite: TextureAtlasSprite {
+                    custom_size: Some(size),
+                    ..default()
+                },
+                texture_atlas: atlas_handle,
+                transform: Transform::from_translation(home.extend(1.)),
+                ..default()
+            })
+            .insert(Name::new(def.name.clone()))
+            .insert(Boss {
+                def: def.clone(),
+                phase: 0,
+                home,
+                arena: spawn.arena,
+                exits: spawn.exits.clone(),
+                closed: None,
+                step: 0,
+                timer: Timer::from_seconds(0., false),
+                started: false,
+                charge: Vec2::ZERO,
+            })
+            .insert(Motion::default())
+            .insert(AnimationTimer(Timer::from_seconds(0.1, true)))
+            .insert(Health::new(def.health))
+            .insert(Hurtbox {
+                size: size * 0.8,
+                team: Team::Enemy,
+            })
+            .insert(Hitbox {
+                size: size * 0.7,
+                team: Team::Enemy,
+                damage: def.damage,
+                knockback: 1.5,
+            });
+    }
+}
+
+/// Starts the fight when the player steps into the arena, closing the exits,
+/// and calls it off if the player ends up outside, e.g. after being knocked out.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn engage_bosses(
+    mut commands: Commands,
+    mut tile_edits: EventWriter<TileEdit>,
+    asset_server: Res<AssetServer>,
+    map: Res<MapData>,
+    mut boss_query: Query<(&mut Boss, &mut Health, &mut Transform), Without<Player>>,
+    player_query: Query<&Transform, With<Player>>,
+    cleanup_query: Query<Entity, Or<(With<Summoned>, With<BossBar>)>>,
+) {
+    let player_tile = match player_query.get_single() {
+        Ok(transform) => map.world_to_tile(transform.translation.truncate()),
+        Err(_) => return,
+    };
+    for (mut boss, mut health, mut transform) in &mut boss_query {
+        let inside = player_tile.is_some_and(|tile| boss.in_arena(tile));
+        if inside && !boss.is_engaged() {
+            let closed = boss
+                .exits
+                .iter()
+                .map(|&(x, y)| ((x, y), map.tile_at(MapLayer::Foreground, x, y).unwrap_or(' ')))
+                .collect();
+            boss.closed = Some(closed);
+            for &position in &boss.exits {
+                tile_edits.send(TileEdit {
+                    layer: MapLayer::Foreground,
+                    position,
+                    tile: 'f',
+                });
+            }
+            boss.start_phase(0);
+            spawn_boss_bar(&mut commands, &asset_server, &boss.def.name);
+        } else if !inside && boss.is_engaged() {
+            open_exits(&mut boss, &mut tile_edits);
+            *health = Health::new(boss.def.health);
+            transform.translation = boss.home.extend(transform.translation.z);
+            boss.start_phase(0);
+            for entity in &cleanup_query {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+}
+
+fn open_exits(boss: &mut Boss, tile_edits: &mut EventWriter<TileEdit>) {
+    for (position, tile) in boss.closed.take().unwrap_or_default() {
+        tile_edits.send(TileEdit {
+            layer: MapLayer::Foreground,
+            position,
+            tile,
+        });
+    }
+}
+
+/// Moves bosses on to the phase their health calls for.
+fn boss_phases(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut query: Query<(&mut Boss, &Health), Changed<Health>>,
+) {
+    for (mut boss, health) in &mut query {
+        let fraction = health.current as f32 / health.max.max(1) as f32;
+        let phase = boss
+            .def
+            .phases
+            .iter()
+            .rposition(|phase| fraction <= phase.below)
+            .unwrap_or(0);
+        if phase <= boss.phase {
+            continue;
+        }
+        boss.start_phase(phase);
+        if let Some(announce) = boss.def.phases[phase].announce.clone() {
+            spawn_toast(&mut commands, &asset_server, announce, 0);
+        }
+    }
+}
+
+/// Plays each engaged boss's phase script.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn run_boss_scripts(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    mut shots: EventWriter<Shoot>,
+    time: Res<Time>,
+    mob_database: Res<Assets<MobDatabase>>,
+    mob_database_handle: Res<MobDatabaseHandle>,
+    mobs: Res<Mobs>,
+    named_atlases: Res<Assets<NamedAtlas>>,
+    texture_atlases: Res<Assets<TextureAtlas>>,
+    mut boss_query: Query<(&mut Boss, &mut Motion, &mut Transform), (Without<Knockback>, Without<Player>)>,
+    player_query: Query<&Transform, With<Player>>,
+    wall_query: Query<&GlobalTransform, With<TileCollider>>,
+    summoned_query: Query<(), With<Summoned>>,
+) {
+    let player = match player_query.get_single() {
+        Ok(transform) => transform.translation.truncate(),
+        Err(_) => return,
+    };
+    for (mut boss, mut motion, mut transform) in &mut boss_query {
+        if !boss.is_engaged() {
+            motion.update(Vec3::ZERO);
+            continue;
+        }
+        let action = match boss.action() {
+            Some(action) => action.clone(),
+            None => continue,
+        };
+        let position = transform.translation.truncate();
+        let to_player = (player - position).normalize_or_zero();
+
+        if !boss.started {
+            boss.started = true;
+            boss.timer = Timer::from_seconds(action.duration(), false);
+            match &action {
+                BossAction::Charge { .. } => boss.charge = to_player,
+                BossAction::Volley(pattern) => shots.send(Shoot {
+                    position,
+                    aim: to_player,
+                    pattern: pattern.clone(),
+                    projectile: boss.def.projectile.clone(),
+                    team: Team::Enemy,
+                }),
+                BossAction::Summon { mob, count } => {
+                    let def = mob_database
+                        .get(&mob_database_handle.0)
+                        .and_then(|database| database.0.get(mob));
+                    let def = match def {
+                        Some(def) => def,
+                        None => {
+                            warn!("Boss `{}` summons unknown mob `{mob}`", boss.def.name);
+                            continue;
+                        }
+                    };
+                    let missing = (*count as usize).saturating_sub(summoned_query.iter().count());
+                    for direction in (Pattern::Ring { count: missing as u32 }).directions(Vec2::X) {
+                        let home = position + direction * config.tile_size;
+                        let summoned = spawn_mob(
+                            &mut commands,
+                            def,
+                            home,
+                            &config,
+                            &mobs,
+                            &named_atlases,
+                            &texture_atlases,
+                        );
+                        if let Some(summoned) = summoned {
+                            commands.entity(summoned).insert(Summoned);
+                        }
+                    }
+                }
+                BossAction::Wait(_) | BossAction::Chase { .. } => {}
+            }
+        }
+
+        let velocity = match action {
+            BossAction::Chase { .. } => to_player * boss.def.speed * config.tile_size,
+            BossAction::Charge { speed, .. } => boss.charge * speed * config.tile_size,
+            _ => Vec2::ZERO,
+        };
+        let mut movement = (velocity * time.delta_seconds()).extend(0.);
+        let walls = || wall_query.iter().map(GlobalTransform::translation);
+        if collides(transform.translation + Vec3::new(movement.x, 0., 0.), walls(), [], config.tile_size) {
+            movement.x = 0.;
+        }
+        if collides(transform.translation + Vec3::new(0., movement.y, 0.), walls(), [], config.tile_size) {
+            movement.y = 0.;
+        }
+        motion.update(movement);
+        transform.translation += movement;
+
+        // A charge into a wall ends early.
+        let stopped = velocity != Vec2::ZERO && movement == Vec3::ZERO;
+        if boss.timer.tick(time.delta()).finished() || stopped {
+            let steps = boss.def.phases[boss.phase].script.len();
+            boss.step = (boss.step + 1) % steps.max(1);
+            boss.started = false;
+        }
+    }
+}
+
+fn spawn_boss_bar(commands: &mut Commands, asset_server: &AssetServer, name: &str) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    top: Val::Px(16.),
+                    ..default()
+                },
+                size: Size::new(Val::Percent(100.), Val::Auto),
+                flex_direction: FlexDirection::ColumnReverse,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .insert(Name::new("Boss Bar"))
+        .insert(BossBar)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(
+                name,
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 22.,
+                    color: Color::WHITE,
+                },
+            ));
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(400.), Val::Px(14.)),
+                        margin: UiRect::all(Val::Px(4.)),
+                        ..default()
+                    },
+                    color: Color::rgb(0.25, 0.05, 0.05).into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                                ..default()
+                            },
+                            color: Color::rgb(0.85, 0.15, 0.1).into(),
+                            ..default()
+                        })
+                        .insert(BossBarFill);
+                });
+        });
+}
+
+fn update_boss_bar(
+    boss_query: Query<(&Boss, &Health)>,
+    mut fill_query: Query<&mut Style, With<BossBarFill>>,
+) {
+    let fraction = match boss_query.iter().find(|(boss, _)| boss.is_engaged()) {
+        Some((_, health)) => health.current as f32 / health.max.max(1) as f32,
+        None => return,
+    };
+    for mut style in &mut fill_query {
+        style.size.width = Val::Percent(fraction * 100.);
+    }
+}
+
+/// Opens the arena back up and remembers the boss is beaten.
+#[allow(clippy::type_complexity)]
+fn defeat_bosses(
+    mut commands: Commands,
+    mut died_events: EventReader<Died>,
+    mut tile_edits: EventWriter<TileEdit>,
+    mut flags: ResMut<GameFlags>,
+    asset_server: Res<AssetServer>,
+    mut boss_query: Query<&mut Boss>,
+    cleanup_query: Query<Entity, Or<(With<Summoned>, With<BossBar>)>>,
+) {
+    for died in died_events.iter() {
+        let mut boss = match boss_query.get_mut(died.entity) {
+            Ok(boss) => boss,
+            Err(_) => continue,
+        };
+        open_exits(&mut boss, &mut tile_edits);
+        flags.insert(boss.def.defeated_flag.clone());
+        spawn_toast(&mut commands, &asset_server, format!("{} defeated!", boss.def.name), 0);
+        commands.entity(died.entity).despawn_recursive();
+        for entity in &cleanup_query {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
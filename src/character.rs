@@ -0,0 +1,168 @@
+use bevy::{prelude::*, sprite::collide_aabb::collide};
+use bevy_inspector_egui::Inspectable;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::AppState;
+
+/// Animation and collision shared by the player and every NPC.
+pub struct CharacterPlugin;
+
+impl Plugin for CharacterPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Direction>()
+            .register_type::<MoveStatus>()
+            .register_type::<Motion>()
+            .register_type::<AnimationTimer>()
+            .register_type::<CharacterCollider>()
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame).with_system(character_animation),
+            );
+    }
+}
+
+/// How many pixels of a character sheet make up one tile.
+pub const CHARACTER_PIXELS: f32 = 24.;
+
+/// Side of the square characters collide with, in tiles.
+pub const CHARACTER_HITBOX: f32 = 0.75;
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Inspectable, PartialEq, Reflect, Serialize)]
+#[reflect_value(PartialEq, Serialize, Deserialize)]
+pub enum Direction {
+    Down,
+    Left,
+    Up,
+    #[default]
+    Right,
+}
+
+impl Direction {
+    pub fn vector(self) -> Vec2 {
+        match self {
+            Direction::Down => Vec2::NEG_Y,
+            Direction::Left => Vec2::NEG_X,
+            Direction::Up => Vec2::Y,
+            Direction::Right => Vec2::X,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Inspectable, PartialEq, Reflect, Serialize)]
+#[reflect_value(PartialEq, Serialize, Deserialize)]
+pub enum MoveStatus {
+    #[default]
+    Stopped,
+    Moving(Direction),
+}
+
+#[derive(Component, Default, Inspectable, Reflect)]
+#[reflect(Component)]
+pub struct Motion {
+    pub status: MoveStatus,
+    pub facing: Direction,
+}
+
+impl Motion {
+    /// Updates the status from this frame's movement, horizontal movement winning
+    /// over vertical.
+    pub fn update(&mut self, movement: Vec3) {
+        self.status = if movement.x > 0. {
+            MoveStatus::Moving(Direction::Right)
+        } else if movement.x < 0. {
+            MoveStatus::Moving(Direction::Left)
+        } else if movement.y > 0. {
+            MoveStatus::Moving(Direction::Up)
+        } else if movement.y < 0. {
+            MoveStatus::Moving(Direction::Down)
+        } else {
+            MoveStatus::Stopped
+        };
+        if let MoveStatus::Moving(direction) = self.status {
+            self.facing = direction;
+        }
+    }
+}
+
+#[derive(Component, Default, Deref, DerefMut, Reflect)]
+#[reflect(Component)]
+pub struct AnimationTimer(pub Timer);
+
+/// Marks characters that block each other's movement.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct CharacterCollider;
+
+/// Size of a character sprite showing `index` of `atlas`.
+pub fn character_size(atlas: &TextureAtlas, index: usize, tile_size: f32) -> Vec2 {
+    atlas
+        .textures
+        .get(index)
+        .map_or(Vec2::splat(tile_size), |rect| {
+            rect.size() / CHARACTER_PIXELS * tile_size
+        })
+}
+
+/// Whether a character at `target` would overlap any tile collider or any other
+/// character.
+pub fn collides(
+    target: Vec3,
+    tiles: impl IntoIterator<Item = Vec3>,
+    characters: impl IntoIterator<Item = Vec3>,
+    tile_size: f32,
+) -> bool {
+    let hitbox = Vec2::splat(CHARACTER_HITBOX * tile_size);
+    tiles
+        .into_iter()
+        .any(|tile| collide(target, hitbox, tile, Vec2::splat(tile_size)).is_some())
+        || characters
+            .into_iter()
+            .any(|character| collide(target, hitbox, character, hitbox).is_some())
+}
+
+/// Rejects a wander radius `wander_target` can't pick a spot in.
+pub fn check_wander_radius(radius: f32) -> Result<(), String> {
+    if !radius.is_finite() || radius < 0. {
+        return Err(format!("Wander `radius` can't be {radius}"));
+    }
+    Ok(())
+}
+
+/// A random spot at most `radius` tiles away from `home`.
+pub fn wander_target(home: Vec2, radius: f32, tile_size: f32) -> Vec2 {
+    let mut rng = rand::thread_rng();
+    let angle = rng.gen_range(0. ..std::f32::consts::TAU);
+    let distance = rng.gen_range(0. ..=radius) * tile_size;
+    home + Vec2::from_angle(angle) * distance
+}
+
+fn character_animation(
+    time: Res<Time>,
+    texture_atlases: Res<Assets<TextureAtlas>>,
+    mut query: Query<(
+        &Motion,
+        &mut AnimationTimer,
+        &mut TextureAtlasSprite,
+        &Handle<TextureAtlas>,
+    )>,
+) {
+    for (motion, mut timer, mut sprite, atlas_handle) in &mut query {
+        timer.tick(time.delta());
+        if timer.just_finished() {
+            let texture_atlas = match texture_atlases.get(atlas_handle) {
+                Some(texture_atlas) => texture_atlas,
+                None => continue,
+            };
+            sprite.index = if motion.status != MoveStatus::Stopped {
+                sprite.index + 1
+            } else {
+                0
+            } % texture_atlas.textures.len();
+            sprite.flip_x = match motion.facing {
+                Direction::Left => true,
+                Direction::Right => false,
+                _ => sprite.flip_x,
+            }
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashMap, HashSet},
+};
+use bevy_inspector_egui::Inspectable;
+use rand::{distributions::WeightedIndex, prelude::Distribution, Rng};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    interact::{Interactable, InteractionKind, OpenChest},
+    inventory::{spawn_toast, Inventory},
+    items::Items,
+    loading::LoadingAssets,
+    player::Player,
+    sprites::{NamedAtlas, Props},
+    tilemap::{prop_size, MapData, MapObject, TileCollider},
+    AppState, GameConfig,
+};
+
+pub struct ChestPlugin;
+
+impl Plugin for ChestPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Chest>()
+            .add_asset::<LootTables>()
+            .init_asset_loader::<LootTablesLoader>()
+            .init_resource::<OpenedChests>()
+            .add_system_set(SystemSet::on_enter(AppState::Loading).with_system(load_loot_tables))
+            .add_system_set(SystemSet::on_enter(AppState::InGame).with_system(spawn_chests))
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame).with_system(open_chests.after("interact")),
+            );
+    }
+}
+
+pub const LOOT_TABLES_PATH: &str = "game.loot.ron";
+
+const CLOSED_FRAME: &str = "treasure-closed";
+const OPEN_FRAME: &str = "treasure-open";
+
+/// A chest as placed in a map's objects file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ChestSpawn {
+    /// Tells the chest apart from the map's other chests, to remember it was opened.
+    pub id: String,
+    pub position: (usize, usize),
+    /// Name of the loot table in `game.loot.ron` the chest is filled from.
+    pub loot: String,
+}
+
+/// Every loot table, by name, as read from `game.loot.ron`.
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "c4a1e7b3-2f6d-4e59-8b0a-71d2f3e9a6c5"]
+pub struct LootTables(pub HashMap<String, LootTable>);
+
+#[derive(Debug, Deserialize)]
+pub struct LootTable {
+    /// How many times an entry is picked.
+    #[serde(default = "default_rolls")]
+    pub rolls: u32,
+    pub entries: Vec<LootEntry>,
+}
+
+fn default_rolls() -> u32 {
+    1
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LootEntry {
+    pub item: String,
+    /// Chance of the entry being picked, relative to the table's other entries.
+    pub weight: u32,
+    /// Inclusive range of how many of the item the entry gives.
+    #[serde(default = "default_count")]
+    pub count: (u32, u32),
+}
+
+fn default_count() -> (u32, u32) {
+    (1, 1)
+}
+
+impl LootTable {
+    /// Picks the table's entries, merging repeats.
+    pub fn roll(&self, rng: &mut impl Rng) -> HashMap<String, u32> {
+        let mut loot = HashMap::default();
+        let weights = match WeightedIndex::new(self.entries.iter().map(|entry| entry.weight)) {
+            Ok(weights) => weights,
+            Err(_) => return loot,
+        };
+        for _ in 0..self.rolls {
+            let entry = &self.entries[weights.sample(rng)];
+            let (min, max) = entry.count;
+            let count = rng.gen_range(min..=max.max(min));
+            *loot.entry(entry.item.clone()).or_insert(0) += count;
+        }
+        loot
+    }
+}
+
+#[derive(Default)]
+pub struct LootTablesLoader;
+
+impl AssetLoader for LootTablesLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let tables: LootTables = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(tables));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["loot.ron"]
+    }
+}
+
+/// Keys of every chest the player has opened, `<map>/<chest id>`. Chests
+/// spawned with one of these keys start open.
+#[derive(Debug, Default, Deref, DerefMut, Serialize, Deserialize)]
+pub struct OpenedChests(pub HashSet<String>);
+
+#[derive(Component, Debug, Default, Inspectable, Reflect)]
+#[reflect(Component)]
+pub struct Chest {
+    pub key: String,
+    pub loot: String,
+}
+
+struct LootTablesHandle(Handle<LootTables>);
+
+fn load_loot_tables(
+    mut commands: Commands,
+    mut loading: ResMut<LoadingAssets>,
+    asset_server: Res<AssetServer>,
+) {
+    let handle = asset_server.load(LOOT_TABLES_PATH);
+    loading.add(LOOT_TABLES_PATH, &handle);
+    commands.insert_resource(LootTablesHandle(handle));
+}
+
+fn spawn_chests(
+    mut commands: Commands,
+    map: Res<MapData>,
+    opened: Res<OpenedChests>,
+    props: Res<Props>,
+    named_atlases: Res<Assets<NamedAtlas>>,
+    texture_atlases: Res<Assets<TextureAtlas>>,
+) {
+    for object in &map.objects {
+        let spawn = match object {
+            MapObject::Chest(spawn) => spawn,
+            _ => continue,
+        };
+        let key = format!("{}/{}", map.name, spawn.id);
+        let is_open = opened.contains(&key);
+        let frame = if is_open { OPEN_FRAME } else { CLOSED_FRAME };
+        let (texture_atlas, index) = match props.frame(&named_atlases, frame) {
+            Some(frame) => frame,
+            None => {
+                warn!("No `{frame}` frame in the prop atlases");
+                continue;
+            }
+        };
+        let size = texture_atlases
+            .get(&texture_atlas)
+            .map_or(Vec2::splat(map.tile_size), |atlas| prop_size(atlas, index, map.tile_size));
+        // Sit the chest on the bottom of its tile, whichever frame it shows.
+        let position = map.tile_to_world(spawn.position.0, spawn.position.1)
+            + Vec2::new(0., (size.y - map.tile_size) / 2.);
+
+        let mut chest = commands.spawn_bundle(SpriteSheetBundle {
+            sprite: TextureAtlasSprite {
+                index,
+                custom_size: Some(size),
+                ..default()
+            },
+            texture_atlas,
+            transform: Transform::from_translation(position.extend(1.)),
+            ..default()
+        });
+        chest
+            .insert(Name::new("Chest"))
+            .insert(Chest {
+                key,
+                loot: spawn.loot.clone(),
+            })
+            .insert(TileCollider);
+        if !is_open {
+            chest.insert(Interactable::new(InteractionKind::OpenChest));
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn open_chests(
+    mut commands: Commands,
+    mut events: EventReader<OpenChest>,
+    asset_server: Res<AssetServer>,
+    config: Res<GameConfig>,
+    loot_tables: Res<Assets<LootTables>>,
+    loot_handle: Res<LootTablesHandle>,
+    items: Res<Items>,
+    texture_atlases: Res<Assets<TextureAtlas>>,
+    named_atlases: Res<Assets<NamedAtlas>>,
+    props: Res<Props>,
+    mut opened: ResMut<OpenedChests>,
+    mut chest_query: Query<(
+        &Chest,
+        &Handle<TextureAtlas>,
+        &mut TextureAtlasSprite,
+        &mut Transform,
+    )>,
+    mut player_query: Query<&mut Inventory, With<Player>>,
+) {
+    let mut inventory = player_query.single_mut();
+    for OpenChest(entity) in events.iter() {
+        let (chest, atlas_handle, mut sprite, mut transform) = match chest_query.get_mut(*entity) {
+            Ok(chest) => chest,
+            Err(_) => continue,
+        };
+        if opened.contains(&chest.key) {
+            continue;
+        }
+        let table = match loot_tables
+            .get(&loot_handle.0)
+            .and_then(|tables| tables.0.get(&chest.loot))
+        {
+            Some(table) => table,
+            None => {
+                warn!("Chest `{}` uses unknown loot table `{}`", chest.key, chest.loot);
+                continue;
+            }
+        };
+
+        // Everything goes in or the chest stays shut, so nothing is lost.
+        let loot = table.roll(&mut rand::thread_rng());
+        let stacks = loot.iter().map(|(item, count)| (item.as_str(), *count));
+        if let Err(err) = inventory.add_all(stacks, &items) {
+            spawn_toast(&mut commands, &asset_server, err.to_string(), 0);
+            continue;
+        }
+
+        opened.insert(chest.key.clone());
+        commands.entity(*entity).remove::<Interactable>();
+        if let Some((_, index)) = props.frame(&named_atlases, OPEN_FRAME) {
+            let old_height = sprite.custom_size.map_or(config.tile_size, |size| size.y);
+            let size = texture_atlases
+                .get(atlas_handle)
+                .map_or(Vec2::splat(config.tile_size), |atlas| prop_size(atlas, index, config.tile_size));
+            sprite.index = index;
+            sprite.custom_size = Some(size);
+            transform.translation.y += (size.y - old_height) / 2.;
+        }
+    }
+}
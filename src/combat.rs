@@ -0,0 +1,333 @@
+use bevy::{prelude::*, sprite::collide_aabb::collide};
+use bevy_inspector_egui::{egui, Context, Inspectable};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    character::{collides, CharacterCollider, Motion},
+    inspector::Fields,
+    player::Player,
+    tilemap::TileCollider,
+    AppState, GameConfig,
+};
+
+pub struct CombatPlugin;
+
+impl Plugin for CombatPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Team>()
+            .register_type::<Health>()
+            .register_type::<Hitbox>()
+            .register_type::<Hurtbox>()
+            .add_event::<Damage>()
+            .add_event::<Died>()
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(player_attack.label("player_attack").after("player_movement"))
+                    .with_system(
+                        detect_hits
+                            .label("detect_hits")
+                            .after("player_attack")
+                            .after("mob_movement")
+                            .after("boss_scripts"),
+                    )
+                    .with_system(apply_damage.label("apply_damage").after("detect_hits"))
+                    .with_system(apply_knockback.after("apply_damage"))
+                    .with_system(flash_invulnerable.after("apply_damage"))
+                    .with_system(expire_hitboxes),
+            );
+    }
+}
+
+/// The attack action.
+pub const ATTACK_KEY: KeyCode = KeyCode::Space;
+
+/// Seconds between two player attacks.
+const ATTACK_COOLDOWN: f32 = 0.4;
+/// How long an attack's hitbox stays out, in seconds.
+const ATTACK_DURATION: f32 = 0.15;
+const ATTACK_DAMAGE: u32 = 1;
+/// How far the player's attacks push, in tiles.
+const ATTACK_KNOCKBACK: f32 = 1.;
+/// How long a knockback pushes for, in seconds.
+const KNOCKBACK_DURATION: f32 = 0.15;
+/// Seconds an entity can't be hit again after a hit, by team.
+const PLAYER_INVULNERABILITY: f32 = 1.;
+const ENEMY_INVULNERABILITY: f32 = 0.3;
+/// Seconds between two blinks of an invulnerable sprite.
+const FLASH_INTERVAL: f32 = 0.08;
+
+/// Which side something fights on; hitboxes only hurt the other side.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Inspectable, Reflect, Serialize, Deserialize)]
+#[reflect_value(PartialEq, Serialize, Deserialize)]
+pub enum Team {
+    Player,
+    #[default]
+    Enemy,
+}
+
+#[derive(Component, Debug, Clone, Copy, Default, Inspectable, Reflect)]
+#[reflect(Component)]
+pub struct Health {
+    pub current: u32,
+    pub max: u32,
+}
+
+impl Health {
+    pub fn new(max: u32) -> Health {
+        Health { current: max, max }
+    }
+
+    pub fn heal(&mut self, amount: u32) {
+        self.current = (self.current + amount).min(self.max);
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.current == 0
+    }
+}
+
+/// An area that hurts the other team's hurtboxes it overlaps, centred on the
+/// entity.
+#[derive(Component, Debug, Clone, Default, Inspectable, Reflect)]
+#[reflect(Component)]
+pub struct Hitbox {
+    pub size: Vec2,
+    pub team: Team,
+    pub damage: u32,
+    /// How far a hit pushes, in tiles.
+    pub knockback: f32,
+}
+
+/// The area an entity can be hit in, centred on it.
+#[derive(Component, Debug, Clone, Default, Inspectable, Reflect)]
+#[reflect(Component)]
+pub struct Hurtbox {
+    pub size: Vec2,
+    pub team: Team,
+}
+
+/// An attack's hitbox, gone when the timer runs out.
+#[derive(Component)]
+struct Attack(Timer);
+
+/// Pushes an entity along, through the same collision checks as walking.
+#[derive(Component)]
+pub struct Knockback {
+    pub velocity: Vec2,
+    pub timer: Timer,
+}
+
+impl Inspectable for Knockback {
+    type Attributes = ();
+
+    fn ui(&mut self, ui: &mut egui::Ui, _: Self::Attributes, context: &mut Context) -> bool {
+        Fields::show(ui, context, |fields| {
+            fields.edit("velocity", &mut self.velocity).timer("timer", &mut self.timer);
+        })
+    }
+}
+
+/// Can't be hurt until the timer runs out. The sprite blinks meanwhile.
+#[derive(Component)]
+pub struct Invulnerable {
+    pub timer: Timer,
+    flash: Timer,
+}
+
+impl Inspectable for Invulnerable {
+    type Attributes = ();
+
+    fn ui(&mut self, ui: &mut egui::Ui, _: Self::Attributes, context: &mut Context) -> bool {
+        Fields::show(ui, context, |fields| {
+            fields.timer("timer", &mut self.timer).timer("flash", &mut self.flash);
+        })
+    }
+}
+
+#[derive(Component, Default)]
+pub struct AttackCooldown(Timer);
+
+impl Inspectable for AttackCooldown {
+    type Attributes = ();
+
+    fn ui(&mut self, ui: &mut egui::Ui, _: Self::Attributes, context: &mut Context) -> bool {
+        Fields::show(ui, context, |fields| {
+            fields.timer("cooldown", &mut self.0);
+        })
+    }
+}
+
+/// A hitbox hit `target`.
+pub struct Damage {
+    pub target: Entity,
+    pub amount: u32,
+    /// Push in tiles, the direction being the way it goes.
+    pub knockback: Vec2,
+}
+
+/// `entity` ran out of health. Whoever owns it decides what happens next.
+pub struct Died {
+    pub entity: Entity,
+}
+
+/// Size of the player's attack, reaching out in front of them.
+fn attack_size(facing: Vec2, tile_size: f32) -> Vec2 {
+    if facing.x != 0. {
+        Vec2::new(0.75, 1.) * tile_size
+    } else {
+        Vec2::new(1., 0.75) * tile_size
+    }
+}
+
+fn player_attack(
+    mut commands: Commands,
+    time: Res<Time>,
+    config: Res<GameConfig>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut player_query: Query<(&Transform, &Motion, &mut AttackCooldown), With<Player>>,
+) {
+    let (transform, motion, mut cooldown) = match player_query.get_single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    cooldown.0.tick(time.delta());
+    if !keyboard_input.just_pressed(ATTACK_KEY) || !cooldown.0.finished() {
+        return;
+    }
+    keyboard_input.reset(ATTACK_KEY);
+    cooldown.0 = Timer::from_seconds(ATTACK_COOLDOWN, false);
+
+    let facing = motion.facing.vector();
+    let position = transform.translation + (facing * config.tile_size * 0.75).extend(0.);
+    commands
+        .spawn_bundle(TransformBundle::from_transform(Transform::from_translation(position)))
+        .insert(Name::new("Player Attack"))
+        .insert(Hitbox {
+            size: attack_size(facing, config.tile_size),
+            team: Team::Player,
+            damage: ATTACK_DAMAGE,
+            knockback: ATTACK_KNOCKBACK,
+        })
+        .insert(Attack(Timer::from_seconds(ATTACK_DURATION, false)));
+}
+
+#[allow(clippy::type_complexity)]
+fn detect_hits(
+    mut damage_events: EventWriter<Damage>,
+    hitbox_query: Query<(&GlobalTransform, &Hitbox)>,
+    hurtbox_query: Query<(Entity, &GlobalTransform, &Hurtbox), (With<Health>, Without<Invulnerable>)>,
+) {
+    for (target, target_transform, hurtbox) in &hurtbox_query {
+        let target_position = target_transform.translation();
+        let hit = hitbox_query.iter().find(|(transform, hitbox)| {
+            hitbox.team != hurtbox.team
+                && collide(transform.translation(), hitbox.size, target_position, hurtbox.size).is_some()
+        });
+        if let Some((transform, hitbox)) = hit {
+            let direction = (target_position - transform.translation()).truncate().normalize_or_zero();
+            damage_events.send(Damage {
+                target,
+                amount: hitbox.damage,
+                knockback: direction * hitbox.knockback,
+            });
+        }
+    }
+}
+
+fn apply_damage(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    mut damage_events: EventReader<Damage>,
+    mut died_events: EventWriter<Died>,
+    mut query: Query<(&mut Health, &Hurtbox), Without<Invulnerable>>,
+) {
+    for damage in damage_events.iter() {
+        let (mut health, hurtbox) = match query.get_mut(damage.target) {
+            Ok(target) => target,
+            Err(_) => continue,
+        };
+        // Already dying from an earlier hit this frame.
+        if health.is_dead() {
+            continue;
+        }
+        health.current = health.current.saturating_sub(damage.amount);
+        if health.is_dead() {
+            died_events.send(Died {
+                entity: damage.target,
+            });
+            continue;
+        }
+
+        let invulnerability = match hurtbox.team {
+            Team::Player => PLAYER_INVULNERABILITY,
+            Team::Enemy => ENEMY_INVULNERABILITY,
+        };
+        let mut target = commands.entity(damage.target);
+        target.insert(Invulnerable {
+            timer: Timer::from_seconds(invulnerability, false),
+            flash: Timer::from_seconds(FLASH_INTERVAL, true),
+        });
+        if damage.knockback != Vec2::ZERO {
+            target.insert(Knockback {
+                velocity: damage.knockback * config.tile_size / KNOCKBACK_DURATION,
+                timer: Timer::from_seconds(KNOCKBACK_DURATION, false),
+            });
+        }
+    }
+}
+
+fn apply_knockback(
+    mut commands: Commands,
+    time: Res<Time>,
+    config: Res<GameConfig>,
+    mut query: Query<(Entity, &mut Knockback, &mut Transform)>,
+    wall_query: Query<&GlobalTransform, With<TileCollider>>,
+    character_query: Query<(Entity, &GlobalTransform), With<CharacterCollider>>,
+) {
+    for (entity, mut knockback, mut transform) in &mut query {
+        if knockback.timer.tick(time.delta()).finished() {
+            commands.entity(entity).remove::<Knockback>();
+            continue;
+        }
+        let mut movement = (knockback.velocity * time.delta_seconds()).extend(0.);
+        let others = || {
+            character_query
+                .iter()
+                .filter(|(other, _)| *other != entity)
+                .map(|(_, transform)| transform.translation())
+        };
+        let walls = || wall_query.iter().map(GlobalTransform::translation);
+        if collides(transform.translation + Vec3::new(movement.x, 0., 0.), walls(), others(), config.tile_size) {
+            movement.x = 0.;
+        }
+        if collides(transform.translation + Vec3::new(0., movement.y, 0.), walls(), others(), config.tile_size) {
+            movement.y = 0.;
+        }
+        transform.translation += movement;
+    }
+}
+
+fn flash_invulnerable(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut Invulnerable, &mut Visibility)>,
+) {
+    for (entity, mut invulnerable, mut visibility) in &mut query {
+        if invulnerable.timer.tick(time.delta()).finished() {
+            visibility.is_visible = true;
+            commands.entity(entity).remove::<Invulnerable>();
+            continue;
+        }
+        if invulnerable.flash.tick(time.delta()).just_finished() {
+            visibility.is_visible = !visibility.is_visible;
+        }
+    }
+}
+
+fn expire_hitboxes(mut commands: Commands, time: Res<Time>, mut query: Query<(Entity, &mut Attack)>) {
+    for (entity, mut attack) in &mut query {
+        if attack.0.tick(time.delta()).finished() {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+}
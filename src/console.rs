@@ -0,0 +1,423 @@
+//! A drop-down developer console, opened with the backtick key. Plugins add
+//! commands with `App::register_command`; typed arguments are checked before a
+//! command runs, Tab completes command names and argument values, and the
+//! history is kept between sessions.
+
+use std::{collections::BTreeMap, fs};
+
+use bevy::{input::InputSystem, prelude::*};
+use bevy_inspector_egui::{
+    bevy_egui::{EguiContext, EguiPlugin},
+    egui::{
+        self,
+        text::{CCursor, CCursorRange},
+        text_edit::TextEditState,
+    },
+};
+
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        if !app.world.contains_resource::<EguiContext>() {
+            app.add_plugin(EguiPlugin);
+        }
+        app.init_resource::<ConsoleCommands>()
+            .insert_resource(Console {
+                history: read_history(),
+                ..default()
+            })
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                toggle_console.label("toggle_console").after(InputSystem),
+            )
+            .add_system_to_stage(
+                CoreStage::PreUpdate,
+                block_game_input.after("toggle_console"),
+            )
+            .add_system(console_ui.exclusive_system());
+    }
+}
+
+/// Opens and closes the console.
+pub const CONSOLE_KEY: KeyCode = KeyCode::Grave;
+
+/// Commands typed into the console, oldest first, one per line.
+const HISTORY_PATH: &str = "console_history.txt";
+
+/// How many commands the history file keeps.
+const HISTORY_LENGTH: usize = 200;
+
+/// How many lines of output are kept on screen.
+const OUTPUT_LENGTH: usize = 500;
+
+/// What a command argument has to parse as.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArgKind {
+    Number,
+    Integer,
+    Word,
+}
+
+/// A parsed command argument.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ArgValue {
+    Number(f32),
+    Integer(i64),
+    Word(String),
+}
+
+pub struct Arg {
+    pub name: &'static str,
+    pub kind: ArgKind,
+    /// Optional arguments can only follow required ones.
+    pub optional: bool,
+    /// Values Tab cycles through, e.g. every mob id.
+    pub completions: Option<fn(&World) -> Vec<String>>,
+}
+
+/// The arguments a command was run with, already checked against its `Arg`s.
+/// Optional ones that were left out are `None`.
+pub struct Args(Vec<ArgValue>);
+
+impl Args {
+    pub fn number(&self, index: usize) -> Option<f32> {
+        match self.0.get(index)? {
+            ArgValue::Number(value) => Some(*value),
+            ArgValue::Integer(value) => Some(*value as f32),
+            ArgValue::Word(_) => None,
+        }
+    }
+
+    pub fn integer(&self, index: usize) -> Option<i64> {
+        match self.0.get(index)? {
+            ArgValue::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn word(&self, index: usize) -> Option<&str> {
+        match self.0.get(index)? {
+            ArgValue::Word(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// Runs a command, returning what to print or why it failed.
+pub type CommandFn = fn(&mut World, &Args) -> Result<String, String>;
+
+pub struct ConsoleCommand {
+    pub name: &'static str,
+    pub help: &'static str,
+    pub args: Vec<Arg>,
+    pub run: CommandFn,
+}
+
+impl ConsoleCommand {
+    pub fn new(name: &'static str, help: &'static str, run: CommandFn) -> ConsoleCommand {
+        ConsoleCommand {
+            name,
+            help,
+            args: Vec::new(),
+            run,
+        }
+    }
+
+    pub fn arg(mut self, name: &'static str, kind: ArgKind) -> ConsoleCommand {
+        self.args.push(Arg {
+            name,
+            kind,
+            optional: false,
+            completions: None,
+        });
+        self
+    }
+
+    pub fn optional_arg(mut self, name: &'static str, kind: ArgKind) -> ConsoleCommand {
+        self.args.push(Arg {
+            name,
+            kind,
+            optional: true,
+            completions: None,
+        });
+        self
+    }
+
+    /// Completes the last argument added from `completions`.
+    pub fn completions(mut self, completions: fn(&World) -> Vec<String>) -> ConsoleCommand {
+        if let Some(arg) = self.args.last_mut() {
+            arg.completions = Some(completions);
+        }
+        self
+    }
+
+    /// `name <required> [optional]`, as shown by `help`.
+    pub fn usage(&self) -> String {
+        let mut usage = self.name.to_string();
+        for arg in &self.args {
+            if arg.optional {
+                usage += &format!(" [{}]", arg.name);
+            } else {
+                usage += &format!(" <{}>", arg.name);
+            }
+        }
+        usage
+    }
+
+    fn parse(&self, words: &[&str]) -> Result<Args, String> {
+        if words.len() > self.args.len() {
+            return Err(format!("Too many arguments, usage: {}", self.usage()));
+        }
+        let mut values = Vec::new();
+        for (index, arg) in self.args.iter().enumerate() {
+            let word = match words.get(index) {
+                Some(word) => *word,
+                None if arg.optional => break,
+                None => return Err(format!("Missing <{}>, usage: {}", arg.name, self.usage())),
+            };
+            let value = match arg.kind {
+                ArgKind::Number => word.parse().map(ArgValue::Number).ok(),
+                ArgKind::Integer => word.parse().map(ArgValue::Integer).ok(),
+                ArgKind::Word => Some(ArgValue::Word(word.to_string())),
+            };
+            match value {
+                Some(value) => values.push(value),
+                None => return Err(format!("<{}> should be a {:?}, not `{word}`", arg.name, arg.kind)),
+            }
+        }
+        Ok(Args(values))
+    }
+}
+
+/// Every command the console knows, by name.
+#[derive(Default)]
+pub struct ConsoleCommands(BTreeMap<&'static str, ConsoleCommand>);
+
+impl ConsoleCommands {
+    /// Runs a line typed into the console, returning its output.
+    pub fn run(&self, world: &mut World, line: &str) -> Result<String, String> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let (name, words) = match words.split_first() {
+            Some(split) => split,
+            None => return Ok(String::new()),
+        };
+        if *name == "help" {
+            return Ok(self.help());
+        }
+        let command = self
+            .0
+            .get(name)
+            .ok_or_else(|| format!("Unknown command `{name}`, try `help`"))?;
+        let args = command.parse(words)?;
+        (command.run)(world, &args)
+    }
+
+    fn help(&self) -> String {
+        let mut lines = vec!["help - lists every command".to_string()];
+        for command in self.0.values() {
+            lines.push(format!("{} - {}", command.usage(), command.help));
+        }
+        lines.join("\n")
+    }
+
+    /// Everything the last word of `line` could be completed to.
+    pub fn completions(&self, world: &World, line: &str) -> Vec<String> {
+        let mut words: Vec<&str> = line.split_whitespace().collect();
+        // A trailing space starts a new, empty word.
+        if line.is_empty() || line.ends_with(' ') {
+            words.push("");
+        }
+        let (last, previous) = match words.split_last() {
+            Some(split) => split,
+            None => return Vec::new(),
+        };
+        let candidates = match previous.split_first() {
+            None => std::iter::once("help")
+                .chain(self.0.keys().copied())
+                .map(str::to_string)
+                .collect(),
+            Some((name, args)) => self
+                .0
+                .get(name)
+                .and_then(|command| command.args.get(args.len()))
+                .and_then(|arg| arg.completions)
+                .map_or_else(Vec::new, |completions| completions(world)),
+        };
+        let mut candidates: Vec<String> = candidates
+            .into_iter()
+            .filter(|candidate| candidate.starts_with(last))
+            .collect();
+        candidates.sort();
+        candidates
+    }
+}
+
+pub trait RegisterCommand {
+    fn register_command(&mut self, command: ConsoleCommand) -> &mut Self;
+}
+
+impl RegisterCommand for App {
+    fn register_command(&mut self, command: ConsoleCommand) -> &mut Self {
+        self.world
+            .get_resource_or_insert_with(ConsoleCommands::default)
+            .0
+            .insert(command.name, command);
+        self
+    }
+}
+
+#[derive(Default)]
+pub struct Console {
+    pub open: bool,
+    input: String,
+    output: Vec<String>,
+    history: Vec<String>,
+    /// How far back in the history Up has gone.
+    browsing: Option<usize>,
+}
+
+impl Console {
+    pub fn print(&mut self, text: &str) {
+        self.output.extend(text.lines().map(str::to_string));
+        let excess = self.output.len().saturating_sub(OUTPUT_LENGTH);
+        self.output.drain(..excess);
+    }
+
+    fn remember(&mut self, line: &str) {
+        self.browsing = None;
+        if self.history.last().map(String::as_str) == Some(line) {
+            return;
+        }
+        self.history.push(line.to_string());
+        let excess = self.history.len().saturating_sub(HISTORY_LENGTH);
+        self.history.drain(..excess);
+        if let Err(err) = fs::write(HISTORY_PATH, self.history.join("\n") + "\n") {
+            warn!("Could not write to `{HISTORY_PATH}`: {err}");
+        }
+    }
+
+    /// Steps through the history, `back` towards older commands.
+    fn browse(&mut self, back: bool) {
+        let len = self.history.len();
+        self.browsing = match (self.browsing, back) {
+            (None, true) if len > 0 => Some(len - 1),
+            (Some(index), true) => Some(index.saturating_sub(1)),
+            (Some(index), false) if index + 1 < len => Some(index + 1),
+            _ => None,
+        };
+        self.input = self
+            .browsing
+            .map_or_else(String::new, |index| self.history[index].clone());
+    }
+}
+
+fn read_history() -> Vec<String> {
+    let text = fs::read_to_string(HISTORY_PATH).unwrap_or_default();
+    text.lines().map(str::to_string).collect()
+}
+
+fn toggle_console(mut keyboard_input: ResMut<Input<KeyCode>>, mut console: ResMut<Console>) {
+    if keyboard_input.just_pressed(CONSOLE_KEY) {
+        console.open = !console.open;
+    } else if console.open && keyboard_input.just_pressed(KeyCode::Escape) {
+        console.open = false;
+        // Or the same press would go on to pause the game.
+        keyboard_input.reset(KeyCode::Escape);
+    }
+}
+
+/// Keeps keys typed into the console from also moving the player.
+fn block_game_input(console: Res<Console>, mut keyboard_input: ResMut<Input<KeyCode>>) {
+    if console.open {
+        keyboard_input.reset_all();
+    }
+}
+
+fn console_ui(world: &mut World) {
+    if !world.resource::<Console>().open {
+        return;
+    }
+    let ctx = world.resource_mut::<EguiContext>().ctx_mut().clone();
+    world.resource_scope(|world, mut console: Mut<Console>| {
+        world.resource_scope(|world, commands: Mut<ConsoleCommands>| {
+            let mut submitted = None;
+            egui::TopBottomPanel::top("console").show(&ctx, |ui| {
+                egui::ScrollArea::vertical()
+                    .max_height(240.)
+                    .stick_to_bottom(true)
+                    .show(ui, |ui| {
+                        for line in &console.output {
+                            ui.monospace(line);
+                        }
+                    });
+
+                let input_id = egui::Id::new("console_input");
+                let response = ui.add(
+                    egui::TextEdit::singleline(&mut console.input)
+                        .id(input_id)
+                        .font(egui::TextStyle::Monospace)
+                        .desired_width(f32::INFINITY)
+                        .lock_focus(true),
+                );
+                response.request_focus();
+                // The key that opened the console comes through as text.
+                console.input.retain(|c| c != '`');
+
+                let completions = commands.completions(world, &console.input);
+                let (enter, tab, up, down) = {
+                    let input = ui.input();
+                    (
+                        input.key_pressed(egui::Key::Enter),
+                        input.key_pressed(egui::Key::Tab),
+                        input.key_pressed(egui::Key::ArrowUp),
+                        input.key_pressed(egui::Key::ArrowDown),
+                    )
+                };
+                if enter {
+                    submitted = Some(std::mem::take(&mut console.input));
+                } else if up || down {
+                    console.browse(up);
+                    move_cursor_to_end(&ctx, input_id, &console.input);
+                } else if tab && !completions.is_empty() {
+                    let start = console.input.rfind(' ').map_or(0, |space| space + 1);
+                    console.input.truncate(start);
+                    console.input += &common_prefix(&completions);
+                    if completions.len() == 1 {
+                        console.input.push(' ');
+                    }
+                    move_cursor_to_end(&ctx, input_id, &console.input);
+                }
+                if completions.len() > 1 {
+                    ui.weak(completions.join("  "));
+                }
+            });
+
+            if let Some(line) = submitted.filter(|line| !line.trim().is_empty()) {
+                console.print(&format!("> {line}"));
+                console.remember(&line);
+                match commands.run(world, &line) {
+                    Ok(output) => console.print(&output),
+                    Err(err) => console.print(&format!("error: {err}")),
+                }
+            }
+        });
+    });
+}
+
+fn move_cursor_to_end(ctx: &egui::Context, id: egui::Id, text: &str) {
+    if let Some(mut state) = TextEditState::load(ctx, id) {
+        let end = CCursor::new(text.chars().count());
+        state.set_ccursor_range(Some(CCursorRange::one(end)));
+        state.store(ctx, id);
+    }
+}
+
+fn common_prefix(words: &[String]) -> String {
+    let mut prefix: Vec<char> = words[0].chars().collect();
+    for word in &words[1..] {
+        let common = prefix.iter().zip(word.chars()).take_while(|(a, b)| **a == *b).count();
+        prefix.truncate(common);
+    }
+    prefix.into_iter().collect()
+}
@@ -1,8 +1,29 @@
-use bevy::prelude::*;
-use bevy_inspector_egui::{RegisterInspectable, WorldInspectorPlugin};
+use bevy::{ecs::system::CommandQueue, prelude::*};
+use bevy_inspector_egui::{
+    plugin::InspectorWindows, InspectableRegistry, InspectorPlugin, RegisterInspectable, WorldInspectorPlugin,
+};
 
-use crate::player::Player;
-use crate::CameraProperties;
+use crate::boss::Boss;
+use crate::character::{AnimationTimer, Motion};
+use crate::chest::Chest;
+use crate::combat::{AttackCooldown, Health, Hitbox, Hurtbox, Invulnerable, Knockback};
+use crate::console::{ArgKind, Args, ConsoleCommand, ConsolePlugin, RegisterCommand};
+use crate::dialogue::DialogueSource;
+use crate::inspector::{atlas_sprite_ui, timer_ui, transform_ui};
+use crate::interact::{Interactable, OpenChest, PickUp, ReadSign, TalkTo};
+use crate::inventory::Inventory;
+use crate::items::{ItemPickup, Items};
+use crate::mob::{spawn_mob, Mob, MobDatabase, MobDatabaseHandle};
+use crate::npc::Npc;
+use crate::pathfinding::Navigator;
+use crate::player::{Noclip, Player};
+use crate::projectile::{Pattern, Projectile};
+use crate::scenes::{ExportScene, SpawnScene};
+use crate::settings::SaveSettings;
+use crate::shop::{Vendor, Wallet};
+use crate::sprites::{Characters, Mobs, NamedAtlas, Props, Tiles};
+use crate::tilemap::{LoadMap, MapData, MapRoot, Tile};
+use crate::{AppState, CameraProperties, CameraTimer, GameConfig};
 
 pub struct DebugPlugin;
 
@@ -11,7 +32,283 @@ impl Plugin for DebugPlugin {
         if cfg!(debug_assertions) {
             app.add_plugin(WorldInspectorPlugin::new())
                 .register_inspectable::<Player>()
-                .register_inspectable::<CameraProperties>();
+                .add_plugin(InspectorPlugin::<GameConfig>::new_insert_manually())
+                .register_inspectable::<CameraProperties>()
+                .register_inspectable::<Motion>()
+                .register_inspectable::<Health>()
+                .register_inspectable::<Hitbox>()
+                .register_inspectable::<Hurtbox>()
+                .register_inspectable::<Knockback>()
+                .register_inspectable::<Invulnerable>()
+                .register_inspectable::<AttackCooldown>()
+                .register_inspectable::<Projectile>()
+                .register_inspectable::<Mob>()
+                .register_inspectable::<Npc>()
+                .register_inspectable::<Boss>()
+                .register_inspectable::<Navigator>()
+                .register_inspectable::<Interactable>()
+                .register_inspectable::<DialogueSource>()
+                .register_inspectable::<Inventory>()
+                .register_inspectable::<Wallet>()
+                .register_inspectable::<Vendor>()
+                .register_inspectable::<Chest>()
+                .register_inspectable::<ItemPickup>()
+                .register_inspectable::<Tile>()
+                .add_plugin(InspectorPlugin::<Characters>::new_insert_manually())
+                .add_plugin(InspectorPlugin::<Mobs>::new_insert_manually())
+                .add_plugin(InspectorPlugin::<Tiles>::new_insert_manually())
+                .add_plugin(InspectorPlugin::<Props>::new_insert_manually())
+                .add_system(log_interactions)
+                .add_system(scene_keys)
+                .add_system(settings_keys)
+                .add_plugin(ConsolePlugin)
+                .register_command(
+                    ConsoleCommand::new("teleport", "moves the player to a tile", teleport)
+                        .arg("x", ArgKind::Integer)
+                        .arg("y", ArgKind::Integer),
+                )
+                .register_command(
+                    ConsoleCommand::new("speed", "sets how fast the player walks and runs", set_speed)
+                        .arg("walk", ArgKind::Number)
+                        .optional_arg("run", ArgKind::Number),
+                )
+                .register_command(
+                    ConsoleCommand::new("spawn", "spawns mobs around the player", spawn)
+                        .arg("mob", ArgKind::Word)
+                        .completions(mob_ids)
+                        .optional_arg("count", ArgKind::Integer),
+                )
+                .register_command(
+                    ConsoleCommand::new("map", "starts over on another map", load_map)
+                        .arg("name", ArgKind::Word)
+                        .completions(|_| MapData::names()),
+                )
+                .register_command(ConsoleCommand::new(
+                    "noclip",
+                    "lets the player walk through walls",
+                    toggle_noclip,
+                ))
+                .register_command(
+                    ConsoleCommand::new("give", "puts items in the player's inventory", give)
+                        .arg("item", ArgKind::Word)
+                        .completions(item_ids)
+                        .optional_arg("count", ArgKind::Integer),
+                )
+                .register_command(
+                    ConsoleCommand::new("sprites", "shows or hides a sprite collection's inspector", toggle_sprites)
+                        .arg("collection", ArgKind::Word)
+                        .completions(|_| SPRITE_COLLECTIONS.map(String::from).to_vec()),
+                );
+
+            let mut registry = app.world.resource_mut::<InspectableRegistry>();
+            registry.register_raw::<Timer, _>(|timer, ui, _| timer_ui(timer, ui));
+            registry.register_raw::<AnimationTimer, _>(|timer, ui, _| timer_ui(timer, ui));
+            registry.register_raw::<CameraTimer, _>(|timer, ui, _| timer_ui(timer, ui));
+            registry.register_raw(transform_ui);
+            registry.register_raw(atlas_sprite_ui);
+
+            // The sprite collections are big; they open from the console.
+            let mut windows = app.world.resource_mut::<InspectorWindows>();
+            windows.window_data_mut::<Characters>().visible = false;
+            windows.window_data_mut::<Mobs>().visible = false;
+            windows.window_data_mut::<Tiles>().visible = false;
+            windows.window_data_mut::<Props>().visible = false;
+            #[cfg(debug_assertions)]
+            app.add_plugin(crate::overlay::OverlayPlugin)
+                .add_plugin(crate::editor::EditorPlugin);
+        }
+    }
+}
+
+fn log_interactions(
+    mut open_chest: EventReader<OpenChest>,
+    mut read_sign: EventReader<ReadSign>,
+    mut talk_to: EventReader<TalkTo>,
+    mut pick_up: EventReader<PickUp>,
+) {
+    for OpenChest(entity) in open_chest.iter() {
+        debug!("Opened chest {entity:?}");
+    }
+    for ReadSign(entity) in read_sign.iter() {
+        debug!("Read sign {entity:?}");
+    }
+    for TalkTo(entity) in talk_to.iter() {
+        debug!("Talked to {entity:?}");
+    }
+    for PickUp(entity) in pick_up.iter() {
+        debug!("Picked up {entity:?}");
+    }
+}
+
+/// F6 exports the map to `map.scn.ron`, F7 the whole world to `world.scn.ron`,
+/// and F8 swaps the map for the exported one.
+fn scene_keys(
+    mut commands: Commands,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut exports: EventWriter<ExportScene>,
+    mut spawns: EventWriter<SpawnScene>,
+    map_query: Query<Entity, With<MapRoot>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::F6) {
+        for map in &map_query {
+            exports.send(ExportScene {
+                root: Some(map),
+                name: "map".to_string(),
+            });
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::F7) {
+        exports.send(ExportScene {
+            root: None,
+            name: "world".to_string(),
+        });
+    }
+    if keyboard_input.just_pressed(KeyCode::F8) {
+        for map in &map_query {
+            commands.entity(map).despawn_recursive();
+        }
+        spawns.send(SpawnScene {
+            name: "map".to_string(),
+        });
+    }
+}
+
+/// F9 writes the settings, as edited in the inspector, to `game.settings.ron`.
+fn settings_keys(keyboard_input: Res<Input<KeyCode>>, mut saves: EventWriter<SaveSettings>) {
+    if keyboard_input.just_pressed(KeyCode::F9) {
+        saves.send(SaveSettings);
+    }
+}
+
+fn player_entity(world: &mut World) -> Result<Entity, String> {
+    world
+        .query_filtered::<Entity, With<Player>>()
+        .get_single(world)
+        .map_err(|_| "There is no player".to_string())
+}
+
+fn teleport(world: &mut World, args: &Args) -> Result<String, String> {
+    let player = player_entity(world)?;
+    let (x, y) = (args.integer(0).unwrap_or_default(), args.integer(1).unwrap_or_default());
+    let map = world.resource::<MapData>();
+    if x < 0 || y < 0 || x as usize >= map.width() || y as usize >= map.height() {
+        return Err(format!("({x}, {y}) is off the map"));
+    }
+    let position = map.tile_to_world(x as usize, y as usize);
+    let mut transform = world.get_mut::<Transform>(player).ok_or("The player has no position")?;
+    transform.translation = position.extend(transform.translation.z);
+    Ok(format!("Teleported to ({x}, {y})"))
+}
+
+fn set_speed(world: &mut World, args: &Args) -> Result<String, String> {
+    let player = player_entity(world)?;
+    let walk = args.number(0).unwrap_or_default();
+    let run = args.number(1).unwrap_or(walk * 1.5);
+    for speed in [walk, run] {
+        if !speed.is_finite() || speed <= 0. {
+            return Err(format!("Speeds have to be above 0, not {speed}"));
         }
     }
+    let mut player = world.get_mut::<Player>(player).ok_or("The player is missing")?;
+    player.speed = walk;
+    player.run_speed = run;
+    Ok(format!("Walking at {walk} and running at {run} tiles per second"))
+}
+
+fn mob_ids(world: &World) -> Vec<String> {
+    world
+        .resource::<Assets<MobDatabase>>()
+        .get(&world.resource::<MobDatabaseHandle>().0)
+        .map_or_else(Vec::new, |database| database.0.keys().cloned().collect())
+}
+
+/// Most mobs `spawn` puts down at once.
+const MAX_SPAWN: u32 = 100;
+
+fn spawn(world: &mut World, args: &Args) -> Result<String, String> {
+    let player = player_entity(world)?;
+    let id = args.word(0).unwrap_or_default();
+    let count = u32::try_from(args.integer(1).unwrap_or(1).max(1))
+        .ok()
+        .filter(|&count| count <= MAX_SPAWN)
+        .ok_or_else(|| format!("Can spawn at most {MAX_SPAWN} mobs at once"))?;
+    let def = world
+        .resource::<Assets<MobDatabase>>()
+        .get(&world.resource::<MobDatabaseHandle>().0)
+        .and_then(|database| database.0.get(id))
+        .cloned()
+        .ok_or_else(|| format!("No mob `{id}`"))?;
+    let position = world.get::<Transform>(player).map_or(Vec2::ZERO, |transform| transform.translation.truncate());
+
+    let mut queue = CommandQueue::default();
+    let mut commands = Commands::new(&mut queue, world);
+    for direction in (Pattern::Ring { count }).directions(Vec2::X) {
+        spawn_mob(
+            &mut commands,
+            &def,
+            position + direction * 1.5 * world.resource::<GameConfig>().tile_size,
+            world.resource::<GameConfig>(),
+            world.resource::<Mobs>(),
+            world.resource::<Assets<NamedAtlas>>(),
+            world.resource::<Assets<TextureAtlas>>(),
+        );
+    }
+    queue.apply(world);
+    Ok(format!("Spawned {count} {}", def.name))
+}
+
+fn load_map(world: &mut World, args: &Args) -> Result<String, String> {
+    let name = args.word(0).unwrap_or_default();
+    if *world.resource::<State<AppState>>().current() != AppState::InGame {
+        return Err("Maps can only be loaded in game".to_string());
+    }
+    if !MapData::exists(name) {
+        return Err(format!("No map `{name}` in `assets/maps`"));
+    }
+    world.send_event(LoadMap(name.to_string()));
+    Ok(format!("Loading `{name}`"))
+}
+
+fn toggle_noclip(world: &mut World, _: &Args) -> Result<String, String> {
+    let player = player_entity(world)?;
+    let mut player = world.entity_mut(player);
+    if player.contains::<Noclip>() {
+        player.remove::<Noclip>();
+        Ok("Noclip off".to_string())
+    } else {
+        player.insert(Noclip);
+        Ok("Noclip on".to_string())
+    }
+}
+
+fn item_ids(world: &World) -> Vec<String> {
+    world.resource::<Items>().0.keys().cloned().collect()
+}
+
+fn give(world: &mut World, args: &Args) -> Result<String, String> {
+    let player = player_entity(world)?;
+    let item = args.word(0).unwrap_or_default();
+    let count = u32::try_from(args.integer(1).unwrap_or(1).max(1))
+        .map_err(|_| format!("Can give at most {} at once", u32::MAX))?;
+    world.resource_scope(|world, items: Mut<Items>| {
+        let mut inventory = world.get_mut::<Inventory>(player).ok_or("The player has no inventory")?;
+        inventory.add(item, count, &items).map_err(|err| err.to_string())?;
+        Ok(format!("Gave {count} {item}"))
+    })
+}
+
+const SPRITE_COLLECTIONS: [&str; 4] = ["characters", "mobs", "tiles", "props"];
+
+fn toggle_sprites(world: &mut World, args: &Args) -> Result<String, String> {
+    let name = args.word(0).unwrap_or_default();
+    let mut windows = world.resource_mut::<InspectorWindows>();
+    let window = match name {
+        "characters" => windows.window_data_mut::<Characters>(),
+        "mobs" => windows.window_data_mut::<Mobs>(),
+        "tiles" => windows.window_data_mut::<Tiles>(),
+        "props" => windows.window_data_mut::<Props>(),
+        _ => return Err(format!("No sprite collection `{name}`, try one of {}", SPRITE_COLLECTIONS.join(", "))),
+    };
+    window.visible = !window.visible;
+    Ok(format!("{} the {name} inspector", if window.visible { "Opened" } else { "Closed" }))
 }
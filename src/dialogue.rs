@@ -0,0 +1,573 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashMap, HashSet},
+};
+use bevy_inspector_egui::{egui, Context, Inspectable};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    character::{Direction, Motion},
+    inspector::Fields,
+    interact::{Interactable, InteractionKind, ReadSign, TalkTo, INTERACT_KEY},
+    loading::LoadingAssets,
+    player::Player,
+    sprites::{NamedAtlas, Props},
+    tilemap::{prop_size, MapData, MapObject, TileCollider},
+    AppState,
+};
+
+pub struct DialoguePlugin;
+
+impl Plugin for DialoguePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<Dialogue>()
+            .init_asset_loader::<DialogueLoader>()
+            .init_resource::<GameFlags>()
+            .add_event::<DialogueEvent>()
+            .add_system_set(SystemSet::on_enter(AppState::Loading).with_system(load_dialogues))
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(start_dialogue.after("interact")),
+            )
+            .add_system_set(SystemSet::on_enter(AppState::InGame).with_system(spawn_signs))
+            .add_system_set(SystemSet::on_enter(AppState::Dialogue).with_system(spawn_dialogue_box))
+            .add_system_set(
+                SystemSet::on_update(AppState::Dialogue)
+                    .with_system(advance_dialogue.label("advance_dialogue"))
+                    .with_system(update_dialogue_box.after("advance_dialogue")),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::Dialogue).with_system(despawn_dialogue_box));
+    }
+}
+
+/// Characters revealed per second by the typewriter effect.
+const TYPEWRITER_SPEED: f32 = 40.;
+
+const TEXT_BOX_PATH: &str = "textures/rpg/ui/generic-rpg-ui-text-box.png";
+
+/// Named world flags, set and tested by dialogue.
+#[derive(Debug, Default, Deref, DerefMut, Serialize, Deserialize)]
+pub struct GameFlags(pub HashSet<String>);
+
+/// Sent for every `Emit` command a dialogue runs, for other systems to act on.
+pub struct DialogueEvent {
+    pub name: String,
+    /// The entity the player is talking to.
+    pub speaker: Entity,
+}
+
+/// A conversation: a graph of nodes starting at `start`.
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "26a642cf-e942-4e7e-8ad1-8ca47f25300b"]
+pub struct Dialogue {
+    pub start: String,
+    pub nodes: HashMap<String, DialogueNode>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DialogueNode {
+    /// Who is talking, the NPC's name if left out.
+    #[serde(default)]
+    pub speaker: Option<String>,
+    #[serde(default)]
+    pub text: String,
+    /// Checked in order before the node is shown; the first one that holds
+    /// jumps straight to its node instead.
+    #[serde(default)]
+    pub branches: Vec<Branch>,
+    /// Run when the node is shown.
+    #[serde(default)]
+    pub commands: Vec<DialogueCommand>,
+    #[serde(default)]
+    pub choices: Vec<Choice>,
+    /// Node to show after this one when there are no choices; the
+    /// conversation ends if there is none.
+    #[serde(default)]
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Branch {
+    pub condition: Condition,
+    pub node: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Choice {
+    pub text: String,
+    /// The choice is only offered while this holds.
+    #[serde(default)]
+    pub condition: Option<Condition>,
+    #[serde(default)]
+    pub commands: Vec<DialogueCommand>,
+    #[serde(default)]
+    pub next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum Condition {
+    Flag(String),
+    Not(Box<Condition>),
+    All(Vec<Condition>),
+    Any(Vec<Condition>),
+}
+
+impl Condition {
+    pub fn holds(&self, flags: &GameFlags) -> bool {
+        match self {
+            Condition::Flag(flag) => flags.contains(flag),
+            Condition::Not(condition) => !condition.holds(flags),
+            Condition::All(conditions) => conditions.iter().all(|c| c.holds(flags)),
+            Condition::Any(conditions) => conditions.iter().any(|c| c.holds(flags)),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub enum DialogueCommand {
+    SetFlag(String),
+    ClearFlag(String),
+    /// Sends a `DialogueEvent` with the given name. Systems outside dialogue
+    /// only run once the conversation is over, so emit from the last node.
+    Emit(String),
+}
+
+impl Dialogue {
+    /// Follows branches from `node` to the node that should actually be shown.
+    fn resolve<'a>(&'a self, mut node: &'a str, flags: &GameFlags) -> Option<&'a str> {
+        // Bounded so a cycle of branches can't hang the game.
+        for _ in 0..self.nodes.len() + 1 {
+            let branch = self
+                .nodes
+                .get(node)?
+                .branches
+                .iter()
+                .find(|branch| branch.condition.holds(flags));
+            match branch {
+                Some(branch) => node = &branch.node,
+                None => return Some(node),
+            }
+        }
+        warn!("Dialogue branches loop around `{node}`");
+        None
+    }
+}
+
+#[derive(Default)]
+pub struct DialogueLoader;
+
+impl AssetLoader for DialogueLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let dialogue: Dialogue = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(dialogue));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["dialogue.ron"]
+    }
+}
+
+/// A sign as placed in a map's objects file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SignSpawn {
+    pub position: (usize, usize),
+    pub text: String,
+    /// Frame of the props atlases the sign is drawn with.
+    #[serde(default = "default_sign_sprite")]
+    pub sprite: String,
+}
+
+fn default_sign_sprite() -> String {
+    "board-01".to_string()
+}
+
+/// Lets the player talk to the entity.
+#[derive(Component)]
+pub struct DialogueSource(pub Handle<Dialogue>);
+
+impl Inspectable for DialogueSource {
+    type Attributes = ();
+
+    fn ui(&mut self, ui: &mut egui::Ui, _: Self::Attributes, context: &mut Context) -> bool {
+        Fields::show(ui, context, |fields| {
+            fields.asset("dialogue", &self.0);
+        })
+    }
+}
+
+/// The conversation currently on screen.
+pub struct ActiveDialogue {
+    dialogue: Handle<Dialogue>,
+    speaker: Entity,
+    /// Speaker shown for nodes that don't name one.
+    default_speaker: String,
+    node: String,
+    revealed: f32,
+    selected: usize,
+}
+
+#[derive(Component)]
+struct DialogueBox;
+
+#[derive(Component)]
+struct SpeakerText;
+
+#[derive(Component)]
+struct BodyText;
+
+#[derive(Component)]
+struct ChoicesText;
+
+struct DialogueAssets {
+    text_box: Handle<Image>,
+    font: Handle<Font>,
+}
+
+fn load_dialogues(
+    mut commands: Commands,
+    mut loading: ResMut<LoadingAssets>,
+    map: Res<MapData>,
+    asset_server: Res<AssetServer>,
+) {
+    let text_box = asset_server.load(TEXT_BOX_PATH);
+    loading.add(TEXT_BOX_PATH, &text_box);
+    commands.insert_resource(DialogueAssets {
+        text_box,
+        font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+    });
+
+    for object in &map.objects {
+        let spawn = match object {
+            MapObject::Npc(spawn) => spawn,
+            _ => continue,
+        };
+        if let Some(path) = &spawn.dialogue {
+            let handle: Handle<Dialogue> = asset_server.load(path.as_str());
+            loading.add(path.as_str(), &handle);
+        }
+    }
+}
+
+/// Starts the conversation of whoever the player talks to, or the text of the
+/// sign they read.
+#[allow(clippy::too_many_arguments)]
+fn start_dialogue(
+    mut commands: Commands,
+    mut state: ResMut<State<AppState>>,
+    mut talk_to: EventReader<TalkTo>,
+    mut read_sign: EventReader<ReadSign>,
+    mut flags: ResMut<GameFlags>,
+    mut events: EventWriter<DialogueEvent>,
+    dialogues: Res<Assets<Dialogue>>,
+    player_query: Query<&Transform, With<Player>>,
+    mut speaker_query: Query<
+        (&GlobalTransform, &DialogueSource, &Name, Option<&mut Motion>),
+        Without<Player>,
+    >,
+) {
+    let speakers: Vec<Entity> = talk_to
+        .iter()
+        .map(|event| event.0)
+        .chain(read_sign.iter().map(|event| event.0))
+        .collect();
+    for speaker in speakers {
+        let (transform, source, name, motion) = match speaker_query.get_mut(speaker) {
+            Ok(speaker) => speaker,
+            Err(_) => continue,
+        };
+        let dialogue = match dialogues.get(&source.0) {
+            Some(dialogue) => dialogue,
+            None => continue,
+        };
+        let node = match dialogue.resolve(&dialogue.start, &flags) {
+            Some(node) => node.to_string(),
+            None => continue,
+        };
+
+        if let (Some(mut motion), Ok(player_transform)) = (motion, player_query.get_single()) {
+            motion.facing = if transform.translation().x > player_transform.translation.x {
+                Direction::Left
+            } else {
+                Direction::Right
+            };
+        }
+        run_commands(&dialogue.nodes[&node].commands, speaker, &mut flags, &mut events);
+        commands.insert_resource(ActiveDialogue {
+            dialogue: source.0.clone(),
+            speaker,
+            default_speaker: name.to_string(),
+            node,
+            revealed: 0.,
+            selected: 0,
+        });
+        state.push(AppState::Dialogue).unwrap();
+        return;
+    }
+}
+
+/// Puts the map's signs down, each reading as a one node dialogue.
+fn spawn_signs(
+    mut commands: Commands,
+    map: Res<MapData>,
+    props: Res<Props>,
+    named_atlases: Res<Assets<NamedAtlas>>,
+    texture_atlases: Res<Assets<TextureAtlas>>,
+    mut dialogues: ResMut<Assets<Dialogue>>,
+) {
+    for object in &map.objects {
+        let spawn = match object {
+            MapObject::Sign(spawn) => spawn,
+            _ => continue,
+        };
+        let (texture_atlas, index) = match props.frame(&named_atlases, &spawn.sprite) {
+            Some(frame) => frame,
+            None => {
+                warn!("No `{}` frame in the prop atlases", spawn.sprite);
+                continue;
+            }
+        };
+        let size = texture_atlases
+            .get(&texture_atlas)
+            .map_or(Vec2::splat(map.tile_size), |atlas| prop_size(atlas, index, map.tile_size));
+        let dialogue = dialogues.add(Dialogue {
+            start: "start".to_string(),
+            nodes: HashMap::from_iter([(
+                "start".to_string(),
+                DialogueNode {
+                    text: spawn.text.clone(),
+                    ..default()
+                },
+            )]),
+        });
+
+        commands
+            .spawn_bundle(SpriteSheetBundle {
+                sprite: TextureAtlasSprite {
+                    index,
+                    custom_size: Some(size),
+                    ..default()
+                },
+                texture_atlas,
+                transform: Transform::from_translation(
+                    map.tile_to_world(spawn.position.0, spawn.position.1).extend(1.),
+                ),
+                ..default()
+            })
+            .insert(Name::new("Sign"))
+            .insert(DialogueSource(dialogue))
+            .insert(Interactable::new(InteractionKind::ReadSign))
+            .insert(TileCollider);
+    }
+}
+
+fn run_commands(
+    dialogue_commands: &[DialogueCommand],
+    speaker: Entity,
+    flags: &mut GameFlags,
+    events: &mut EventWriter<DialogueEvent>,
+) {
+    for command in dialogue_commands {
+        match command {
+            DialogueCommand::SetFlag(flag) => {
+                flags.insert(flag.clone());
+            }
+            DialogueCommand::ClearFlag(flag) => {
+                flags.remove(flag);
+            }
+            DialogueCommand::Emit(event) => events.send(DialogueEvent {
+                name: event.clone(),
+                speaker,
+            }),
+        }
+    }
+}
+
+fn visible_choices<'a>(node: &'a DialogueNode, flags: &GameFlags) -> Vec<&'a Choice> {
+    node.choices
+        .iter()
+        .filter(|choice| {
+            choice
+                .condition
+                .as_ref()
+                .is_none_or(|condition| condition.holds(flags))
+        })
+        .collect()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn advance_dialogue(
+    mut commands: Commands,
+    mut state: ResMut<State<AppState>>,
+    time: Res<Time>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut flags: ResMut<GameFlags>,
+    mut events: EventWriter<DialogueEvent>,
+    dialogues: Res<Assets<Dialogue>>,
+    mut active: ResMut<ActiveDialogue>,
+) {
+    let dialogue = match dialogues.get(&active.dialogue) {
+        Some(dialogue) => dialogue,
+        None => return,
+    };
+    let node = &dialogue.nodes[&active.node];
+    let length = node.text.chars().count() as f32;
+
+    if active.revealed < length {
+        active.revealed += TYPEWRITER_SPEED * time.delta_seconds();
+        if keyboard_input.just_pressed(INTERACT_KEY) {
+            keyboard_input.reset(INTERACT_KEY);
+            active.revealed = length;
+        }
+        return;
+    }
+
+    let choices = visible_choices(node, &flags);
+    if !choices.is_empty() {
+        if keyboard_input.just_pressed(KeyCode::W) {
+            active.selected = (active.selected + choices.len() - 1) % choices.len();
+        }
+        if keyboard_input.just_pressed(KeyCode::S) {
+            active.selected = (active.selected + 1) % choices.len();
+        }
+    }
+    if !keyboard_input.just_pressed(INTERACT_KEY) {
+        return;
+    }
+    keyboard_input.reset(INTERACT_KEY);
+
+    let next = match choices.get(active.selected) {
+        Some(choice) => {
+            run_commands(&choice.commands, active.speaker, &mut flags, &mut events);
+            choice.next.as_deref()
+        }
+        None => node.next.as_deref(),
+    };
+    match next.and_then(|next| dialogue.resolve(next, &flags)) {
+        Some(next) => {
+            run_commands(&dialogue.nodes[next].commands, active.speaker, &mut flags, &mut events);
+            active.node = next.to_string();
+            active.revealed = 0.;
+            active.selected = 0;
+        }
+        None => {
+            commands.remove_resource::<ActiveDialogue>();
+            state.pop().unwrap();
+        }
+    }
+}
+
+fn spawn_dialogue_box(mut commands: Commands, assets: Res<DialogueAssets>) {
+    let style = |font_size, color| TextStyle {
+        font: assets.font.clone(),
+        font_size,
+        color,
+    };
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::FlexStart,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .insert(Name::new("Dialogue Box"))
+        .insert(DialogueBox)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(ImageBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(80.), Val::Percent(30.)),
+                        margin: UiRect::all(Val::Percent(2.)),
+                        padding: UiRect::new(
+                            Val::Percent(4.),
+                            Val::Percent(4.),
+                            Val::Percent(2.),
+                            Val::Percent(2.),
+                        ),
+                        flex_direction: FlexDirection::ColumnReverse,
+                        ..default()
+                    },
+                    image: assets.text_box.clone().into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(TextBundle::from_section("", style(24., Color::GOLD)))
+                        .insert(SpeakerText);
+                    parent
+                        .spawn_bundle(TextBundle {
+                            style: Style {
+                                max_size: Size::new(Val::Px(700.), Val::Undefined),
+                                ..default()
+                            },
+                            ..TextBundle::from_section("", style(20., Color::WHITE))
+                        })
+                        .insert(BodyText);
+                    parent
+                        .spawn_bundle(TextBundle::from_section("", style(20., Color::GRAY)))
+                        .insert(ChoicesText);
+                });
+        });
+}
+
+#[allow(clippy::type_complexity)]
+fn update_dialogue_box(
+    active: Option<Res<ActiveDialogue>>,
+    flags: Res<GameFlags>,
+    dialogues: Res<Assets<Dialogue>>,
+    mut speaker_query: Query<&mut Text, (With<SpeakerText>, Without<BodyText>, Without<ChoicesText>)>,
+    mut body_query: Query<&mut Text, (With<BodyText>, Without<ChoicesText>)>,
+    mut choices_query: Query<&mut Text, With<ChoicesText>>,
+) {
+    let active = match active {
+        Some(active) => active,
+        None => return,
+    };
+    let node = match dialogues.get(&active.dialogue) {
+        Some(dialogue) => &dialogue.nodes[&active.node],
+        None => return,
+    };
+
+    for mut text in &mut speaker_query {
+        text.sections[0].value = node
+            .speaker
+            .clone()
+            .unwrap_or_else(|| active.default_speaker.clone());
+    }
+    for mut text in &mut body_query {
+        text.sections[0].value = node.text.chars().take(active.revealed as usize).collect();
+    }
+    for mut text in &mut choices_query {
+        let length = node.text.chars().count() as f32;
+        text.sections[0].value = if active.revealed < length {
+            String::new()
+        } else {
+            visible_choices(node, &flags)
+                .iter()
+                .enumerate()
+                .map(|(i, choice)| {
+                    let marker = if i == active.selected { "> " } else { "  " };
+                    format!("{marker}{}\n", choice.text)
+                })
+                .collect()
+        };
+    }
+}
+
+fn despawn_dialogue_box(mut commands: Commands, query: Query<Entity, With<DialogueBox>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
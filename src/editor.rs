@@ -0,0 +1,421 @@
+//! A tile map editor, toggled with F10 in debug builds. Pick a tile from the
+//! palette and paint the map with the mouse; edits go through `TileEdit`, so
+//! colliders and paths update as you paint. Ctrl+Z undoes, Ctrl+Y redoes and
+//! Save writes the layers back to `assets/maps`.
+
+use bevy::{prelude::*, utils::HashSet};
+use bevy_inspector_egui::{
+    bevy_egui::{EguiContext, EguiPlugin},
+    egui,
+};
+
+use crate::{
+    overlay::{cursor_position, DebugLines},
+    sprites::{frame_image, NamedAtlas, Props, Tiles},
+    tilemap::{MapData, MapLayer, Tile, TileEdit, TileKind, TILE_KINDS},
+    AppState,
+};
+
+pub struct EditorPlugin;
+
+impl Plugin for EditorPlugin {
+    fn build(&self, app: &mut App) {
+        if !app.world.contains_resource::<EguiContext>() {
+            app.add_plugin(EguiPlugin);
+        }
+        app.init_resource::<Editor>()
+            .add_system(toggle_editor)
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(build_palette)
+                    .with_system(editor_panel.label("editor_panel").after(build_palette))
+                    .with_system(paint.after("editor_panel"))
+                    .with_system(undo_keys)
+                    .with_system(layer_visibility),
+            );
+    }
+}
+
+pub const EDITOR_KEY: KeyCode = KeyCode::F10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tool {
+    Brush,
+    Rectangle,
+    Fill,
+    Eraser,
+}
+
+/// One change to a tile, and what it was before, so it can be undone.
+#[derive(Debug, Clone, Copy)]
+struct Change {
+    layer: MapLayer,
+    position: (usize, usize),
+    before: char,
+    after: char,
+}
+
+/// A palette entry, with its frame cut out for the panel.
+struct PaletteTile {
+    kind: TileKind,
+    icon: Option<Handle<Image>>,
+}
+
+pub struct Editor {
+    pub open: bool,
+    pub tool: Tool,
+    /// Layer and character painted by the brush, rectangle and fill.
+    pub tile: (MapLayer, char),
+    /// Layer the eraser clears.
+    pub erase_layer: MapLayer,
+    pub show_background: bool,
+    pub show_foreground: bool,
+    palette: Vec<PaletteTile>,
+    /// Changes of the stroke being painted, undone as one.
+    stroke: Vec<Change>,
+    /// Where the rectangle being dragged started.
+    drag_start: Option<(usize, usize)>,
+    undo: Vec<Vec<Change>>,
+    redo: Vec<Vec<Change>>,
+    status: String,
+}
+
+impl Default for Editor {
+    fn default() -> Self {
+        Editor {
+            open: false,
+            tool: Tool::Brush,
+            tile: (MapLayer::Background, 'g'),
+            erase_layer: MapLayer::Foreground,
+            show_background: true,
+            show_foreground: true,
+            palette: Vec::new(),
+            stroke: Vec::new(),
+            drag_start: None,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            status: String::new(),
+        }
+    }
+}
+
+impl Editor {
+    /// The layer the current tool works on, and the character it puts down.
+    fn target(&self) -> (MapLayer, char) {
+        match self.tool {
+            Tool::Eraser => (self.erase_layer, ' '),
+            _ => self.tile,
+        }
+    }
+
+    /// Sets a tile of the target layer as part of the current stroke, unless
+    /// it already is `c`.
+    fn set(&mut self, map: &MapData, edits: &mut EventWriter<TileEdit>, position: (usize, usize), c: char) {
+        let layer = self.target().0;
+        // Tiles already changed this stroke are not in `map` until the edits apply.
+        let before = match self
+            .stroke
+            .iter()
+            .rev()
+            .find(|change| (change.layer, change.position) == (layer, position))
+        {
+            Some(change) => change.after,
+            None => map.tile_at(layer, position.0, position.1).unwrap_or(' '),
+        };
+        if before == c {
+            return;
+        }
+        self.stroke.push(Change {
+            layer,
+            position,
+            before,
+            after: c,
+        });
+        edits.send(TileEdit {
+            layer,
+            position,
+            tile: c,
+        });
+    }
+
+    fn finish_stroke(&mut self) {
+        if self.stroke.is_empty() {
+            return;
+        }
+        self.undo.push(std::mem::take(&mut self.stroke));
+        self.redo.clear();
+    }
+}
+
+fn toggle_editor(keyboard_input: Res<Input<KeyCode>>, mut editor: ResMut<Editor>) {
+    if keyboard_input.just_pressed(EDITOR_KEY) {
+        editor.open = !editor.open;
+        editor.finish_stroke();
+        editor.drag_start = None;
+    }
+}
+
+/// Cuts every paintable tile out of the atlases, once they are loaded.
+fn build_palette(
+    mut editor: ResMut<Editor>,
+    tiles: Res<Tiles>,
+    props: Res<Props>,
+    named_atlases: Res<Assets<NamedAtlas>>,
+    texture_atlases: Res<Assets<TextureAtlas>>,
+    mut images: ResMut<Assets<Image>>,
+) {
+    if !editor.open || !editor.palette.is_empty() {
+        return;
+    }
+    editor.palette = TILE_KINDS
+        .iter()
+        .map(|kind| {
+            let sprites = match kind.layer {
+                MapLayer::Background => &tiles.0,
+                MapLayer::Foreground => &props.0,
+            };
+            let icon = sprites
+                .frame(&named_atlases, kind.frame)
+                .and_then(|(atlas, index)| frame_image(texture_atlases.get(&atlas)?, index, &images))
+                .map(|image| images.add(image));
+            PaletteTile { kind: *kind, icon }
+        })
+        .collect();
+}
+
+fn layer_name(layer: MapLayer) -> &'static str {
+    match layer {
+        MapLayer::Background => "Background",
+        MapLayer::Foreground => "Foreground",
+    }
+}
+
+fn editor_panel(
+    mut egui_context: ResMut<EguiContext>,
+    mut editor: ResMut<Editor>,
+    mut edits: EventWriter<TileEdit>,
+    map: Res<MapData>,
+) {
+    if !editor.open {
+        return;
+    }
+    let icons: Vec<Option<egui::TextureId>> = editor
+        .palette
+        .iter()
+        .map(|tile| tile.icon.clone().map(|icon| egui_context.add_image(icon)))
+        .collect();
+    let mut undo = false;
+    let mut redo = false;
+    egui::Window::new("Map Editor").show(egui_context.ctx_mut(), |ui| {
+        ui.label(format!("Map `{}`", map.name));
+        ui.horizontal(|ui| {
+            for (tool, name) in [
+                (Tool::Brush, "Brush"),
+                (Tool::Rectangle, "Rectangle"),
+                (Tool::Fill, "Fill"),
+                (Tool::Eraser, "Eraser"),
+            ] {
+                ui.selectable_value(&mut editor.tool, tool, name);
+            }
+        });
+
+        ui.separator();
+        ui.horizontal_wrapped(|ui| {
+            for (index, icon) in icons.iter().enumerate() {
+                let kind = editor.palette[index].kind;
+                let selected = editor.tile == (kind.layer, kind.c);
+                let response = match icon {
+                    Some(icon) => ui.add(egui::ImageButton::new(*icon, [32., 32.]).selected(selected)),
+                    None => ui.selectable_label(selected, kind.name),
+                };
+                if response.on_hover_text(format!("{} `{}`", kind.name, kind.c)).clicked() {
+                    editor.tile = (kind.layer, kind.c);
+                    if editor.tool == Tool::Eraser {
+                        editor.tool = Tool::Brush;
+                    }
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Erase from");
+            for layer in [MapLayer::Background, MapLayer::Foreground] {
+                ui.selectable_value(&mut editor.erase_layer, layer, layer_name(layer));
+            }
+        });
+
+        ui.separator();
+        ui.checkbox(&mut editor.show_background, "Show background");
+        ui.checkbox(&mut editor.show_foreground, "Show foreground");
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            undo = ui
+                .add_enabled(!editor.undo.is_empty(), egui::Button::new("Undo"))
+                .clicked();
+            redo = ui
+                .add_enabled(!editor.redo.is_empty(), egui::Button::new("Redo"))
+                .clicked();
+            if ui.button("Save").clicked() {
+                editor.status = match map.save() {
+                    Ok(()) => format!("Saved `assets/maps/{}`", map.name),
+                    Err(err) => format!("Could not save: {err}"),
+                };
+            }
+        });
+        if !editor.status.is_empty() {
+            ui.label(&editor.status);
+        }
+    });
+    if undo {
+        step_history(&mut editor, &mut edits, true);
+    }
+    if redo {
+        step_history(&mut editor, &mut edits, false);
+    }
+}
+
+fn undo_keys(keyboard_input: Res<Input<KeyCode>>, mut editor: ResMut<Editor>, mut edits: EventWriter<TileEdit>) {
+    if !editor.open || !keyboard_input.any_pressed([KeyCode::LControl, KeyCode::RControl]) {
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::Z) {
+        step_history(&mut editor, &mut edits, true);
+    } else if keyboard_input.just_pressed(KeyCode::Y) {
+        step_history(&mut editor, &mut edits, false);
+    }
+}
+
+/// Undoes the last stroke, or redoes the last one undone.
+fn step_history(editor: &mut Editor, edits: &mut EventWriter<TileEdit>, undo: bool) {
+    editor.finish_stroke();
+    let changes = match if undo { editor.undo.pop() } else { editor.redo.pop() } {
+        Some(changes) => changes,
+        None => return,
+    };
+    // Undone in reverse, so a tile changed twice ends up as it first was.
+    let ordered: Vec<&Change> = if undo {
+        changes.iter().rev().collect()
+    } else {
+        changes.iter().collect()
+    };
+    for change in ordered {
+        edits.send(TileEdit {
+            layer: change.layer,
+            position: change.position,
+            tile: if undo { change.before } else { change.after },
+        });
+    }
+    if undo {
+        editor.redo.push(changes);
+    } else {
+        editor.undo.push(changes);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn paint(
+    mut editor: ResMut<Editor>,
+    mut egui_context: ResMut<EguiContext>,
+    mouse: Res<Input<MouseButton>>,
+    windows: Res<Windows>,
+    map: Res<MapData>,
+    mut edits: EventWriter<TileEdit>,
+    mut lines: ResMut<DebugLines>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+) {
+    if !editor.open {
+        return;
+    }
+    if mouse.just_released(MouseButton::Left) {
+        if let (Some(start), Some(end)) = (editor.drag_start.take(), hovered_tile(&windows, &map, &camera_query)) {
+            let c = editor.target().1;
+            for y in start.1.min(end.1)..=start.1.max(end.1) {
+                for x in start.0.min(end.0)..=start.0.max(end.0) {
+                    editor.set(&map, &mut edits, (x, y), c);
+                }
+            }
+        }
+        editor.finish_stroke();
+    }
+    if egui_context.ctx_mut().wants_pointer_input() {
+        return;
+    }
+    let hovered = match hovered_tile(&windows, &map, &camera_query) {
+        Some(tile) => tile,
+        None => return,
+    };
+    lines.rect(map.tile_to_world(hovered.0, hovered.1), Vec2::splat(map.tile_size), Color::YELLOW);
+
+    let (layer, c) = editor.target();
+    match editor.tool {
+        Tool::Brush | Tool::Eraser if mouse.pressed(MouseButton::Left) => {
+            editor.set(&map, &mut edits, hovered, c);
+        }
+        Tool::Rectangle => {
+            if mouse.just_pressed(MouseButton::Left) {
+                editor.drag_start = Some(hovered);
+            }
+            if let Some(start) = editor.drag_start {
+                let (a, b) = (map.tile_to_world(start.0, start.1), map.tile_to_world(hovered.0, hovered.1));
+                let size = (a - b).abs() + Vec2::splat(map.tile_size);
+                lines.rect((a + b) / 2., size, Color::YELLOW);
+            }
+        }
+        Tool::Fill if mouse.just_pressed(MouseButton::Left) => {
+            for position in flood_fill(&map, layer, hovered) {
+                editor.set(&map, &mut edits, position, c);
+            }
+            editor.finish_stroke();
+        }
+        _ => {}
+    }
+}
+
+fn hovered_tile(
+    windows: &Windows,
+    map: &MapData,
+    camera_query: &Query<(&Camera, &GlobalTransform)>,
+) -> Option<(usize, usize)> {
+    let (camera, transform) = camera_query.get_single().ok()?;
+    map.world_to_tile(cursor_position(windows, camera, transform)?)
+}
+
+/// Every tile of `layer` connected to `start` through tiles of the same
+/// character, `start` included.
+fn flood_fill(map: &MapData, layer: MapLayer, start: (usize, usize)) -> HashSet<(usize, usize)> {
+    let target = map.tile_at(layer, start.0, start.1).unwrap_or(' ');
+    let mut filled = HashSet::from_iter([start]);
+    let mut stack = vec![start];
+    while let Some((x, y)) = stack.pop() {
+        let neighbours = [
+            x.checked_sub(1).map(|x| (x, y)),
+            Some((x + 1, y)).filter(|(x, _)| *x < map.width()),
+            y.checked_sub(1).map(|y| (x, y)),
+            Some((x, y + 1)).filter(|(_, y)| *y < map.height()),
+        ];
+        for position in neighbours.into_iter().flatten() {
+            let c = map.tile_at(layer, position.0, position.1).unwrap_or(' ');
+            if c == target && filled.insert(position) {
+                stack.push(position);
+            }
+        }
+    }
+    filled
+}
+
+/// Hides the layers unticked in the editor, including tiles painted since.
+fn layer_visibility(editor: Res<Editor>, mut tile_query: Query<(&Tile, &mut Visibility)>) {
+    if !editor.open && !editor.is_changed() {
+        return;
+    }
+    for (tile, mut visibility) in &mut tile_query {
+        let shown = !editor.open
+            || match tile.layer {
+                MapLayer::Background => editor.show_background,
+                MapLayer::Foreground => editor.show_foreground,
+            };
+        if visibility.is_visible != shown {
+            visibility.is_visible = shown;
+        }
+    }
+}
@@ -0,0 +1,575 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashMap},
+};
+use rand::{distributions::WeightedIndex, prelude::Distribution, rngs::StdRng, Rng, SeedableRng};
+use serde::Deserialize;
+
+use crate::{
+    character::Motion,
+    inventory::{spawn_toast, Inventory},
+    items::Items,
+    loading::LoadingAssets,
+    player::Player,
+    sprites::{NamedAtlas, Props},
+    tilemap::{prop_size, MapData, MapObject, TILE_PIXELS},
+    AppState, GameConfig,
+};
+
+pub struct FishingPlugin;
+
+impl Plugin for FishingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<FishTables>()
+            .init_asset_loader::<FishTablesLoader>()
+            .init_resource::<FishingRng>()
+            .init_resource::<WaterBodies>()
+            .add_system_set(SystemSet::on_enter(AppState::Loading).with_system(load_fish_tables))
+            .add_system_set(SystemSet::on_enter(AppState::InGame).with_system(spawn_water))
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame).with_system(cast_line.after("player_movement")),
+            )
+            .add_system_set(SystemSet::on_enter(AppState::Fishing).with_system(spawn_fishing_ui))
+            .add_system_set(
+                SystemSet::on_update(AppState::Fishing)
+                    .with_system(fishing.label("fishing"))
+                    .with_system(update_fishing_ui.after("fishing")),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::Fishing).with_system(despawn_fishing_ui));
+    }
+}
+
+/// Casts, hooks and reels in.
+pub const FISHING_KEY: KeyCode = KeyCode::F;
+
+pub const FISH_TABLES_PATH: &str = "game.fish.ron";
+
+/// Item the player needs to fish.
+const ROD_ITEM: &str = "rod";
+/// Shortest and longest wait for a bite, in seconds.
+const BITE_TIME: (f32, f32) = (1.5, 5.);
+/// How long the player has to hook a biting fish, in seconds.
+const HOOK_WINDOW: f32 = 0.8;
+/// Bar widths per second the reeling marker moves at for a fish of difficulty 1.
+const MARKER_SPEED: f32 = 0.8;
+/// Width of the catch zone on the bar for a fish of difficulty 1.
+const ZONE_WIDTH: f32 = 0.3;
+
+/// Random numbers for fishing. Seeded, so a run of casts can be replayed.
+pub struct FishingRng(pub StdRng);
+
+impl FishingRng {
+    pub fn seeded(seed: u64) -> FishingRng {
+        FishingRng(StdRng::seed_from_u64(seed))
+    }
+}
+
+/// Seeded from `GameConfig::fishing_seed` if there is one.
+impl FromWorld for FishingRng {
+    fn from_world(world: &mut World) -> Self {
+        let seed = world.get_resource::<GameConfig>().and_then(|config| config.fishing_seed);
+        FishingRng::seeded(seed.unwrap_or_else(rand::random))
+    }
+}
+
+/// A water body as marked in a map's objects file: the connected water tiles
+/// around `position` are fished from the `fish` table.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WaterSpawn {
+    pub position: (usize, usize),
+    /// Name of the table in `game.fish.ron`.
+    pub fish: String,
+}
+
+/// Every fish table, by name, as read from `game.fish.ron`.
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "8e3b6f1d-4a27-4c95-b0d8-5f6a2c1e9b47"]
+pub struct FishTables(pub HashMap<String, Vec<FishEntry>>);
+
+#[derive(Debug, Deserialize)]
+pub struct FishEntry {
+    /// Id of the fish in the item database.
+    pub item: String,
+    /// Chance of the fish biting, relative to the table's other fish.
+    pub weight: u32,
+    /// How hard the fish is to reel in; faster marker, smaller zone.
+    #[serde(default = "default_difficulty")]
+    pub difficulty: f32,
+}
+
+fn default_difficulty() -> f32 {
+    1.
+}
+
+#[derive(Default)]
+pub struct FishTablesLoader;
+
+impl AssetLoader for FishTablesLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let tables: FishTables = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(tables));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["fish.ron"]
+    }
+}
+
+/// The fish table of every water tile on the map.
+#[derive(Debug, Default)]
+pub struct WaterBodies(pub HashMap<(usize, usize), String>);
+
+struct FishTablesHandle(Handle<FishTables>);
+
+/// The cast on screen.
+struct FishingSession {
+    table: String,
+    phase: FishingPhase,
+}
+
+enum FishingPhase {
+    Waiting(Timer),
+    Biting(Timer),
+    Reeling {
+        fish: String,
+        marker: f32,
+        direction: f32,
+        speed: f32,
+        zone: (f32, f32),
+    },
+}
+
+#[derive(Component)]
+struct FishingUi;
+
+#[derive(Component)]
+struct FishingText;
+
+#[derive(Component)]
+struct FishingBar;
+
+#[derive(Component)]
+struct CatchZone;
+
+#[derive(Component)]
+struct ReelMarker;
+
+#[derive(Component)]
+struct Rod;
+
+fn load_fish_tables(
+    mut commands: Commands,
+    mut loading: ResMut<LoadingAssets>,
+    asset_server: Res<AssetServer>,
+) {
+    let handle = asset_server.load(FISH_TABLES_PATH);
+    loading.add(FISH_TABLES_PATH, &handle);
+    commands.insert_resource(FishTablesHandle(handle));
+}
+
+/// Finds the map's water bodies and draws a lake over each.
+fn spawn_water(
+    mut commands: Commands,
+    map: Res<MapData>,
+    props: Res<Props>,
+    named_atlases: Res<Assets<NamedAtlas>>,
+) {
+    let mut bodies = HashMap::default();
+    for object in &map.objects {
+        let spawn = match object {
+            MapObject::Water(spawn) => spawn,
+            _ => continue,
+        };
+        let tiles = flood_water(&map, spawn.position);
+        if tiles.is_empty() {
+            warn!("Water `{}` at {:?} is not on a water tile", spawn.fish, spawn.position);
+            continue;
+        }
+
+        let min = tiles.iter().fold(Vec2::splat(f32::MAX), |min, &(x, y)| {
+            min.min(map.tile_to_world(x, y))
+        });
+        let max = tiles.iter().fold(Vec2::splat(f32::MIN), |max, &(x, y)| {
+            max.max(map.tile_to_world(x, y))
+        });
+        // The lake art has a bank around the water, so it spills over by a bit.
+        let size = max - min + Vec2::splat(map.tile_size) + Vec2::splat(6. / TILE_PIXELS * map.tile_size);
+        match props.frame(&named_atlases, "mini-lake") {
+            Some((texture_atlas, index)) => {
+                commands
+                    .spawn_bundle(SpriteSheetBundle {
+                        sprite: TextureAtlasSprite {
+                            index,
+                            custom_size: Some(size),
+                            ..default()
+                        },
+                        texture_atlas,
+                        transform: Transform::from_translation(((min + max) / 2.).extend(0.5)),
+                        ..default()
+                    })
+                    .insert(Name::new("Lake"));
+            }
+            None => warn!("No `mini-lake` frame in the prop atlases"),
+        }
+
+        for tile in tiles {
+            bodies.insert(tile, spawn.fish.clone());
+        }
+    }
+    commands.insert_resource(WaterBodies(bodies));
+}
+
+/// Every water tile connected to `start`.
+fn flood_water(map: &MapData, start: (usize, usize)) -> Vec<(usize, usize)> {
+    let mut tiles = Vec::new();
+    let mut open = vec![start];
+    while let Some((x, y)) = open.pop() {
+        if map.background_at(x, y) != Some('w') || tiles.contains(&(x, y)) {
+            continue;
+        }
+        tiles.push((x, y));
+        open.extend([(x + 1, y), (x, y + 1)]);
+        if x > 0 {
+            open.push((x - 1, y));
+        }
+        if y > 0 {
+            open.push((x, y - 1));
+        }
+    }
+    tiles
+}
+
+#[allow(clippy::too_many_arguments)]
+fn cast_line(
+    mut commands: Commands,
+    mut state: ResMut<State<AppState>>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    asset_server: Res<AssetServer>,
+    config: Res<GameConfig>,
+    map: Res<MapData>,
+    water: Res<WaterBodies>,
+    mut rng: ResMut<FishingRng>,
+    player_query: Query<(&Transform, &Motion, &Inventory), With<Player>>,
+) {
+    if !keyboard_input.just_pressed(FISHING_KEY) {
+        return;
+    }
+    let (transform, motion, inventory) = player_query.single();
+    let spot = transform.translation.truncate() + motion.facing.vector() * config.tile_size;
+    let table = match map.world_to_tile(spot).and_then(|tile| water.0.get(&tile)) {
+        Some(table) => table,
+        None => return,
+    };
+    keyboard_input.reset(FISHING_KEY);
+    if inventory.count(ROD_ITEM) == 0 {
+        spawn_toast(&mut commands, &asset_server, "You need a rod to fish.".to_string(), 0);
+        return;
+    }
+
+    let wait = bite_wait(&mut rng.0);
+    commands.insert_resource(FishingSession {
+        table: table.clone(),
+        phase: FishingPhase::Waiting(Timer::from_seconds(wait, false)),
+    });
+    state.push(AppState::Fishing).unwrap();
+}
+
+#[allow(clippy::too_many_arguments)]
+fn fishing(
+    mut commands: Commands,
+    mut state: ResMut<State<AppState>>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    items: Res<Items>,
+    fish_tables: Res<Assets<FishTables>>,
+    fish_handle: Res<FishTablesHandle>,
+    mut rng: ResMut<FishingRng>,
+    mut session: ResMut<FishingSession>,
+    mut player_query: Query<&mut Inventory, With<Player>>,
+) {
+    let pressed = keyboard_input.just_pressed(FISHING_KEY);
+    if pressed {
+        keyboard_input.reset(FISHING_KEY);
+    }
+    let mut end = |commands: &mut Commands, message: String| {
+        spawn_toast(commands, &asset_server, message, 0);
+        commands.remove_resource::<FishingSession>();
+        state.pop().unwrap();
+    };
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        keyboard_input.reset(KeyCode::Escape);
+        end(&mut commands, "You reel in your line.".to_string());
+        return;
+    }
+
+    let table = session.table.clone();
+    match &mut session.phase {
+        FishingPhase::Waiting(timer) => {
+            if pressed {
+                end(&mut commands, "Too soon, nothing was biting.".to_string());
+            } else if timer.tick(time.delta()).finished() {
+                session.phase = FishingPhase::Biting(Timer::from_seconds(HOOK_WINDOW, false));
+            }
+        }
+        FishingPhase::Biting(timer) => {
+            if pressed {
+                let fish = match fish_tables
+                    .get(&fish_handle.0)
+                    .and_then(|tables| tables.0.get(&table))
+                    .and_then(|fish| pick_fish(fish, &mut rng.0))
+                {
+                    Some(fish) => fish,
+                    None => {
+                        warn!("No fish in table `{table}`");
+                        end(&mut commands, "Nothing lives in this water.".to_string());
+                        return;
+                    }
+                };
+                let width = (ZONE_WIDTH / fish.difficulty).clamp(0.05, 0.9);
+                let start = rng.0.gen_range(0. ..1. - width);
+                session.phase = FishingPhase::Reeling {
+                    fish: fish.item.clone(),
+                    marker: 0.,
+                    direction: 1.,
+                    speed: MARKER_SPEED * fish.difficulty,
+                    zone: (start, start + width),
+                };
+            } else if timer.tick(time.delta()).finished() {
+                end(&mut commands, "It got away before you could hook it.".to_string());
+            }
+        }
+        FishingPhase::Reeling {
+            fish,
+            marker,
+            direction,
+            speed,
+            zone,
+        } => {
+            if !pressed {
+                *marker += *direction * *speed * time.delta_seconds();
+                if !(0. ..=1.).contains(marker) {
+                    *marker = marker.clamp(0., 1.);
+                    *direction = -*direction;
+                }
+                return;
+            }
+            if *marker < zone.0 || *marker > zone.1 {
+                end(&mut commands, "The line snapped!".to_string());
+                return;
+            }
+            let name = items.get(fish.as_str()).map_or(fish.clone(), |def| def.name.clone());
+            let message = match player_query.single_mut().add(fish, 1, &items) {
+                Ok(()) => format!("You caught a {name}!"),
+                Err(err) => format!("You caught a {name}, but let it go. {err}"),
+            };
+            end(&mut commands, message);
+        }
+    }
+}
+
+/// How long the next cast waits for a bite, in seconds.
+fn bite_wait(rng: &mut StdRng) -> f32 {
+    rng.gen_range(BITE_TIME.0..BITE_TIME.1)
+}
+
+fn pick_fish<'a>(fish: &'a [FishEntry], rng: &mut StdRng) -> Option<&'a FishEntry> {
+    let weights = WeightedIndex::new(fish.iter().map(|fish| fish.weight)).ok()?;
+    fish.get(weights.sample(rng))
+}
+
+fn spawn_fishing_ui(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<GameConfig>,
+    props: Res<Props>,
+    named_atlases: Res<Assets<NamedAtlas>>,
+    texture_atlases: Res<Assets<TextureAtlas>>,
+    player_query: Query<(&Transform, &Motion), With<Player>>,
+) {
+    if let (Some((texture_atlas, index)), Ok((transform, motion))) =
+        (props.frame(&named_atlases, "rod"), player_query.get_single())
+    {
+        let size = texture_atlases
+            .get(&texture_atlas)
+            .map_or(Vec2::splat(config.tile_size), |atlas| prop_size(atlas, index, config.tile_size));
+        let facing = motion.facing.vector();
+        commands
+            .spawn_bundle(SpriteSheetBundle {
+                sprite: TextureAtlasSprite {
+                    index,
+                    custom_size: Some(size),
+                    flip_x: facing.x < 0.,
+                    ..default()
+                },
+                texture_atlas,
+                transform: Transform::from_translation(
+                    transform.translation + (facing * config.tile_size * 0.6).extend(0.5),
+                ),
+                ..default()
+            })
+            .insert(Name::new("Rod"))
+            .insert(Rod);
+    }
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::FlexStart,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .insert(Name::new("Fishing UI"))
+        .insert(FishingUi)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        margin: UiRect::all(Val::Percent(4.)),
+                        padding: UiRect::all(Val::Px(12.)),
+                        flex_direction: FlexDirection::ColumnReverse,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    color: Color::rgba(0.1, 0.08, 0.06, 0.9).into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                                font_size: 22.,
+                                color: Color::WHITE,
+                            },
+                        ))
+                        .insert(FishingText);
+                    parent
+                        .spawn_bundle(NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(300.), Val::Px(20.)),
+                                margin: UiRect::all(Val::Px(8.)),
+                                ..default()
+                            },
+                            color: Color::rgb(0.05, 0.2, 0.45).into(),
+                            visibility: Visibility { is_visible: false },
+                            ..default()
+                        })
+                        .insert(FishingBar)
+                        .with_children(|parent| {
+                            parent
+                                .spawn_bundle(NodeBundle {
+                                    style: Style {
+                                        position_type: PositionType::Absolute,
+                                        size: Size::new(Val::Percent(0.), Val::Percent(100.)),
+                                        ..default()
+                                    },
+                                    color: Color::rgb(0.3, 0.8, 0.4).into(),
+                                    ..default()
+                                })
+                                .insert(CatchZone);
+                            parent
+                                .spawn_bundle(NodeBundle {
+                                    style: Style {
+                                        position_type: PositionType::Absolute,
+                                        size: Size::new(Val::Px(4.), Val::Percent(100.)),
+                                        ..default()
+                                    },
+                                    color: Color::WHITE.into(),
+                                    ..default()
+                                })
+                                .insert(ReelMarker);
+                        });
+                });
+        });
+}
+
+#[allow(clippy::type_complexity)]
+fn update_fishing_ui(
+    session: Option<Res<FishingSession>>,
+    mut text_query: Query<&mut Text, With<FishingText>>,
+    mut bar_query: Query<&mut Visibility, With<FishingBar>>,
+    mut zone_query: Query<&mut Style, (With<CatchZone>, Without<ReelMarker>)>,
+    mut marker_query: Query<&mut Style, With<ReelMarker>>,
+) {
+    let session = match session {
+        Some(session) => session,
+        None => return,
+    };
+    for mut text in &mut text_query {
+        text.sections[0].value = match session.phase {
+            FishingPhase::Waiting(_) => "Waiting for a bite...",
+            FishingPhase::Biting(_) => "Something's biting! Press F!",
+            FishingPhase::Reeling { .. } => "Press F when the marker is in the green",
+        }
+        .to_string();
+    }
+    let reeling = match session.phase {
+        FishingPhase::Reeling { marker, zone, .. } => Some((marker, zone)),
+        _ => None,
+    };
+    for mut visibility in &mut bar_query {
+        visibility.is_visible = reeling.is_some();
+    }
+    if let Some((marker, (start, end))) = reeling {
+        for mut style in &mut zone_query {
+            style.position.left = Val::Percent(start * 100.);
+            style.size.width = Val::Percent((end - start) * 100.);
+        }
+        for mut style in &mut marker_query {
+            style.position.left = Val::Percent(marker * 100.);
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn despawn_fishing_ui(mut commands: Commands, query: Query<Entity, Or<(With<FishingUi>, With<Rod>)>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rng(fishing_seed: Option<u64>) -> FishingRng {
+        let mut world = World::new();
+        world.insert_resource(GameConfig {
+            fishing_seed,
+            ..default()
+        });
+        FishingRng::from_world(&mut world)
+    }
+
+    #[test]
+    fn the_same_seed_waits_as_long_for_every_bite() {
+        let (mut first, mut second) = (rng(Some(7)), rng(Some(7)));
+        for _ in 0..20 {
+            let wait = bite_wait(&mut first.0);
+            assert!((BITE_TIME.0..BITE_TIME.1).contains(&wait));
+            assert_eq!(wait, bite_wait(&mut second.0));
+        }
+    }
+
+    #[test]
+    fn other_seeds_wait_differently() {
+        let (mut first, mut second) = (rng(Some(7)), rng(Some(8)));
+        let waits = |rng: &mut FishingRng| (0..5).map(|_| bite_wait(&mut rng.0)).collect::<Vec<_>>();
+        assert_ne!(waits(&mut first), waits(&mut second));
+    }
+}
@@ -0,0 +1,111 @@
+//! The game's logic without a window or renderer, stepped frame by frame with
+//! injected input, for tests that run in CI.
+
+use std::time::{Duration, Instant};
+
+use bevy::{
+    asset::AssetPlugin,
+    hierarchy::HierarchyPlugin,
+    input::{keyboard::KeyboardInput, ButtonState, InputPlugin},
+    prelude::*,
+    time::TimePlugin,
+    transform::TransformPlugin,
+};
+
+use crate::{
+    combat::CombatPlugin,
+    player::{Player, PlayerPlugin},
+    sprites::{Characters, NamedAtlas, Props, Tiles},
+    tilemap::TileMapPlugin,
+    AppState, GameConfig,
+};
+
+/// How much time one simulated frame takes.
+pub const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// `MinimalPlugins` and asset loading with the player and tile map plugins,
+/// starting in game. No sprites are loaded, so everything is invisible but
+/// moves and collides as usual. Time only moves through `Simulation::step`.
+pub fn headless_app() -> App {
+    let mut app = App::new();
+    app.add_plugins_with(MinimalPlugins, |group| group.disable::<TimePlugin>())
+        .init_resource::<Time>()
+        .init_resource::<GameConfig>()
+        .add_plugin(TransformPlugin)
+        .add_plugin(HierarchyPlugin)
+        .add_plugin(InputPlugin)
+        .add_plugin(AssetPlugin)
+        .add_asset::<NamedAtlas>()
+        .init_resource::<Characters>()
+        .init_resource::<Tiles>()
+        .init_resource::<Props>()
+        .add_state(AppState::InGame)
+        .add_plugin(CombatPlugin)
+        .add_plugin(PlayerPlugin)
+        .add_plugin(TileMapPlugin);
+    app
+}
+
+/// A headless app and its clock.
+pub struct Simulation {
+    pub app: App,
+    now: Instant,
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Simulation::new()
+    }
+}
+
+impl Simulation {
+    /// A simulation past its first frame, with the map and player spawned.
+    pub fn new() -> Simulation {
+        let mut simulation = Simulation {
+            app: headless_app(),
+            now: Instant::now(),
+        };
+        simulation.step(1);
+        simulation
+    }
+
+    /// Holds `key` down from the next frame on.
+    pub fn press(&mut self, key: KeyCode) {
+        self.send_key(key, ButtonState::Pressed);
+    }
+
+    pub fn release(&mut self, key: KeyCode) {
+        self.send_key(key, ButtonState::Released);
+    }
+
+    fn send_key(&mut self, key: KeyCode, state: ButtonState) {
+        self.app.world.send_event(KeyboardInput {
+            scan_code: 0,
+            key_code: Some(key),
+            state,
+        });
+    }
+
+    /// Runs `frames` frames of `FRAME` each.
+    pub fn step(&mut self, frames: u32) {
+        for _ in 0..frames {
+            self.now += FRAME;
+            self.app.world.resource_mut::<Time>().update_with_instant(self.now);
+            self.app.update();
+        }
+    }
+
+    /// Runs as many frames as fit in `seconds`.
+    pub fn run_for(&mut self, seconds: f32) {
+        self.step((seconds / FRAME.as_secs_f32()).round() as u32);
+    }
+
+    pub fn player_position(&mut self) -> Vec2 {
+        self.app
+            .world
+            .query_filtered::<&Transform, With<Player>>()
+            .single(&self.app.world)
+            .translation
+            .truncate()
+    }
+}
@@ -0,0 +1,414 @@
+//! Widgets for the world inspector: a progress bar for timers, a tile
+//! coordinate editor for transforms and a frame picker for atlas sprites, plus
+//! `Fields` for laying out components the `Inspectable` derive can't handle.
+
+use std::time::Duration;
+
+use bevy::{asset::Asset, ecs::archetype::Archetype, prelude::*};
+use bevy_inspector_egui::{bevy_egui::EguiContext, egui, Context, Inspectable};
+
+use crate::{
+    sprites::{Characters, Mobs, NamedAtlas, Props, SpriteCollection, Tiles},
+    tilemap::MapData,
+};
+
+/// Side of the square frame thumbnails are fitted into, in points.
+const THUMBNAIL_SIZE: f32 = 48.;
+
+/// Longest duration a timer can be dragged to, in seconds.
+const MAX_TIMER_DURATION: f32 = 3600.;
+
+/// A grid of labelled rows, for `Inspectable` impls of components holding
+/// timers, assets or private state.
+pub struct Fields<'a, 'c> {
+    ui: &'a mut egui::Ui,
+    context: &'a mut Context<'c>,
+    row: u64,
+    changed: bool,
+}
+
+impl<'a, 'c> Fields<'a, 'c> {
+    /// Lays the rows added by `add` out in a grid and returns whether any of
+    /// them changed.
+    pub fn show(ui: &mut egui::Ui, context: &mut Context, add: impl FnOnce(&mut Fields)) -> bool {
+        egui::Grid::new(context.id())
+            .show(ui, |ui| {
+                let mut fields = Fields {
+                    ui,
+                    context,
+                    row: 0,
+                    changed: false,
+                };
+                add(&mut fields);
+                fields.changed
+            })
+            .inner
+    }
+
+    fn row(&mut self, label: &str, add: impl FnOnce(&mut egui::Ui, &mut Context) -> bool) -> &mut Self {
+        self.ui.label(label);
+        self.changed |= add(self.ui, &mut self.context.with_id(self.row));
+        self.ui.end_row();
+        self.row += 1;
+        self
+    }
+
+    pub fn edit<T: Inspectable>(&mut self, label: &str, value: &mut T) -> &mut Self {
+        self.row(label, |ui, context| value.ui(ui, default(), context))
+    }
+
+    pub fn timer(&mut self, label: &str, timer: &mut Timer) -> &mut Self {
+        self.row(label, |ui, _| timer_ui(timer, ui))
+    }
+
+    /// A value that can't be edited.
+    pub fn text(&mut self, label: &str, text: impl Into<egui::WidgetText>) -> &mut Self {
+        self.row(label, |ui, _| {
+            ui.label(text);
+            false
+        })
+    }
+
+    /// A value that can't be edited, shown with `Debug`.
+    pub fn show_debug(&mut self, label: &str, value: &impl std::fmt::Debug) -> &mut Self {
+        self.text(label, format!("{value:?}"))
+    }
+
+    /// The file an asset was loaded from.
+    pub fn asset<T: Asset>(&mut self, label: &str, handle: &Handle<T>) -> &mut Self {
+        self.row(label, |ui, context| {
+            let path = context
+                .world()
+                .and_then(|world| world.get_resource::<AssetServer>()?.get_handle_path(handle));
+            match path {
+                Some(path) => ui.label(path.path().display().to_string()),
+                None => ui.label("<not from a file>"),
+            };
+            false
+        })
+    }
+}
+
+/// How far along `timer` is, with its duration, mode and pause state editable.
+pub fn timer_ui(timer: &mut Timer, ui: &mut egui::Ui) -> bool {
+    let mut changed = false;
+    ui.vertical(|ui| {
+        let (elapsed, duration) = (timer.elapsed_secs(), timer.duration().as_secs_f32());
+        ui.add(egui::ProgressBar::new(timer.percent()).text(format!("{elapsed:.2} / {duration:.2} s")));
+        ui.horizontal(|ui| {
+            let mut duration = duration;
+            let drag = egui::DragValue::new(&mut duration)
+                .speed(0.01)
+                .clamp_range(0.0..=MAX_TIMER_DURATION)
+                .suffix(" s");
+            // The clamp still lets a typed in NaN through.
+            if ui.add(drag).changed() {
+                if let Ok(duration) = Duration::try_from_secs_f32(duration) {
+                    timer.set_duration(duration);
+                    changed = true;
+                }
+            }
+            let mut repeating = timer.repeating();
+            if ui.checkbox(&mut repeating, "repeating").changed() {
+                timer.set_repeating(repeating);
+                changed = true;
+            }
+            if ui.button(if timer.paused() { "Resume" } else { "Pause" }).clicked() {
+                if timer.paused() {
+                    timer.unpause();
+                } else {
+                    timer.pause();
+                }
+                changed = true;
+            }
+            if ui.button("Reset").clicked() {
+                timer.reset();
+                changed = true;
+            }
+        });
+    });
+    changed
+}
+
+/// The usual transform editor, plus the map tile the entity is on, which can
+/// be changed to move it there.
+pub fn transform_ui(transform: &mut Transform, ui: &mut egui::Ui, context: &mut Context) -> bool {
+    let mut changed = transform.ui(ui, (), context);
+    let map = match context.world().and_then(|world| world.get_resource::<MapData>()) {
+        Some(map) => map,
+        None => return changed,
+    };
+    ui.horizontal(|ui| {
+        ui.label("tile");
+        let (mut x, mut y) = match map.world_to_tile(transform.translation.truncate()) {
+            Some(tile) => tile,
+            None => {
+                ui.label("off the map");
+                return;
+            }
+        };
+        let x_changed = ui
+            .add(egui::DragValue::new(&mut x).prefix("x: ").clamp_range(0..=map.width() - 1))
+            .changed();
+        let y_changed = ui
+            .add(egui::DragValue::new(&mut y).prefix("y: ").clamp_range(0..=map.height() - 1))
+            .changed();
+        if x_changed || y_changed {
+            transform.translation = map.tile_to_world(x, y).extend(transform.translation.z);
+            changed = true;
+        }
+    });
+    changed
+}
+
+/// The sprite's fields, with its index picked from the frames of its atlas
+/// and the current frame shown.
+pub fn atlas_sprite_ui(sprite: &mut TextureAtlasSprite, ui: &mut egui::Ui, context: &mut Context) -> bool {
+    let atlas = context.world().and_then(|world| sprite_atlas(world, sprite));
+    let frames = match (&atlas, context.world()) {
+        (Some(atlas), Some(world)) => frame_names(world, atlas),
+        _ => Vec::new(),
+    };
+    let mut changed = false;
+    egui::Grid::new(context.id()).show(ui, |ui| {
+        ui.label("index");
+        ui.horizontal(|ui| {
+            // Without the atlas there is nothing to bound the index by.
+            let last = match frames.len() {
+                0 => usize::MAX,
+                len => len - 1,
+            };
+            if ui.button("<").clicked() && sprite.index > 0 {
+                sprite.index -= 1;
+                changed = true;
+            }
+            changed |= ui
+                .add(egui::DragValue::new(&mut sprite.index).clamp_range(0..=last))
+                .changed();
+            if ui.button(">").clicked() && sprite.index < last {
+                sprite.index += 1;
+                changed = true;
+            }
+            let selected = frames.get(sprite.index).map_or("", String::as_str);
+            egui::ComboBox::from_id_source(context.id().with("frame"))
+                .selected_text(selected)
+                .show_ui(ui, |ui| {
+                    for (index, name) in frames.iter().enumerate() {
+                        changed |= ui.selectable_value(&mut sprite.index, index, name).changed();
+                    }
+                });
+        });
+        ui.end_row();
+
+        if let Some(atlas) = &atlas {
+            ui.label("frame");
+            frame_thumbnail(ui, context, atlas, sprite.index);
+            ui.end_row();
+        }
+
+        ui.label("color");
+        changed |= sprite.color.ui(ui, default(), &mut context.with_id(0));
+        ui.end_row();
+        ui.label("flip_x");
+        changed |= sprite.flip_x.ui(ui, (), &mut context.with_id(1));
+        ui.end_row();
+        ui.label("flip_y");
+        changed |= sprite.flip_y.ui(ui, (), &mut context.with_id(2));
+        ui.end_row();
+    });
+    changed
+}
+
+/// The atlas of the entity `sprite` belongs to.
+fn sprite_atlas(world: &World, sprite: &TextureAtlasSprite) -> Option<Handle<TextureAtlas>> {
+    world.get::<Handle<TextureAtlas>>(component_entity(world, sprite)?).cloned()
+}
+
+/// The entity `component` belongs to. Inspector widgets only get the component,
+/// so the entity is worked out from where the component is stored.
+///
+/// This leans on how Bevy lays out tables: each component is a column of
+/// values in row order, next to the table's entities. Finding the column that
+/// holds the address is one check per table, cheap enough to run every frame.
+/// Components in sparse sets, or a layout that stops matching after a Bevy
+/// upgrade, fall back to comparing against every entity with the component.
+fn component_entity<T: Component>(world: &World, component: &T) -> Option<Entity> {
+    let id = world.component_id::<T>()?;
+    let address = component as *const T as usize;
+    let size = std::mem::size_of::<T>();
+    let in_table = world.storages().tables.iter().find_map(|table| {
+        let column = table.get_column(id)?;
+        let offset = address.checked_sub(column.get_data_ptr().as_ptr() as usize)?;
+        let row = offset.checked_div(size)?;
+        (offset % size == 0 && row < column.len()).then(|| table.entities()[row])
+    });
+    in_table.or_else(|| {
+        world
+            .archetypes()
+            .iter()
+            .filter(|archetype| archetype.contains(id))
+            .flat_map(Archetype::entities)
+            .copied()
+            .find(|&entity| world.get::<T>(entity).is_some_and(|stored| std::ptr::eq(stored, component)))
+    })
+}
+
+/// Names of the frames of `atlas` by index, or just the indices for atlases
+/// without names.
+fn frame_names(world: &World, atlas: &Handle<TextureAtlas>) -> Vec<String> {
+    let count = world
+        .resource::<Assets<TextureAtlas>>()
+        .get(atlas)
+        .map_or(0, TextureAtlas::len);
+    let mut names: Vec<_> = (0..count).map(|index| index.to_string()).collect();
+    let named_atlas = world
+        .resource::<Assets<NamedAtlas>>()
+        .iter()
+        .find(|(_, named_atlas)| named_atlas.atlas == *atlas);
+    if let Some((_, named_atlas)) = named_atlas {
+        for (name, &index) in &named_atlas.frames {
+            if let Some(label) = names.get_mut(index) {
+                *label = format!("{index}: {name}");
+            }
+        }
+    }
+    names
+}
+
+/// Draws frame `index` of `atlas`, fitted into a `THUMBNAIL_SIZE` square.
+fn frame_thumbnail(ui: &mut egui::Ui, context: &mut Context, atlas: &Handle<TextureAtlas>, index: usize) {
+    context.resource_scope(ui, "frame thumbnail", |ui, context, mut egui_context: Mut<EguiContext>| {
+        let atlas = context
+            .world()
+            .and_then(|world| world.get_resource::<Assets<TextureAtlas>>()?.get(atlas));
+        let (atlas, rect) = match atlas.and_then(|atlas| Some((atlas, atlas.textures.get(index)?))) {
+            Some(frame) => frame,
+            None => {
+                ui.label("<no frame>");
+                return false;
+            }
+        };
+        let texture = egui_context.add_image(atlas.texture.clone_weak());
+        let (min, max) = (rect.min / atlas.size, rect.max / atlas.size);
+        let size = rect.size() * THUMBNAIL_SIZE / rect.width().max(rect.height());
+        ui.add(
+            egui::Image::new(texture, [size.x, size.y])
+                .uv(egui::Rect::from_min_max(egui::pos2(min.x, min.y), egui::pos2(max.x, max.y))),
+        );
+        false
+    });
+}
+
+/// Every atlas with its frames and every single image of the collection, as
+/// thumbnails. Read only.
+impl Inspectable for SpriteCollection {
+    type Attributes = ();
+
+    fn ui(&mut self, ui: &mut egui::Ui, _: Self::Attributes, context: &mut Context) -> bool {
+        for (name, handle) in &self.atlases {
+            let named_atlas = context
+                .world()
+                .and_then(|world| world.get_resource::<Assets<NamedAtlas>>()?.get(handle));
+            let named_atlas = match named_atlas {
+                Some(named_atlas) => named_atlas,
+                None => {
+                    ui.label(format!("{name} (not loaded)"));
+                    continue;
+                }
+            };
+            let mut frames: Vec<_> = named_atlas.frames.iter().collect();
+            frames.sort_by_key(|(_, &index)| index);
+            egui::CollapsingHeader::new(format!("{name} ({} frames)", frames.len()))
+                .id_source(context.id().with(name))
+                .show(ui, |ui| {
+                    egui::Grid::new(context.id().with(name).with("frames")).show(ui, |ui| {
+                        for (frame, &index) in frames {
+                            frame_thumbnail(ui, context, &named_atlas.atlas, index);
+                            ui.label(format!("{index}: {frame}"));
+                            ui.end_row();
+                        }
+                    });
+                });
+        }
+
+        if self.images.is_empty() {
+            return false;
+        }
+        let mut images: Vec<_> = self.images.iter().collect();
+        images.sort_by_key(|(name, _)| name.as_str());
+        egui::CollapsingHeader::new(format!("images ({})", images.len()))
+            .id_source(context.id().with("images"))
+            .show(ui, |ui| {
+                egui::Grid::new(context.id().with("image grid")).show(ui, |ui| {
+                    for (i, (name, handle)) in images.into_iter().enumerate() {
+                        handle.clone().ui(ui, default(), &mut context.with_id(i as u64));
+                        ui.label(name);
+                        ui.end_row();
+                    }
+                });
+            });
+        false
+    }
+}
+
+macro_rules! inspect_collection {
+    ($($collection:ty),*) => {
+        $(
+            impl Inspectable for $collection {
+                type Attributes = ();
+
+                fn ui(&mut self, ui: &mut egui::Ui, _: Self::Attributes, context: &mut Context) -> bool {
+                    self.0.ui(ui, (), context)
+                }
+            }
+        )*
+    };
+}
+
+inspect_collection!(Characters, Mobs, Tiles, Props);
+
+#[cfg(test)]
+mod tests {
+    use bevy::asset::HandleId;
+
+    use super::*;
+
+    #[test]
+    fn sprite_atlas_finds_the_sprites_entity() {
+        let mut world = World::new();
+        let atlases: Vec<Handle<TextureAtlas>> = (0..3).map(|_| Handle::weak(HandleId::random::<TextureAtlas>())).collect();
+        let entities: Vec<Entity> = atlases
+            .iter()
+            .map(|atlas| world.spawn().insert(TextureAtlasSprite::default()).insert(atlas.clone()).id())
+            .collect();
+        // A sprite in another table, without an atlas.
+        let lone = world.spawn().insert(TextureAtlasSprite::default()).insert(Name::new("lone")).id();
+
+        for (entity, atlas) in entities.iter().zip(&atlases) {
+            let sprite = world.get::<TextureAtlasSprite>(*entity).unwrap();
+            assert_eq!(sprite_atlas(&world, sprite).as_ref(), Some(atlas));
+        }
+        let sprite = world.get::<TextureAtlasSprite>(lone).unwrap();
+        assert_eq!(sprite_atlas(&world, sprite), None);
+        assert_eq!(sprite_atlas(&world, &TextureAtlasSprite::default()), None);
+    }
+
+    #[derive(Component)]
+    #[component(storage = "SparseSet")]
+    struct Sparse(u32);
+
+    #[test]
+    fn component_entity_finds_sparse_set_components() {
+        let mut world = World::new();
+        let entities: Vec<Entity> = (0..3).map(|value| world.spawn().insert(Sparse(value)).id()).collect();
+        // Sparse-set components can sit in archetypes with table components.
+        let mixed = world.spawn().insert(Sparse(3)).insert(Name::new("mixed")).id();
+
+        for (value, &entity) in entities.iter().chain([&mixed]).enumerate() {
+            let sparse = world.get::<Sparse>(entity).unwrap();
+            assert_eq!(sparse.0, value as u32);
+            assert_eq!(component_entity(&world, sparse), Some(entity));
+        }
+        assert_eq!(component_entity(&world, &Sparse(0)), None);
+    }
+}
@@ -0,0 +1,236 @@
+use bevy::prelude::*;
+use bevy_inspector_egui::Inspectable;
+
+use crate::{character::Motion, player::Player, AppState, GameConfig};
+
+pub struct InteractPlugin;
+
+impl Plugin for InteractPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<OpenChest>()
+            .add_event::<ReadSign>()
+            .add_event::<TalkTo>()
+            .add_event::<PickUp>()
+            .init_resource::<InteractionTarget>()
+            .add_system_set(SystemSet::on_enter(AppState::InGame).with_system(spawn_prompt))
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(
+                        find_interaction_target
+                            .label("find_interaction_target")
+                            .after("player_movement"),
+                    )
+                    .with_system(interact.label("interact").after("find_interaction_target"))
+                    .with_system(update_prompt.after("find_interaction_target")),
+            )
+            .add_system_set(SystemSet::on_pause(AppState::InGame).with_system(hide_prompt));
+    }
+}
+
+/// The Interact action.
+pub const INTERACT_KEY: KeyCode = KeyCode::E;
+
+/// Smallest cosine between the player's facing and the direction of a target
+/// that has to be faced, i.e. it must be within 60 degrees of straight ahead.
+const FACING_THRESHOLD: f32 = 0.5;
+
+const PROMPT_PATH: &str = "textures/rpg/ui/generic-rpg-ui-inventario03.png";
+
+/// Something the player can interact with by pressing Interact near it.
+#[derive(Component, Debug, Clone, Inspectable)]
+pub struct Interactable {
+    pub kind: InteractionKind,
+    /// How far away the player can be, in tiles.
+    pub radius: f32,
+    /// Whether the player has to face it.
+    pub needs_facing: bool,
+}
+
+impl Interactable {
+    pub fn new(kind: InteractionKind) -> Interactable {
+        Interactable {
+            kind,
+            radius: 1.5,
+            needs_facing: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Inspectable)]
+pub enum InteractionKind {
+    OpenChest,
+    ReadSign,
+    TalkTo,
+    PickUp,
+}
+
+impl InteractionKind {
+    fn verb(self) -> &'static str {
+        match self {
+            InteractionKind::OpenChest => "Open",
+            InteractionKind::ReadSign => "Read",
+            InteractionKind::TalkTo => "Talk to",
+            InteractionKind::PickUp => "Pick up",
+        }
+    }
+}
+
+/// The player interacted with the chest entity.
+pub struct OpenChest(pub Entity);
+
+/// The player interacted with the sign entity.
+pub struct ReadSign(pub Entity);
+
+/// The player interacted with the character entity.
+pub struct TalkTo(pub Entity);
+
+/// The player interacted with the item entity lying on the ground.
+pub struct PickUp(pub Entity);
+
+/// What pressing Interact would interact with right now.
+#[derive(Debug, Default)]
+pub struct InteractionTarget(pub Option<Entity>);
+
+#[derive(Component)]
+struct InteractionPrompt;
+
+#[derive(Component)]
+struct PromptLabel;
+
+/// Picks the closest interactable in reach, preferring whatever the player is
+/// facing.
+fn find_interaction_target(
+    mut target: ResMut<InteractionTarget>,
+    config: Res<GameConfig>,
+    player_query: Query<(&Transform, &Motion), With<Player>>,
+    query: Query<(Entity, &GlobalTransform, &Interactable)>,
+) {
+    let (player_transform, motion) = match player_query.get_single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let position = player_transform.translation.truncate();
+    let facing = motion.facing.vector();
+
+    target.0 = query
+        .iter()
+        .filter_map(|(entity, transform, interactable)| {
+            let offset = transform.translation().truncate() - position;
+            let distance = offset.length();
+            if distance > interactable.radius * config.tile_size {
+                return None;
+            }
+            let alignment = if distance > 0. {
+                offset.dot(facing) / distance
+            } else {
+                1.
+            };
+            if interactable.needs_facing && alignment < FACING_THRESHOLD {
+                return None;
+            }
+            Some((entity, distance - alignment * config.tile_size))
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(entity, _)| entity);
+}
+
+fn interact(
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    target: Res<InteractionTarget>,
+    query: Query<&Interactable>,
+    mut open_chest: EventWriter<OpenChest>,
+    mut read_sign: EventWriter<ReadSign>,
+    mut talk_to: EventWriter<TalkTo>,
+    mut pick_up: EventWriter<PickUp>,
+) {
+    if !keyboard_input.just_pressed(INTERACT_KEY) {
+        return;
+    }
+    let (entity, interactable) = match target.0.and_then(|entity| Some((entity, query.get(entity).ok()?))) {
+        Some(target) => target,
+        None => return,
+    };
+    keyboard_input.reset(INTERACT_KEY);
+    match interactable.kind {
+        InteractionKind::OpenChest => open_chest.send(OpenChest(entity)),
+        InteractionKind::ReadSign => read_sign.send(ReadSign(entity)),
+        InteractionKind::TalkTo => talk_to.send(TalkTo(entity)),
+        InteractionKind::PickUp => pick_up.send(PickUp(entity)),
+    }
+}
+
+fn spawn_prompt(mut commands: Commands, asset_server: Res<AssetServer>, config: Res<GameConfig>) {
+    commands
+        .spawn_bundle(SpriteBundle {
+            sprite: Sprite {
+                custom_size: Some(Vec2::new(7., 6.) / 16. * config.tile_size),
+                ..default()
+            },
+            texture: asset_server.load(PROMPT_PATH),
+            visibility: Visibility { is_visible: false },
+            ..default()
+        })
+        .insert(Name::new("Interaction Prompt"))
+        .insert(InteractionPrompt);
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    left: Val::Px(16.),
+                    bottom: Val::Px(16.),
+                    ..default()
+                },
+                ..default()
+            },
+            visibility: Visibility { is_visible: false },
+            ..TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 22.,
+                    color: Color::WHITE,
+                },
+            )
+        })
+        .insert(Name::new("Interaction Label"))
+        .insert(PromptLabel);
+}
+
+#[allow(clippy::type_complexity)]
+fn update_prompt(
+    target: Res<InteractionTarget>,
+    config: Res<GameConfig>,
+    target_query: Query<(&GlobalTransform, &Interactable, Option<&Name>)>,
+    mut prompt_query: Query<(&mut Transform, &mut Visibility), With<InteractionPrompt>>,
+    mut label_query: Query<(&mut Text, &mut Visibility), (With<PromptLabel>, Without<InteractionPrompt>)>,
+) {
+    let target = target.0.and_then(|entity| target_query.get(entity).ok());
+    for (mut transform, mut visibility) in &mut prompt_query {
+        visibility.is_visible = target.is_some();
+        if let Some((target_transform, ..)) = target {
+            transform.translation = target_transform.translation() + Vec3::new(0., config.tile_size * 0.8, 5.);
+        }
+    }
+    for (mut text, mut visibility) in &mut label_query {
+        visibility.is_visible = target.is_some();
+        if let Some((_, interactable, name)) = target {
+            let verb = interactable.kind.verb();
+            text.sections[0].value = match name {
+                Some(name) => format!("[E] {verb} {name}"),
+                None => format!("[E] {verb}"),
+            };
+        }
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn hide_prompt(
+    mut target: ResMut<InteractionTarget>,
+    mut query: Query<&mut Visibility, Or<(With<InteractionPrompt>, With<PromptLabel>)>>,
+) {
+    target.0 = None;
+    for mut visibility in &mut query {
+        visibility.is_visible = false;
+    }
+}
@@ -0,0 +1,835 @@
+use std::fmt;
+
+use bevy::prelude::*;
+use bevy_inspector_egui::{egui, Context, Inspectable};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    inspector::Fields,
+    interact::PickUp,
+    items::{ItemIcons, ItemPickup, ItemUsed, Items},
+    player::Player,
+    AppState,
+};
+
+pub struct InventoryPlugin;
+
+impl Plugin for InventoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<InventoryEvent>()
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(open_inventory.after("player_movement"))
+                    .with_system(pick_up_items.after("interact")),
+            )
+            .add_system_set(
+                SystemSet::on_enter(AppState::Inventory).with_system(spawn_inventory_screen),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Inventory)
+                    .with_system(inventory_input.label("inventory_input"))
+                    .with_system(update_inventory_screen.after("inventory_input")),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Inventory).with_system(despawn_inventory_screen),
+            )
+            .add_system_to_stage(CoreStage::PostUpdate, send_inventory_events)
+            .add_system(spawn_item_toasts)
+            .add_system(fade_item_toasts);
+    }
+}
+
+/// Opens and closes the inventory screen.
+pub const INVENTORY_KEY: KeyCode = KeyCode::I;
+
+pub const INVENTORY_SLOTS: usize = 12;
+const INVENTORY_COLUMNS: usize = 4;
+
+const SLOT_PATH: &str = "textures/rpg/ui/generic-rpg-ui-inventario02.png";
+const SELECTED_SLOT_PATH: &str = "textures/rpg/ui/generic-rpg-ui-inventario01.png";
+/// How many screen pixels each pixel of the UI textures takes up.
+const UI_SCALE: f32 = 4.;
+/// How long notes like "+1 Sword" stay on screen, in seconds.
+const TOAST_DURATION: f32 = 2.;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemStack {
+    pub item: String,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InventoryChange {
+    Added { item: String, count: u32 },
+    Removed { item: String, count: u32 },
+    Moved { from: usize, to: usize },
+    Split { from: usize, to: usize, count: u32 },
+}
+
+/// Sent for every change made to an inventory.
+#[derive(Debug, Clone)]
+pub struct InventoryEvent {
+    pub entity: Entity,
+    pub change: InventoryChange,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InventoryError {
+    UnknownItem,
+    Full,
+    NotEnough,
+    EmptySlot,
+    NoSuchSlot,
+}
+
+impl fmt::Display for InventoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            InventoryError::UnknownItem => "There's no such item.",
+            InventoryError::Full => "There's no room for that.",
+            InventoryError::NotEnough => "You don't have enough.",
+            InventoryError::EmptySlot => "That slot is empty.",
+            InventoryError::NoSuchSlot => "There's no such slot.",
+        })
+    }
+}
+
+/// Slot based item storage. Operations either go through completely or leave
+/// the inventory untouched, and every change is reported as an
+/// `InventoryEvent` at the end of the frame.
+#[derive(Component, Debug)]
+pub struct Inventory {
+    slots: Vec<Option<ItemStack>>,
+    changes: Vec<InventoryChange>,
+}
+
+/// Read only: changes have to go through the methods to be reported.
+impl Inspectable for Inventory {
+    type Attributes = ();
+
+    fn ui(&mut self, ui: &mut egui::Ui, _: Self::Attributes, context: &mut Context) -> bool {
+        Fields::show(ui, context, |fields| {
+            for (slot, stack) in self.slots.iter().enumerate() {
+                let label = format!("slot {slot}");
+                match stack {
+                    Some(stack) => fields.text(&label, format!("{} x{}", stack.item, stack.count)),
+                    None => fields.text(&label, "empty"),
+                };
+            }
+        })
+    }
+}
+
+impl Inventory {
+    pub fn new(size: usize) -> Inventory {
+        Inventory {
+            slots: vec![None; size],
+            changes: Vec::new(),
+        }
+    }
+
+    /// An inventory holding exactly `slots`, e.g. from a save.
+    pub fn from_slots(slots: Vec<Option<ItemStack>>) -> Inventory {
+        Inventory {
+            slots,
+            changes: Vec::new(),
+        }
+    }
+
+    pub fn slots(&self) -> &[Option<ItemStack>] {
+        &self.slots
+    }
+
+    pub fn get(&self, slot: usize) -> Option<&ItemStack> {
+        self.slots.get(slot)?.as_ref()
+    }
+
+    /// How many of `item` the inventory holds over all slots.
+    pub fn count(&self, item: &str) -> u32 {
+        self.slots
+            .iter()
+            .flatten()
+            .filter(|stack| stack.item == item)
+            .map(|stack| stack.count)
+            .sum()
+    }
+
+    /// How many more of `item` would fit.
+    pub fn room_for(&self, item: &str, items: &Items) -> u32 {
+        let stack_size = match items.get(item) {
+            Some(def) => def.stack_size,
+            None => return 0,
+        };
+        self.slots
+            .iter()
+            .map(|slot| match slot {
+                None => stack_size,
+                Some(stack) if stack.item == item => stack_size.saturating_sub(stack.count),
+                Some(_) => 0,
+            })
+            .fold(0, u32::saturating_add)
+    }
+
+    /// Adds `count` of `item`, topping up existing stacks before starting new ones.
+    pub fn add(&mut self, item: &str, count: u32, items: &Items) -> Result<(), InventoryError> {
+        let stack_size = items.get(item).ok_or(InventoryError::UnknownItem)?.stack_size;
+        if self.room_for(item, items) < count {
+            return Err(InventoryError::Full);
+        }
+        let mut left = count;
+        for stack in self.slots.iter_mut().flatten() {
+            if stack.item == item && left > 0 {
+                let added = left.min(stack_size.saturating_sub(stack.count));
+                stack.count += added;
+                left -= added;
+            }
+        }
+        for slot in &mut self.slots {
+            if slot.is_none() && left > 0 {
+                let added = left.min(stack_size);
+                *slot = Some(ItemStack {
+                    item: item.to_string(),
+                    count: added,
+                });
+                left -= added;
+            }
+        }
+        self.changes.push(InventoryChange::Added {
+            item: item.to_string(),
+            count,
+        });
+        Ok(())
+    }
+
+    /// Adds every `(item, count)` pair, or none of them if they don't all fit.
+    pub fn add_all<'a>(
+        &mut self,
+        stacks: impl IntoIterator<Item = (&'a str, u32)>,
+        items: &Items,
+    ) -> Result<(), InventoryError> {
+        let mut trial = Inventory {
+            slots: self.slots.clone(),
+            changes: Vec::new(),
+        };
+        for (item, count) in stacks {
+            trial.add(item, count, items)?;
+        }
+        self.slots = trial.slots;
+        self.changes.append(&mut trial.changes);
+        Ok(())
+    }
+
+    /// Removes `count` of `item`, emptying the last stacks first.
+    pub fn remove(&mut self, item: &str, count: u32) -> Result<(), InventoryError> {
+        if self.count(item) < count {
+            return Err(InventoryError::NotEnough);
+        }
+        let mut left = count;
+        for slot in self.slots.iter_mut().rev() {
+            if let Some(stack) = slot {
+                if stack.item == item && left > 0 {
+                    let removed = left.min(stack.count);
+                    stack.count -= removed;
+                    left -= removed;
+                    if stack.count == 0 {
+                        *slot = None;
+                    }
+                }
+            }
+        }
+        self.changes.push(InventoryChange::Removed {
+            item: item.to_string(),
+            count,
+        });
+        Ok(())
+    }
+
+    /// Moves the stack in `from` onto `to`, merging stacks of the same item as
+    /// far as they go and swapping otherwise.
+    pub fn move_stack(&mut self, from: usize, to: usize, items: &Items) -> Result<(), InventoryError> {
+        if from >= self.slots.len() || to >= self.slots.len() {
+            return Err(InventoryError::NoSuchSlot);
+        }
+        if self.slots[from].is_none() {
+            return Err(InventoryError::EmptySlot);
+        }
+        if from == to {
+            return Ok(());
+        }
+        match (&self.slots[from], &self.slots[to]) {
+            (Some(source), Some(target)) if source.item == target.item => {
+                let stack_size = items.get(&source.item).map_or(1, |def| def.stack_size);
+                let moved = source.count.min(stack_size.saturating_sub(target.count));
+                let left = source.count - moved;
+                self.slots[to].as_mut().unwrap().count += moved;
+                match left {
+                    0 => self.slots[from] = None,
+                    _ => self.slots[from].as_mut().unwrap().count = left,
+                }
+            }
+            _ => self.slots.swap(from, to),
+        }
+        self.changes.push(InventoryChange::Moved { from, to });
+        Ok(())
+    }
+
+    /// Moves `count` items off the stack in `from` into the first empty slot,
+    /// returning that slot.
+    pub fn split(&mut self, from: usize, count: u32) -> Result<usize, InventoryError> {
+        let stack = self
+            .slots
+            .get(from)
+            .ok_or(InventoryError::NoSuchSlot)?
+            .as_ref()
+            .ok_or(InventoryError::EmptySlot)?;
+        if count == 0 || count >= stack.count {
+            return Err(InventoryError::NotEnough);
+        }
+        let to = self
+            .slots
+            .iter()
+            .position(Option::is_none)
+            .ok_or(InventoryError::Full)?;
+        let item = stack.item.clone();
+        self.slots[from].as_mut().unwrap().count -= count;
+        self.slots[to] = Some(ItemStack { item, count });
+        self.changes.push(InventoryChange::Split { from, to, count });
+        Ok(to)
+    }
+}
+
+/// Cursor and message of the open inventory screen.
+struct InventoryScreenState {
+    selected: usize,
+    /// Slot picked up to be moved, if any.
+    held: Option<usize>,
+    message: String,
+}
+
+struct InventoryTextures {
+    slot: Handle<Image>,
+    selected_slot: Handle<Image>,
+}
+
+#[derive(Component)]
+struct InventoryScreen;
+
+#[derive(Component)]
+struct SlotNode(usize);
+
+#[derive(Component)]
+struct SlotIcon(usize);
+
+#[derive(Component)]
+struct SlotCount(usize);
+
+#[derive(Component)]
+struct ItemDetails;
+
+#[derive(Component, Deref, DerefMut)]
+struct ItemToast(Timer);
+
+fn send_inventory_events(
+    mut events: EventWriter<InventoryEvent>,
+    mut query: Query<(Entity, &mut Inventory), Changed<Inventory>>,
+) {
+    for (entity, mut inventory) in &mut query {
+        if inventory.changes.is_empty() {
+            continue;
+        }
+        for change in inventory.changes.drain(..) {
+            events.send(InventoryEvent { entity, change });
+        }
+    }
+}
+
+fn open_inventory(
+    mut commands: Commands,
+    mut state: ResMut<State<AppState>>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+) {
+    if keyboard_input.just_pressed(INVENTORY_KEY) {
+        keyboard_input.reset(INVENTORY_KEY);
+        commands.insert_resource(InventoryScreenState {
+            selected: 0,
+            held: None,
+            message: String::new(),
+        });
+        state.push(AppState::Inventory).unwrap();
+    }
+}
+
+fn inventory_input(
+    mut commands: Commands,
+    mut state: ResMut<State<AppState>>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    items: Res<Items>,
+    mut screen: ResMut<InventoryScreenState>,
+    mut used_events: EventWriter<ItemUsed>,
+    mut query: Query<&mut Inventory, With<Player>>,
+) {
+    for key in [INVENTORY_KEY, KeyCode::Escape] {
+        if keyboard_input.just_pressed(key) {
+            keyboard_input.reset(key);
+            commands.remove_resource::<InventoryScreenState>();
+            state.pop().unwrap();
+            return;
+        }
+    }
+    let mut inventory = query.single_mut();
+    let slots = inventory.slots.len();
+    let selected = screen.selected;
+
+    if keyboard_input.just_pressed(KeyCode::A) && !selected.is_multiple_of(INVENTORY_COLUMNS) {
+        screen.selected -= 1;
+    }
+    if keyboard_input.just_pressed(KeyCode::D)
+        && selected % INVENTORY_COLUMNS < INVENTORY_COLUMNS - 1
+        && selected + 1 < slots
+    {
+        screen.selected += 1;
+    }
+    if keyboard_input.just_pressed(KeyCode::W) && selected >= INVENTORY_COLUMNS {
+        screen.selected -= INVENTORY_COLUMNS;
+    }
+    if keyboard_input.just_pressed(KeyCode::S) && selected + INVENTORY_COLUMNS < slots {
+        screen.selected += INVENTORY_COLUMNS;
+    }
+
+    let selected = screen.selected;
+    if keyboard_input.just_pressed(KeyCode::Space) {
+        match screen.held.take() {
+            Some(from) => {
+                if let Err(err) = inventory.move_stack(from, selected, &items) {
+                    screen.message = err.to_string();
+                }
+            }
+            None if inventory.get(selected).is_some() => screen.held = Some(selected),
+            None => {}
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::X) {
+        let half = inventory.get(selected).map_or(0, |stack| stack.count / 2);
+        screen.message = match inventory.split(selected, half) {
+            Ok(_) => String::new(),
+            Err(err) => err.to_string(),
+        };
+    }
+    if keyboard_input.just_pressed(KeyCode::E) {
+        keyboard_input.reset(KeyCode::E);
+        let def = match inventory.get(selected).and_then(|stack| items.get(&stack.item)) {
+            Some(def) => def,
+            None => return,
+        };
+        screen.message = match &def.effect {
+            Some(effect) => match inventory.remove(&def.id, 1) {
+                Ok(()) => {
+                    used_events.send(ItemUsed(effect.clone()));
+                    format!("Used {}.", def.name)
+                }
+                Err(err) => err.to_string(),
+            },
+            None => format!("You can't use {}.", def.name),
+        };
+    }
+}
+
+fn spawn_inventory_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    query: Query<&Inventory, With<Player>>,
+) {
+    let textures = InventoryTextures {
+        slot: asset_server.load(SLOT_PATH),
+        selected_slot: asset_server.load(SELECTED_SLOT_PATH),
+    };
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let style = |font_size, color| TextStyle {
+        font: font.clone(),
+        font_size,
+        color,
+    };
+    let slots = query.get_single().map_or(0, |inventory| inventory.slots.len());
+
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .insert(Name::new("Inventory Screen"))
+        .insert(InventoryScreen)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        padding: UiRect::all(Val::Px(16.)),
+                        flex_direction: FlexDirection::ColumnReverse,
+                        align_items: AlignItems::Center,
+                        ..default()
+                    },
+                    color: Color::rgb_u8(0x7a, 0x44, 0x41).into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn_bundle(TextBundle::from_section(
+                        "Items",
+                        style(28., Color::rgb_u8(0xe8, 0xb7, 0x96)),
+                    ));
+                    for row in 0..slots.div_ceil(INVENTORY_COLUMNS) {
+                        parent
+                            .spawn_bundle(NodeBundle {
+                                color: Color::NONE.into(),
+                                ..default()
+                            })
+                            .with_children(|parent| {
+                                let first = row * INVENTORY_COLUMNS;
+                                for slot in first..slots.min(first + INVENTORY_COLUMNS) {
+                                    spawn_slot(parent, slot, &textures, style(18., Color::WHITE));
+                                }
+                            });
+                    }
+                    parent
+                        .spawn_bundle(TextBundle {
+                            style: Style {
+                                margin: UiRect::new(
+                                    Val::Px(0.),
+                                    Val::Px(0.),
+                                    Val::Px(12.),
+                                    Val::Px(0.),
+                                ),
+                                ..default()
+                            },
+                            ..TextBundle::from_section("", style(18., Color::WHITE))
+                        })
+                        .insert(ItemDetails);
+                });
+        });
+    commands.insert_resource(textures);
+}
+
+fn spawn_slot(
+    parent: &mut ChildBuilder,
+    slot: usize,
+    textures: &InventoryTextures,
+    count_style: TextStyle,
+) {
+    parent
+        .spawn_bundle(ImageBundle {
+            style: Style {
+                size: Size::new(Val::Px(14. * UI_SCALE), Val::Px(15. * UI_SCALE)),
+                margin: UiRect::all(Val::Px(4.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            image: textures.slot.clone().into(),
+            ..default()
+        })
+        .insert(SlotNode(slot))
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(ImageBundle {
+                    color: Color::NONE.into(),
+                    ..default()
+                })
+                .insert(SlotIcon(slot));
+            parent
+                .spawn_bundle(TextBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        position: UiRect {
+                            right: Val::Px(4.),
+                            bottom: Val::Px(2.),
+                            ..default()
+                        },
+                        ..default()
+                    },
+                    ..TextBundle::from_section("", count_style)
+                })
+                .insert(SlotCount(slot));
+        });
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn update_inventory_screen(
+    screen: Option<Res<InventoryScreenState>>,
+    textures: Res<InventoryTextures>,
+    items: Res<Items>,
+    icons: Res<ItemIcons>,
+    images: Res<Assets<Image>>,
+    query: Query<&Inventory, With<Player>>,
+    mut slot_query: Query<(&SlotNode, &mut UiImage), (Without<SlotIcon>, Without<SlotCount>)>,
+    mut icon_query: Query<(&SlotIcon, &mut UiImage, &mut UiColor, &mut Style)>,
+    mut text_query: Query<(Option<&SlotCount>, &mut Text), Or<(With<SlotCount>, With<ItemDetails>)>>,
+) {
+    let screen = match screen {
+        Some(screen) => screen,
+        None => return,
+    };
+    let inventory = query.single();
+
+    for (slot, mut image) in &mut slot_query {
+        image.0 = if slot.0 == screen.selected || Some(slot.0) == screen.held {
+            textures.selected_slot.clone()
+        } else {
+            textures.slot.clone()
+        };
+    }
+    for (slot, mut image, mut color, mut style) in &mut icon_query {
+        let icon = inventory
+            .get(slot.0)
+            .and_then(|stack| icons.get(&stack.item));
+        match icon {
+            Some(icon) => {
+                let size = images.get(icon).map_or(Vec2::ZERO, Image::size) * UI_SCALE;
+                style.size = Size::new(Val::Px(size.x), Val::Px(size.y));
+                image.0 = icon.clone();
+                *color = Color::WHITE.into();
+            }
+            None => *color = Color::NONE.into(),
+        }
+    }
+    for (count, mut text) in &mut text_query {
+        text.sections[0].value = match count {
+            Some(SlotCount(slot)) => match inventory.get(*slot) {
+                Some(stack) if stack.count > 1 => stack.count.to_string(),
+                _ => String::new(),
+            },
+            None => {
+                let details = match inventory
+                    .get(screen.selected)
+                    .and_then(|stack| items.get(&stack.item))
+                {
+                    Some(def) => format!("{} ({:?})", def.name, def.category),
+                    None => String::new(),
+                };
+                format!(
+                    "{details}\n{}\nWASD select  Space move  X split  E use  I close",
+                    screen.message
+                )
+            }
+        };
+    }
+}
+
+fn despawn_inventory_screen(mut commands: Commands, query: Query<Entity, With<InventoryScreen>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn pick_up_items(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    items: Res<Items>,
+    mut events: EventReader<PickUp>,
+    pickup_query: Query<&ItemPickup>,
+    mut player_query: Query<&mut Inventory, With<Player>>,
+    toast_query: Query<&ItemToast>,
+) {
+    let mut inventory = player_query.single_mut();
+    for event in events.iter() {
+        let pickup = match pickup_query.get(event.0) {
+            Ok(pickup) => pickup,
+            Err(_) => continue,
+        };
+        match inventory.add(&pickup.item, pickup.count, &items) {
+            Ok(()) => commands.entity(event.0).despawn_recursive(),
+            Err(err) => spawn_toast(
+                &mut commands,
+                &asset_server,
+                err.to_string(),
+                toast_query.iter().count(),
+            ),
+        }
+    }
+}
+
+/// Notes items the player receives in the bottom right corner.
+fn spawn_item_toasts(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    items: Res<Items>,
+    mut events: EventReader<InventoryEvent>,
+    player_query: Query<Entity, With<Player>>,
+    toast_query: Query<&ItemToast>,
+) {
+    let mut shown = toast_query.iter().count();
+    for event in events.iter() {
+        let (item, count) = match &event.change {
+            InventoryChange::Added { item, count } => (item, count),
+            _ => continue,
+        };
+        if !player_query.contains(event.entity) {
+            continue;
+        }
+        let name = items.get(item).map_or(item, |def| &def.name);
+        spawn_toast(&mut commands, &asset_server, format!("+{count} {name}"), shown);
+        shown += 1;
+    }
+}
+
+/// Shows `text` in the bottom right corner for a moment, `index` lines up
+/// from the bottom.
+pub fn spawn_toast(commands: &mut Commands, asset_server: &AssetServer, text: String, index: usize) {
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    right: Val::Px(16.),
+                    bottom: Val::Px(16. + 28. * index as f32),
+                    ..default()
+                },
+                ..default()
+            },
+            ..TextBundle::from_section(
+                text,
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 22.,
+                    color: Color::WHITE,
+                },
+            )
+        })
+        .insert(Name::new("Toast"))
+        .insert(ItemToast(Timer::from_seconds(TOAST_DURATION, false)));
+}
+
+fn fade_item_toasts(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, &mut ItemToast, &mut Text)>,
+) {
+    for (entity, mut timer, mut text) in &mut query {
+        timer.tick(time.delta());
+        if timer.finished() {
+            commands.entity(entity).despawn_recursive();
+        } else {
+            text.sections[0].style.color.set_a(timer.percent_left());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stack(item: &str, count: u32) -> Option<ItemStack> {
+        Some(ItemStack {
+            item: item.to_string(),
+            count,
+        })
+    }
+
+    #[test]
+    fn add_tops_up_stacks_before_starting_new_ones() {
+        let mut inventory = Inventory::from_slots(vec![None, stack("potion", 3), None]);
+        inventory.add("potion", 4, &Items::fixture()).unwrap();
+        assert_eq!(inventory.slots(), [stack("potion", 2), stack("potion", 5), None]);
+        assert_eq!(
+            inventory.changes,
+            [InventoryChange::Added {
+                item: "potion".to_string(),
+                count: 4
+            }]
+        );
+    }
+
+    #[test]
+    fn add_that_does_not_fit_changes_nothing() {
+        let mut inventory = Inventory::from_slots(vec![stack("potion", 3), stack("sword", 1)]);
+        assert_eq!(inventory.add("potion", 3, &Items::fixture()), Err(InventoryError::Full));
+        assert_eq!(inventory.add("shield", 1, &Items::fixture()), Err(InventoryError::UnknownItem));
+        assert_eq!(inventory.slots(), [stack("potion", 3), stack("sword", 1)]);
+        assert!(inventory.changes.is_empty());
+    }
+
+    #[test]
+    fn add_all_adds_everything_or_nothing() {
+        let mut inventory = Inventory::from_slots(vec![None, None]);
+        let result = inventory.add_all([("potion", 5), ("sword", 1), ("sword", 1)], &Items::fixture());
+        assert_eq!(result, Err(InventoryError::Full));
+        assert_eq!(inventory.slots(), [None, None]);
+        assert!(inventory.changes.is_empty());
+
+        inventory.add_all([("potion", 5), ("sword", 1)], &Items::fixture()).unwrap();
+        assert_eq!(inventory.slots(), [stack("potion", 5), stack("sword", 1)]);
+        assert_eq!(inventory.changes.len(), 2);
+    }
+
+    #[test]
+    fn remove_empties_the_last_stacks_first() {
+        let mut inventory = Inventory::from_slots(vec![stack("potion", 5), stack("sword", 1), stack("potion", 2)]);
+        inventory.remove("potion", 3).unwrap();
+        assert_eq!(inventory.slots(), [stack("potion", 4), stack("sword", 1), None]);
+        assert_eq!(
+            inventory.changes,
+            [InventoryChange::Removed {
+                item: "potion".to_string(),
+                count: 3
+            }]
+        );
+    }
+
+    #[test]
+    fn remove_more_than_held_changes_nothing() {
+        let mut inventory = Inventory::from_slots(vec![stack("potion", 2), stack("potion", 2)]);
+        assert_eq!(inventory.remove("potion", 5), Err(InventoryError::NotEnough));
+        assert_eq!(inventory.slots(), [stack("potion", 2), stack("potion", 2)]);
+        assert!(inventory.changes.is_empty());
+    }
+
+    #[test]
+    fn move_stack_merges_what_fits_and_swaps_the_rest() {
+        let mut inventory = Inventory::from_slots(vec![stack("potion", 4), stack("potion", 3), stack("sword", 1)]);
+        inventory.move_stack(0, 1, &Items::fixture()).unwrap();
+        assert_eq!(inventory.slots(), [stack("potion", 2), stack("potion", 5), stack("sword", 1)]);
+        inventory.move_stack(2, 0, &Items::fixture()).unwrap();
+        assert_eq!(inventory.slots(), [stack("sword", 1), stack("potion", 5), stack("potion", 2)]);
+        assert_eq!(
+            inventory.changes,
+            [
+                InventoryChange::Moved { from: 0, to: 1 },
+                InventoryChange::Moved { from: 2, to: 0 }
+            ]
+        );
+    }
+
+    #[test]
+    fn move_stack_from_an_empty_or_missing_slot_changes_nothing() {
+        let mut inventory = Inventory::from_slots(vec![stack("potion", 4), None]);
+        assert_eq!(inventory.move_stack(1, 0, &Items::fixture()), Err(InventoryError::EmptySlot));
+        assert_eq!(inventory.move_stack(0, 2, &Items::fixture()), Err(InventoryError::NoSuchSlot));
+        assert_eq!(inventory.slots(), [stack("potion", 4), None]);
+        assert!(inventory.changes.is_empty());
+    }
+
+    #[test]
+    fn split_moves_items_into_the_first_empty_slot() {
+        let mut inventory = Inventory::from_slots(vec![stack("sword", 1), stack("potion", 5), None, None]);
+        assert_eq!(inventory.split(1, 2), Ok(2));
+        assert_eq!(inventory.slots(), [stack("sword", 1), stack("potion", 3), stack("potion", 2), None]);
+        assert_eq!(inventory.changes, [InventoryChange::Split { from: 1, to: 2, count: 2 }]);
+    }
+
+    #[test]
+    fn split_that_cannot_happen_changes_nothing() {
+        let mut inventory = Inventory::from_slots(vec![stack("potion", 5), None]);
+        assert_eq!(inventory.split(0, 5), Err(InventoryError::NotEnough));
+        assert_eq!(inventory.split(0, 0), Err(InventoryError::NotEnough));
+        assert_eq!(inventory.split(1, 1), Err(InventoryError::EmptySlot));
+        assert_eq!(inventory.split(2, 1), Err(InventoryError::NoSuchSlot));
+        let mut full = Inventory::from_slots(vec![stack("potion", 5)]);
+        assert_eq!(full.split(0, 2), Err(InventoryError::Full));
+        assert_eq!(full.slots(), [stack("potion", 5)]);
+        assert_eq!(inventory.slots(), [stack("potion", 5), None]);
+        assert!(inventory.changes.is_empty() && full.changes.is_empty());
+    }
+}
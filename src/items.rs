@@ -0,0 +1,295 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashMap},
+};
+use bevy_inspector_egui::Inspectable;
+use serde::Deserialize;
+
+use crate::{
+    combat::Health,
+    dialogue::GameFlags,
+    interact::{Interactable, InteractionKind},
+    loading::LoadingAssets,
+    player::Player,
+    shop::Wallet,
+    sprites::{frame_image, NamedAtlas, Props},
+    tilemap::{prop_size, MapData, MapObject},
+    AppState,
+};
+
+pub struct ItemPlugin;
+
+impl Plugin for ItemPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<ItemPickup>()
+            .add_asset::<ItemDatabase>()
+            .init_asset_loader::<ItemDatabaseLoader>()
+            .init_resource::<Items>()
+            .init_resource::<ItemIcons>()
+            .add_event::<ItemUsed>()
+            .add_system_set(SystemSet::on_enter(AppState::Loading).with_system(load_items))
+            .add_system_set(SystemSet::on_exit(AppState::Loading).with_system(setup_items))
+            .add_system_set(
+                SystemSet::on_enter(AppState::InGame)
+                    .with_system(build_item_icons)
+                    .with_system(spawn_item_pickups),
+            )
+            .add_system(apply_item_effects);
+    }
+}
+
+pub const ITEMS_PATH: &str = "game.items.ron";
+
+/// Every item in the game, as read from `game.items.ron`.
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "0f7d3a52-9c1e-4b8a-a6f2-3d5e8b1c7a94"]
+pub struct ItemDatabase(pub Vec<ItemDef>);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ItemDef {
+    pub id: String,
+    pub name: String,
+    /// Frame of the props atlases the item is drawn with, e.g. `"loot-03"`.
+    pub icon: String,
+    /// How many fit in one inventory slot.
+    #[serde(default = "default_stack_size")]
+    pub stack_size: u32,
+    pub category: ItemCategory,
+    /// What happens when the item is used; it can't be used without one.
+    #[serde(default)]
+    pub effect: Option<UseEffect>,
+}
+
+fn default_stack_size() -> u32 {
+    1
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+pub enum ItemCategory {
+    Weapon,
+    Armor,
+    Consumable,
+    Food,
+    Fish,
+    Tool,
+    Key,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub enum UseEffect {
+    GrantCoins(u32),
+    /// Restores the player's health, up to their maximum.
+    Heal(u32),
+    SetFlag(String),
+}
+
+#[derive(Default)]
+pub struct ItemDatabaseLoader;
+
+impl AssetLoader for ItemDatabaseLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let database: ItemDatabase = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(database));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["items.ron"]
+    }
+}
+
+/// Items lying on the ground, as placed in a map's objects file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ItemSpawn {
+    pub item: String,
+    #[serde(default = "default_count")]
+    pub count: u32,
+    pub position: (usize, usize),
+}
+
+fn default_count() -> u32 {
+    1
+}
+
+/// Items the player can pick up off the ground.
+#[derive(Component, Debug, Default, Inspectable, Reflect)]
+#[reflect(Component)]
+pub struct ItemPickup {
+    pub item: String,
+    pub count: u32,
+}
+
+/// Item definitions by id.
+#[derive(Debug, Default, Deref)]
+pub struct Items(pub HashMap<String, ItemDef>);
+
+#[cfg(test)]
+impl Items {
+    /// Potions stacking up to 5 and swords that don't stack, for unit tests.
+    pub fn fixture() -> Items {
+        let def = |id: &str, stack_size| ItemDef {
+            id: id.to_string(),
+            name: id.to_string(),
+            icon: String::new(),
+            stack_size,
+            category: ItemCategory::Consumable,
+            effect: None,
+        };
+        Items(HashMap::from_iter([
+            ("potion".to_string(), def("potion", 5)),
+            ("sword".to_string(), def("sword", 1)),
+        ]))
+    }
+}
+
+/// Item icons cut out of the props atlases, by item id, for UI nodes.
+#[derive(Debug, Default, Deref, DerefMut)]
+pub struct ItemIcons(pub HashMap<String, Handle<Image>>);
+
+/// Sent when the player uses an item; the item is already gone by then.
+pub struct ItemUsed(pub UseEffect);
+
+struct ItemDatabaseHandle(Handle<ItemDatabase>);
+
+fn load_items(
+    mut commands: Commands,
+    mut loading: ResMut<LoadingAssets>,
+    asset_server: Res<AssetServer>,
+) {
+    let handle = asset_server.load(ITEMS_PATH);
+    loading.add(ITEMS_PATH, &handle);
+    commands.insert_resource(ItemDatabaseHandle(handle));
+}
+
+fn setup_items(
+    mut commands: Commands,
+    handle: Res<ItemDatabaseHandle>,
+    databases: Res<Assets<ItemDatabase>>,
+) {
+    let database = match databases.get(&handle.0) {
+        Some(database) => database,
+        None => return,
+    };
+    let mut items = HashMap::default();
+    for item in &database.0 {
+        if items.insert(item.id.clone(), item.clone()).is_some() {
+            warn!("Item `{}` is defined more than once", item.id);
+        }
+    }
+    commands.insert_resource(Items(items));
+}
+
+fn build_item_icons(
+    items: Res<Items>,
+    props: Res<Props>,
+    named_atlases: Res<Assets<NamedAtlas>>,
+    texture_atlases: Res<Assets<TextureAtlas>>,
+    mut images: ResMut<Assets<Image>>,
+    mut icons: ResMut<ItemIcons>,
+) {
+    for item in items.values() {
+        if icons.contains_key(&item.id) {
+            continue;
+        }
+        let image = props
+            .frame(&named_atlases, &item.icon)
+            .and_then(|(atlas, index)| frame_image(texture_atlases.get(&atlas)?, index, &images));
+        match image {
+            Some(image) => {
+                let handle = images.add(image);
+                icons.insert(item.id.clone(), handle);
+            }
+            None => warn!("No `{}` frame in the prop atlases", item.icon),
+        }
+    }
+}
+
+fn spawn_item_pickups(
+    mut commands: Commands,
+    map: Res<MapData>,
+    items: Res<Items>,
+    props: Res<Props>,
+    named_atlases: Res<Assets<NamedAtlas>>,
+    texture_atlases: Res<Assets<TextureAtlas>>,
+) {
+    for object in &map.objects {
+        let spawn = match object {
+            MapObject::Item(spawn) => spawn,
+            _ => continue,
+        };
+        let def = match items.get(&spawn.item) {
+            Some(def) => def,
+            None => {
+                warn!("Map places unknown item `{}`", spawn.item);
+                continue;
+            }
+        };
+        let (texture_atlas, index) = match props.frame(&named_atlases, &def.icon) {
+            Some(frame) => frame,
+            None => {
+                warn!("No `{}` frame in the prop atlases", def.icon);
+                continue;
+            }
+        };
+        let size = texture_atlases
+            .get(&texture_atlas)
+            .map_or(Vec2::splat(map.tile_size), |atlas| prop_size(atlas, index, map.tile_size));
+
+        commands
+            .spawn_bundle(SpriteSheetBundle {
+                sprite: TextureAtlasSprite {
+                    index,
+                    custom_size: Some(size),
+                    ..default()
+                },
+                texture_atlas,
+                transform: Transform::from_translation(
+                    map.tile_to_world(spawn.position.0, spawn.position.1).extend(0.5),
+                ),
+                ..default()
+            })
+            .insert(Name::new(def.name.clone()))
+            .insert(ItemPickup {
+                item: spawn.item.clone(),
+                count: spawn.count,
+            })
+            .insert(Interactable {
+                radius: 1.,
+                needs_facing: false,
+                ..Interactable::new(InteractionKind::PickUp)
+            });
+    }
+}
+
+fn apply_item_effects(
+    mut events: EventReader<ItemUsed>,
+    mut flags: ResMut<GameFlags>,
+    mut wallet_query: Query<&mut Wallet>,
+    mut health_query: Query<&mut Health, With<Player>>,
+) {
+    for event in events.iter() {
+        match &event.0 {
+            UseEffect::GrantCoins(coins) => {
+                for mut wallet in &mut wallet_query {
+                    wallet.coins = wallet.coins.saturating_add(*coins);
+                }
+            }
+            UseEffect::Heal(amount) => {
+                for mut health in &mut health_query {
+                    health.heal(*amount);
+                }
+            }
+            UseEffect::SetFlag(flag) => {
+                flags.insert(flag.clone());
+            }
+        }
+    }
+}
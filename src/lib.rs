@@ -0,0 +1,199 @@
+//! The game as a library: `GamePlugin` adds everything, and each module's
+//! plugin can be used on its own, e.g. by the headless simulation.
+
+use bevy::{prelude::*, reflect::TypeUuid, render::camera::ScalingMode};
+use serde::{Deserialize, Serialize};
+
+pub mod boss;
+pub mod character;
+pub mod chest;
+pub mod combat;
+pub mod console;
+pub mod debug;
+pub mod dialogue;
+#[cfg(debug_assertions)]
+pub mod editor;
+pub mod fishing;
+pub mod headless;
+pub mod inspector;
+pub mod interact;
+pub mod inventory;
+pub mod items;
+pub mod loading;
+pub mod menu;
+pub mod mob;
+pub mod npc;
+#[cfg(debug_assertions)]
+pub mod overlay;
+pub mod pathfinding;
+pub mod player;
+pub mod projectile;
+pub mod save;
+pub mod scenes;
+pub mod settings;
+pub mod shop;
+pub mod sprites;
+pub mod tilemap;
+
+use bevy_inspector_egui::Inspectable;
+use boss::BossPlugin;
+use character::CharacterPlugin;
+use chest::ChestPlugin;
+use combat::CombatPlugin;
+use debug::DebugPlugin;
+use dialogue::DialoguePlugin;
+use fishing::FishingPlugin;
+use interact::InteractPlugin;
+use inventory::InventoryPlugin;
+use items::ItemPlugin;
+use loading::LoadingPlugin;
+use menu::MenuPlugin;
+use mob::MobPlugin;
+use npc::NpcPlugin;
+use pathfinding::PathfindingPlugin;
+use player::PlayerPlugin;
+use projectile::ProjectilePlugin;
+use save::SavePlugin;
+use scenes::SceneExportPlugin;
+use settings::SettingsPlugin;
+use shop::ShopPlugin;
+use sprites::SpritePlugin;
+use tilemap::TileMapPlugin;
+
+/// Tuning values, read from `assets/game.settings.ron` at startup and again
+/// whenever the file changes in debug builds. Insert one before adding
+/// `GamePlugin` to ignore the file; missing fields take their defaults.
+#[derive(Debug, Clone, Inspectable, Serialize, Deserialize, TypeUuid)]
+#[uuid = "9a4f1c6e-3b82-4d7a-b5e0-6c2d8f17a3b9"]
+#[serde(default)]
+pub struct GameConfig {
+    /// Width of the view over its height.
+    pub resolution: f32,
+    /// Map loaded at startup, by name under `assets/maps`.
+    pub map: String,
+    /// World units per tile. Speeds, ranges and hitboxes are given in tiles.
+    pub tile_size: f32,
+    /// Where the player starts, and comes back to after being knocked out.
+    pub player_start: Vec2,
+    /// In tiles per second.
+    pub player_speed: f32,
+    pub player_run_speed: f32,
+    pub player_health: u32,
+    /// Seconds per frame of a walking animation; running plays faster.
+    pub animation_frame: f32,
+    /// How far behind the camera trails the player; 0 keeps it on the player.
+    pub camera_follow_distance: f32,
+    /// Seconds between two steps of the camera catching up.
+    pub camera_step: f32,
+    /// Seeds fishing's random numbers at startup, so a run of casts can be
+    /// replayed. Without one every run fishes differently.
+    pub fishing_seed: Option<u64>,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            resolution: 16.0 / 9.0,
+            map: "path".to_string(),
+            tile_size: 0.2,
+            player_start: Vec2::ZERO,
+            player_speed: 4.,
+            player_run_speed: 6.,
+            player_health: 6,
+            animation_frame: 0.1,
+            camera_follow_distance: 30.,
+            camera_step: 1. / 400.,
+            fishing_seed: None,
+        }
+    }
+}
+
+/// The whole game, on top of `DefaultPlugins`.
+pub struct GamePlugin;
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_plugin(SettingsPlugin)
+            .add_state(AppState::Boot)
+            .register_type::<CameraProperties>()
+            .register_type::<CameraTimer>()
+            .add_startup_system(spawn_camera)
+            .add_plugin(LoadingPlugin)
+            .add_plugin(MenuPlugin)
+            .add_plugin(SpritePlugin)
+            .add_plugin(CharacterPlugin)
+            .add_plugin(PlayerPlugin)
+            .add_plugin(CombatPlugin)
+            .add_plugin(ProjectilePlugin)
+            .add_plugin(InteractPlugin)
+            .add_plugin(NpcPlugin)
+            .add_plugin(MobPlugin)
+            .add_plugin(BossPlugin)
+            .add_plugin(DialoguePlugin)
+            .add_plugin(ShopPlugin)
+            .add_plugin(ItemPlugin)
+            .add_plugin(InventoryPlugin)
+            .add_plugin(ChestPlugin)
+            .add_plugin(FishingPlugin)
+            .add_plugin(TileMapPlugin)
+            .add_plugin(PathfindingPlugin)
+            .add_plugin(SavePlugin)
+            .add_plugin(SceneExportPlugin)
+            .add_plugin(DebugPlugin);
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AppState {
+    Boot,
+    Loading,
+    MainMenu,
+    InGame,
+    Paused,
+    Dialogue,
+    Shop,
+    Inventory,
+    Fishing,
+}
+
+#[derive(Component, Default, Deref, DerefMut, Reflect)]
+#[reflect(Component)]
+pub struct CameraTimer(Timer);
+
+#[derive(Component, Inspectable, Reflect)]
+#[reflect(Component)]
+pub struct CameraProperties{
+    pub follow_distance: f32
+}
+
+impl CameraProperties {
+    pub fn new(config: &GameConfig) -> CameraProperties {
+        CameraProperties {
+            follow_distance: config.camera_follow_distance
+        }
+    }
+}
+
+/// For scenes, which add components without a way to pass the config.
+impl FromWorld for CameraProperties {
+    fn from_world(world: &mut World) -> Self {
+        CameraProperties::new(&world.get_resource::<GameConfig>().cloned().unwrap_or_default())
+    }
+}
+
+fn spawn_camera(mut commands: Commands, config: Res<GameConfig>) {
+    commands
+    .spawn_bundle(Camera2dBundle {
+        projection: OrthographicProjection {
+            left: -config.resolution,
+            right: config.resolution,
+            bottom: -1.,
+            top: 1.,
+            scaling_mode: ScalingMode::None,
+            ..default()
+        },
+        ..default()
+    })
+    .insert(CameraProperties::new(&config))
+    .insert(CameraTimer(Timer::from_seconds(config.camera_step, true)));
+}
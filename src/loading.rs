@@ -0,0 +1,130 @@
+use bevy::{asset::{Asset, LoadState}, prelude::*};
+
+use crate::AppState;
+
+pub struct LoadingPlugin;
+
+impl Plugin for LoadingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LoadingAssets>()
+            .add_system_set(SystemSet::on_update(AppState::Boot).with_system(finish_boot))
+            .add_system_set(
+                SystemSet::on_enter(AppState::Loading).with_system(spawn_loading_screen),
+            )
+            .add_system_set(
+                SystemSet::on_update(AppState::Loading)
+                    .with_system(check_loading.label("check_loading")),
+            )
+            .add_system_set(
+                SystemSet::on_exit(AppState::Loading).with_system(despawn_loading_screen),
+            );
+    }
+}
+
+/// Assets that have to finish loading before the game leaves `AppState::Loading`.
+///
+/// Plugins add their handles from a system running on `AppState::Loading`; anything
+/// queued while loading is already under way must run `.before("check_loading")`.
+#[derive(Default)]
+pub struct LoadingAssets {
+    assets: Vec<(String, HandleUntyped)>,
+}
+
+impl LoadingAssets {
+    pub fn add<T: Asset>(&mut self, name: impl Into<String>, handle: &Handle<T>) {
+        self.assets.push((name.into(), handle.clone_untyped()));
+    }
+
+    fn status(&self, asset_server: &AssetServer) -> LoadingStatus {
+        let mut status = LoadingStatus {
+            loaded: 0,
+            total: self.assets.len(),
+            failed: Vec::new(),
+        };
+        for (name, handle) in &self.assets {
+            match asset_server.get_load_state(handle) {
+                LoadState::Loaded => status.loaded += 1,
+                LoadState::Failed => status.failed.push(name.clone()),
+                _ => {}
+            }
+        }
+        status
+    }
+}
+
+struct LoadingStatus {
+    loaded: usize,
+    total: usize,
+    failed: Vec<String>,
+}
+
+#[derive(Component)]
+struct LoadingScreen;
+
+#[derive(Component)]
+struct LoadingText;
+
+fn finish_boot(mut state: ResMut<State<AppState>>) {
+    state.set(AppState::Loading).unwrap();
+}
+
+fn spawn_loading_screen(
+    mut commands: Commands,
+    mut loading: ResMut<LoadingAssets>,
+    asset_server: Res<AssetServer>,
+) {
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    loading.add("fonts/FiraSans-Bold.ttf", &font);
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::BLACK.into(),
+            ..default()
+        })
+        .insert(Name::new("Loading Screen"))
+        .insert(LoadingScreen)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(TextBundle::from_section(
+                    "Loading...",
+                    TextStyle {
+                        font,
+                        font_size: 30.,
+                        color: Color::WHITE,
+                    },
+                ))
+                .insert(LoadingText);
+        });
+}
+
+fn check_loading(
+    mut state: ResMut<State<AppState>>,
+    loading: Res<LoadingAssets>,
+    asset_server: Res<AssetServer>,
+    mut text_query: Query<&mut Text, With<LoadingText>>,
+) {
+    let status = loading.status(&asset_server);
+
+    for mut text in &mut text_query {
+        text.sections[0].value = if status.failed.is_empty() {
+            format!("Loading... {}/{}", status.loaded, status.total)
+        } else {
+            format!("Failed to load:\n{}", status.failed.join("\n"))
+        };
+    }
+
+    if status.failed.is_empty() && status.loaded == status.total {
+        state.set(AppState::MainMenu).unwrap();
+    }
+}
+
+fn despawn_loading_screen(mut commands: Commands, query: Query<Entity, With<LoadingScreen>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
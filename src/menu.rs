@@ -0,0 +1,109 @@
+use bevy::prelude::*;
+
+use crate::AppState;
+
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(AppState::MainMenu).with_system(spawn_main_menu))
+            .add_system_set(SystemSet::on_update(AppState::MainMenu).with_system(start_game))
+            .add_system_set(SystemSet::on_exit(AppState::MainMenu).with_system(despawn_screen))
+            .add_system_set(SystemSet::on_update(AppState::InGame).with_system(pause_game))
+            .add_system_set(SystemSet::on_enter(AppState::Paused).with_system(spawn_pause_menu))
+            .add_system_set(SystemSet::on_update(AppState::Paused).with_system(resume_game))
+            .add_system_set(SystemSet::on_exit(AppState::Paused).with_system(despawn_screen));
+    }
+}
+
+#[derive(Component)]
+struct MenuScreen;
+
+fn spawn_main_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    spawn_menu(
+        &mut commands,
+        asset_server.load("fonts/FiraSans-Bold.ttf"),
+        "Main Menu",
+        "Press Enter to start, or 1-3 to load a save",
+        Color::BLACK,
+    );
+}
+
+fn spawn_pause_menu(mut commands: Commands, asset_server: Res<AssetServer>) {
+    spawn_menu(
+        &mut commands,
+        asset_server.load("fonts/FiraSans-Bold.ttf"),
+        "Paused",
+        "Press Escape to resume. In game, F5 saves and Shift+1-3 saves to another slot",
+        Color::rgba(0., 0., 0., 0.6),
+    );
+}
+
+fn spawn_menu(
+    commands: &mut Commands,
+    font: Handle<Font>,
+    title: &str,
+    hint: &str,
+    background: Color,
+) {
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: background.into(),
+            ..default()
+        })
+        .insert(Name::new(title.to_string()))
+        .insert(MenuScreen)
+        .with_children(|parent| {
+            parent.spawn_bundle(TextBundle::from_section(
+                title,
+                TextStyle {
+                    font: font.clone(),
+                    font_size: 50.,
+                    color: Color::WHITE,
+                },
+            ));
+            parent.spawn_bundle(TextBundle::from_section(
+                hint,
+                TextStyle {
+                    font,
+                    font_size: 25.,
+                    color: Color::GRAY,
+                },
+            ));
+        });
+}
+
+fn despawn_screen(mut commands: Commands, query: Query<Entity, With<MenuScreen>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn start_game(mut state: ResMut<State<AppState>>, mut keyboard_input: ResMut<Input<KeyCode>>) {
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        keyboard_input.reset(KeyCode::Return);
+        state.set(AppState::InGame).unwrap();
+    }
+}
+
+fn pause_game(mut state: ResMut<State<AppState>>, mut keyboard_input: ResMut<Input<KeyCode>>) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        // Reset so the paused state doesn't see the same press and resume right away.
+        keyboard_input.reset(KeyCode::Escape);
+        state.push(AppState::Paused).unwrap();
+    }
+}
+
+fn resume_game(mut state: ResMut<State<AppState>>, mut keyboard_input: ResMut<Input<KeyCode>>) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        keyboard_input.reset(KeyCode::Escape);
+        state.pop().unwrap();
+    }
+}
@@ -0,0 +1,576 @@
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::{BoxedFuture, HashMap},
+};
+use bevy_inspector_egui::{egui, Context, Inspectable};
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{
+    character::{
+        character_size, check_wander_radius, collides, wander_target, AnimationTimer, CharacterCollider, Motion,
+        CHARACTER_HITBOX,
+    },
+    combat::{Died, Health, Hitbox, Hurtbox, Knockback, Team},
+    inspector::Fields,
+    loading::LoadingAssets,
+    pathfinding::{Navigation, Navigator, PathOptions, Step},
+    player::Player,
+    projectile::{Pattern, ProjectileDef, Shoot},
+    sprites::{Mobs, NamedAtlas},
+    tilemap::{MapData, MapObject, TileCollider},
+    AppState, GameConfig,
+};
+
+pub struct MobPlugin;
+
+impl Plugin for MobPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<MobDatabase>()
+            .init_asset_loader::<MobDatabaseLoader>()
+            .add_system_set(SystemSet::on_enter(AppState::Loading).with_system(load_mobs))
+            .add_system_set(SystemSet::on_enter(AppState::InGame).with_system(spawn_mobs))
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(mob_ai.label("mob_ai").after("player_movement"))
+                    .with_system(mob_movement.label("mob_movement").after("mob_ai"))
+                    .with_system(mob_shoot.label("mob_shoot").after("mob_ai"))
+                    .with_system(despawn_dead_mobs.after("apply_damage")),
+            );
+    }
+}
+
+pub const MOBS_PATH: &str = "game.mobs.ron";
+
+/// How close a mob has to get to a spot to count as there, in tiles.
+const ARRIVE_DISTANCE: f32 = 0.1;
+
+/// Every kind of mob, by id, as read from `game.mobs.ron`.
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "5b9d2e74-0c6a-4f1e-9a83-e27c4d6f1b08"]
+pub struct MobDatabase(pub HashMap<String, MobDef>);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct MobDef {
+    pub name: String,
+    /// Name of an atlas in the `mobs` section of the sprite manifest.
+    pub sprite: String,
+    /// Walking speed in tiles per second, when idle or going home.
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+    /// Speed in tiles per second when chasing or fleeing.
+    #[serde(default = "default_run_speed")]
+    pub run_speed: f32,
+    /// How far the mob sees the player, in tiles. Walls block its view.
+    #[serde(default = "default_sight")]
+    pub sight: f32,
+    /// How far from home the mob follows or runs from the player before
+    /// turning back, in tiles.
+    #[serde(default = "default_leash")]
+    pub leash: f32,
+    #[serde(default)]
+    pub idle: IdleBehaviour,
+    #[serde(default)]
+    pub reaction: Reaction,
+    #[serde(default = "default_health")]
+    pub health: u32,
+    /// Damage dealt to the player on touch; harmless mobs have none.
+    #[serde(default)]
+    pub damage: u32,
+    /// How far a touch pushes the player, in tiles.
+    #[serde(default = "default_knockback")]
+    pub knockback: f32,
+    /// How the mob finds its way around fences.
+    #[serde(default)]
+    pub pathing: PathOptions,
+    /// Shot at the player while chasing them.
+    #[serde(default)]
+    pub ranged: Option<RangedAttack>,
+}
+
+impl MobDef {
+    /// Catches values the mob's timers and movement can't work with.
+    fn validate(&self) -> Result<(), String> {
+        for (field, value) in [("speed", self.speed), ("run_speed", self.run_speed)] {
+            if !value.is_finite() || value <= 0. {
+                return Err(format!("`{field}` has to be above 0, not {value}"));
+            }
+        }
+        if let IdleBehaviour::Wander { radius } = self.idle {
+            check_wander_radius(radius)?;
+        }
+        if let Some(ranged) = &self.ranged {
+            if !ranged.cooldown.is_finite() || ranged.cooldown < 0. {
+                return Err(format!("`cooldown` can't be {}", ranged.cooldown));
+            }
+            ranged.projectile.validate().map_err(|err| format!("Projectile: {err}"))?;
+        }
+        Ok(())
+    }
+}
+
+fn default_speed() -> f32 {
+    1.5
+}
+
+fn default_run_speed() -> f32 {
+    2.5
+}
+
+fn default_sight() -> f32 {
+    4.
+}
+
+fn default_leash() -> f32 {
+    8.
+}
+
+fn default_health() -> u32 {
+    3
+}
+
+fn default_knockback() -> f32 {
+    1.
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RangedAttack {
+    pub pattern: Pattern,
+    pub projectile: ProjectileDef,
+    /// Seconds between two shots.
+    #[serde(default = "default_cooldown")]
+    pub cooldown: f32,
+    /// How close the player has to be to get shot at, in tiles.
+    #[serde(default = "default_range")]
+    pub range: f32,
+}
+
+fn default_cooldown() -> f32 {
+    1.5
+}
+
+fn default_range() -> f32 {
+    4.
+}
+
+/// What a mob does while it has nothing better to do.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub enum IdleBehaviour {
+    #[default]
+    Stand,
+    /// Hops to random spots at most `radius` tiles away from home.
+    Wander { radius: f32 },
+}
+
+/// What a mob does when it sees the player.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum Reaction {
+    #[default]
+    Ignore,
+    Chase,
+    Flee,
+}
+
+#[derive(Default)]
+pub struct MobDatabaseLoader;
+
+impl AssetLoader for MobDatabaseLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let database: MobDatabase = ron::de::from_bytes(bytes)?;
+            for (id, def) in &database.0 {
+                def.validate()
+                    .map_err(|err| bevy::asset::Error::msg(format!("Mob `{id}`: {err}")))?;
+            }
+            load_context.set_default_asset(LoadedAsset::new(database));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["mobs.ron"]
+    }
+}
+
+/// A mob as placed in a map's objects file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MobSpawn {
+    /// Id of the mob in `game.mobs.ron`.
+    pub mob: String,
+    /// Home tile, where the mob starts and goes back to.
+    pub position: (usize, usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MobState {
+    Idle,
+    Chase,
+    Flee,
+    ReturnHome,
+}
+
+#[derive(Component, Debug)]
+pub struct Mob {
+    pub def: MobDef,
+    pub home: Vec2,
+    pub state: MobState,
+    target: Option<Vec2>,
+    wait: Timer,
+    reload: Timer,
+}
+
+impl Inspectable for Mob {
+    type Attributes = ();
+
+    fn ui(&mut self, ui: &mut egui::Ui, _: Self::Attributes, context: &mut Context) -> bool {
+        Fields::show(ui, context, |fields| {
+            fields
+                .show_debug("mob", &self.def.name)
+                .edit("home", &mut self.home)
+                .show_debug("state", &self.state)
+                .show_debug("target", &self.target)
+                .timer("wait", &mut self.wait)
+                .timer("reload", &mut self.reload);
+        })
+    }
+}
+
+impl Mob {
+    fn wait(&mut self, seconds: f32) {
+        self.wait = Timer::from_seconds(seconds, false);
+    }
+
+    /// Where to wander to next, if anywhere.
+    fn wander_target(&self, tile_size: f32) -> Option<Vec2> {
+        match self.def.idle {
+            IdleBehaviour::Stand => None,
+            IdleBehaviour::Wander { radius } => Some(wander_target(self.home, radius, tile_size)),
+        }
+    }
+}
+
+pub struct MobDatabaseHandle(pub Handle<MobDatabase>);
+
+fn load_mobs(mut commands: Commands, mut loading: ResMut<LoadingAssets>, asset_server: Res<AssetServer>) {
+    let handle = asset_server.load(MOBS_PATH);
+    loading.add(MOBS_PATH, &handle);
+    commands.insert_resource(MobDatabaseHandle(handle));
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_mobs(
+    mut commands: Commands,
+    config: Res<GameConfig>,
+    map: Res<MapData>,
+    database: Res<Assets<MobDatabase>>,
+    database_handle: Res<MobDatabaseHandle>,
+    mobs: Res<Mobs>,
+    named_atlases: Res<Assets<NamedAtlas>>,
+    texture_atlases: Res<Assets<TextureAtlas>>,
+) {
+    let database = match database.get(&database_handle.0) {
+        Some(database) => database,
+        None => return,
+    };
+    for object in &map.objects {
+        let spawn = match object {
+            MapObject::Mob(spawn) => spawn,
+            _ => continue,
+        };
+        let def = match database.0.get(&spawn.mob) {
+            Some(def) => def,
+            None => {
+                warn!("Map places unknown mob `{}`", spawn.mob);
+                continue;
+            }
+        };
+        let home = map.tile_to_world(spawn.position.0, spawn.position.1);
+        spawn_mob(&mut commands, def, home, &config, &mobs, &named_atlases, &texture_atlases);
+    }
+}
+
+/// Spawns a mob at `home`, the spot it wanders around and returns to.
+pub fn spawn_mob(
+    commands: &mut Commands,
+    def: &MobDef,
+    home: Vec2,
+    config: &GameConfig,
+    mobs: &Mobs,
+    named_atlases: &Assets<NamedAtlas>,
+    texture_atlases: &Assets<TextureAtlas>,
+) -> Option<Entity> {
+    let atlas_handle = match mobs.texture_atlas(named_atlases, &def.sprite) {
+        Some(handle) => handle,
+        None => {
+            warn!("Mob `{}` uses unknown sprite `{}`", def.name, def.sprite);
+            return None;
+        }
+    };
+    let size = texture_atlases
+        .get(&atlas_handle)
+        .map_or(Vec2::splat(config.tile_size), |atlas| character_size(atlas, 0, config.tile_size));
+
+    let mut mob = commands.spawn_bundle(SpriteSheetBundle {
+        sprite: TextureAtlasSprite {
+            custom_size: Some(size),
+            ..default()
+        },
+        texture_atlas: atlas_handle,
+        transform: Transform::from_translation(home.extend(1.)),
+        ..default()
+    });
+    mob.insert(Name::new(def.name.clone()))
+        .insert(Mob {
+            def: def.clone(),
+            home,
+            state: MobState::Idle,
+            target: None,
+            wait: Timer::from_seconds(0., false),
+            reload: Timer::from_seconds(0., false),
+        })
+        .insert(Navigator::new(def.pathing))
+        .insert(Motion::default())
+        .insert(CharacterCollider)
+        .insert(AnimationTimer(Timer::from_seconds(config.animation_frame, true)))
+        .insert(Health::new(def.health))
+        .insert(Hurtbox {
+            size: Vec2::splat(CHARACTER_HITBOX * config.tile_size),
+            team: Team::Enemy,
+        });
+    if def.damage > 0 {
+        // A little larger than the collider, so touching is enough.
+        mob.insert(Hitbox {
+            size: Vec2::splat(config.tile_size * 0.9),
+            team: Team::Enemy,
+            damage: def.damage,
+            knockback: def.knockback,
+        });
+    }
+    Some(mob.id())
+}
+
+fn despawn_dead_mobs(
+    mut commands: Commands,
+    mut died_events: EventReader<Died>,
+    mob_query: Query<(), With<Mob>>,
+) {
+    for died in died_events.iter() {
+        if mob_query.contains(died.entity) {
+            commands.entity(died.entity).despawn_recursive();
+        }
+    }
+}
+
+/// Picks each mob's state from where the player is, and where it should head.
+fn mob_ai(
+    config: Res<GameConfig>,
+    navigation: Res<Navigation>,
+    mut mob_query: Query<(&mut Mob, &Transform), Without<Player>>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    let player = match player_query.get_single() {
+        Ok(transform) => transform.translation.truncate(),
+        Err(_) => return,
+    };
+    for (mut mob, transform) in &mut mob_query {
+        let position = transform.translation.truncate();
+        let from_home = position.distance(mob.home) / config.tile_size;
+        let sees_player = || {
+            position.distance(player) <= mob.def.sight * config.tile_size
+                && navigation.grid.line_of_sight(position, player)
+        };
+
+        let state = match (mob.state, mob.def.reaction) {
+            // Going home ignores the player until the mob is back.
+            (MobState::ReturnHome, _) => MobState::ReturnHome,
+            (_, Reaction::Ignore) => MobState::Idle,
+            (MobState::Idle, reaction) if from_home <= mob.def.leash && sees_player() => {
+                if reaction == Reaction::Chase {
+                    MobState::Chase
+                } else {
+                    MobState::Flee
+                }
+            }
+            (MobState::Idle, _) => MobState::Idle,
+            (_, _) if from_home > mob.def.leash || !sees_player() => MobState::ReturnHome,
+            (state, _) => state,
+        };
+        if state != mob.state {
+            mob.state = state;
+            mob.target = None;
+            mob.wait(0.);
+        }
+
+        match mob.state {
+            MobState::Idle => {}
+            MobState::Chase => mob.target = Some(player),
+            MobState::Flee => {
+                let away = (position - player).normalize_or_zero();
+                mob.target = Some(position + away * config.tile_size);
+            }
+            MobState::ReturnHome => {
+                if from_home <= ARRIVE_DISTANCE {
+                    mob.state = MobState::Idle;
+                    mob.target = None;
+                    mob.wait(1.);
+                } else {
+                    mob.target = Some(mob.home);
+                }
+            }
+        }
+    }
+}
+
+/// Fires ranged mobs' attacks at the player while they chase and can see them.
+fn mob_shoot(
+    time: Res<Time>,
+    config: Res<GameConfig>,
+    navigation: Res<Navigation>,
+    mut shots: EventWriter<Shoot>,
+    mut mob_query: Query<(&mut Mob, &Transform), Without<Player>>,
+    player_query: Query<&Transform, With<Player>>,
+) {
+    let player = match player_query.get_single() {
+        Ok(transform) => transform.translation.truncate(),
+        Err(_) => return,
+    };
+    for (mut mob, transform) in &mut mob_query {
+        let ranged = match (&mob.def.ranged, mob.state) {
+            (Some(ranged), MobState::Chase) => ranged.clone(),
+            _ => continue,
+        };
+        if !mob.reload.tick(time.delta()).finished() {
+            continue;
+        }
+        let position = transform.translation.truncate();
+        if position.distance(player) > ranged.range * config.tile_size || !navigation.grid.line_of_sight(position, player) {
+            continue;
+        }
+        mob.reload = Timer::from_seconds(ranged.cooldown, false);
+        shots.send(Shoot {
+            position,
+            aim: player - position,
+            pattern: ranged.pattern,
+            projectile: ranged.projectile,
+            team: Team::Enemy,
+        });
+    }
+}
+
+/// Walks mobs towards their target, around fences when it can and blocked by
+/// walls and characters like the player is.
+#[allow(clippy::type_complexity)]
+fn mob_movement(
+    time: Res<Time>,
+    config: Res<GameConfig>,
+    navigation: Res<Navigation>,
+    mut mob_query: Query<
+        (
+            Entity,
+            &mut Mob,
+            &mut Navigator,
+            &mut Motion,
+            &mut AnimationTimer,
+            &mut Transform,
+        ),
+        Without<Knockback>,
+    >,
+    wall_query: Query<&GlobalTransform, With<TileCollider>>,
+    character_query: Query<(Entity, &GlobalTransform), With<CharacterCollider>>,
+) {
+    for (entity, mut mob, mut navigator, mut motion, mut timer, mut transform) in &mut mob_query {
+        mob.wait.tick(time.delta());
+        if !mob.wait.finished() {
+            motion.update(Vec3::ZERO);
+            continue;
+        }
+        if mob.target.is_none() && mob.state == MobState::Idle {
+            mob.target = mob.wander_target(config.tile_size);
+        }
+        let target = match mob.target {
+            Some(target) => target,
+            None => {
+                motion.update(Vec3::ZERO);
+                continue;
+            }
+        };
+
+        let running = matches!(mob.state, MobState::Chase | MobState::Flee);
+        let speed = if running { mob.def.run_speed } else { mob.def.speed };
+        // `animation_frame` can be edited in the inspector, which doesn't validate it.
+        if let Ok(frame) = std::time::Duration::try_from_secs_f32(config.animation_frame * mob.def.speed / speed) {
+            timer.set_duration(frame);
+        }
+
+        let position = transform.translation.truncate();
+        let step = speed * config.tile_size * time.delta_seconds();
+        if position.distance(target) <= step.max(ARRIVE_DISTANCE * config.tile_size) {
+            if mob.state == MobState::Idle {
+                mob.target = None;
+                let seconds = rand::thread_rng().gen_range(1. ..3.);
+                mob.wait(seconds);
+            }
+            motion.update(Vec3::ZERO);
+            continue;
+        }
+        // Fleeing mobs just run; there is no goal worth a path.
+        let waypoint = if mob.state == MobState::Flee {
+            target
+        } else {
+            match navigator.next_step(position, target, &navigation) {
+                Step::Walk(waypoint) => waypoint,
+                Step::Wait => {
+                    motion.update(Vec3::ZERO);
+                    continue;
+                }
+                Step::Unreachable => {
+                    // Wanderers pick another spot, chasers head home, and mobs
+                    // that can't get home make a new one where they are.
+                    match mob.state {
+                        MobState::Chase => mob.state = MobState::ReturnHome,
+                        MobState::ReturnHome => {
+                            mob.state = MobState::Idle;
+                            mob.home = position;
+                        }
+                        MobState::Idle | MobState::Flee => {}
+                    }
+                    mob.target = None;
+                    navigator.clear();
+                    mob.wait(0.5);
+                    motion.update(Vec3::ZERO);
+                    continue;
+                }
+            }
+        };
+        let to_waypoint = waypoint - position;
+        let mut movement = (to_waypoint.normalize_or_zero() * step.min(to_waypoint.length())).extend(0.);
+
+        let others = || {
+            character_query
+                .iter()
+                .filter(|(other, _)| *other != entity)
+                .map(|(_, transform)| transform.translation())
+        };
+        let walls = || wall_query.iter().map(GlobalTransform::translation);
+        if collides(transform.translation + Vec3::new(movement.x, 0., 0.), walls(), others(), config.tile_size) {
+            movement.x = 0.;
+        }
+        if collides(transform.translation + Vec3::new(0., movement.y, 0.), walls(), others(), config.tile_size) {
+            movement.y = 0.;
+        }
+        if movement == Vec3::ZERO && mob.state == MobState::Idle {
+            // Stuck while wandering: try somewhere else in a bit.
+            mob.target = None;
+            mob.wait(0.5);
+        }
+
+        motion.update(movement);
+        transform.translation += movement;
+    }
+}
@@ -0,0 +1,276 @@
+use bevy::prelude::*;
+use bevy_inspector_egui::{egui, Context, Inspectable};
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::{
+    character::{
+        character_size, check_wander_radius, collides, wander_target, AnimationTimer, CharacterCollider, Direction,
+        Motion,
+    },
+    dialogue::DialogueSource,
+    inspector::Fields,
+    interact::{Interactable, InteractionKind},
+    pathfinding::{Navigation, Navigator, Step},
+    shop::Vendor,
+    sprites::{Characters, NamedAtlas},
+    tilemap::{MapData, MapObject, TileCollider},
+    AppState, GameConfig,
+};
+
+pub struct NpcPlugin;
+
+impl Plugin for NpcPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(SystemSet::on_enter(AppState::InGame).with_system(spawn_npcs))
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(npc_movement.after("player_movement")),
+            );
+    }
+}
+
+/// An NPC as placed in a map's objects file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct NpcSpawn {
+    pub name: String,
+    /// Name of an atlas in the `characters` section of the sprite manifest.
+    pub sprite: String,
+    /// Tile the NPC starts on, in the same coordinates as the map layers.
+    pub position: (usize, usize),
+    #[serde(default)]
+    pub facing: Direction,
+    #[serde(default)]
+    pub behaviour: Behaviour,
+    /// Path of the `.dialogue.ron` asset played when the player talks to the NPC.
+    #[serde(default)]
+    pub dialogue: Option<String>,
+    /// Path of the `.shop.ron` asset if the NPC is a vendor.
+    #[serde(default)]
+    pub shop: Option<String>,
+    /// Walking speed in tiles per second.
+    #[serde(default = "default_speed")]
+    pub speed: f32,
+}
+
+fn default_speed() -> f32 {
+    2.
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub enum Behaviour {
+    #[default]
+    Stand,
+    /// Walks to random spots at most `radius` tiles away from where it was placed.
+    Wander { radius: f32 },
+    /// Walks between the given tiles in order, looping back to the first.
+    Patrol { waypoints: Vec<(usize, usize)> },
+}
+
+#[derive(Component)]
+pub struct Npc {
+    speed: f32,
+    home: Vec2,
+    behaviour: Behaviour,
+    waypoints: Vec<Vec2>,
+    next_waypoint: usize,
+    target: Option<Vec2>,
+    wait: Timer,
+}
+
+impl Inspectable for Npc {
+    type Attributes = ();
+
+    fn ui(&mut self, ui: &mut egui::Ui, _: Self::Attributes, context: &mut Context) -> bool {
+        Fields::show(ui, context, |fields| {
+            fields
+                .edit("speed", &mut self.speed)
+                .edit("home", &mut self.home)
+                .show_debug("behaviour", &self.behaviour)
+                .show_debug("next waypoint", &self.waypoints.get(self.next_waypoint))
+                .show_debug("target", &self.target)
+                .timer("wait", &mut self.wait);
+        })
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn spawn_npcs(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    config: Res<GameConfig>,
+    map: Res<MapData>,
+    characters: Res<Characters>,
+    named_atlases: Res<Assets<NamedAtlas>>,
+    texture_atlases: Res<Assets<TextureAtlas>>,
+) {
+    for object in &map.objects {
+        let spawn = match object {
+            MapObject::Npc(spawn) => spawn,
+            _ => continue,
+        };
+        let atlas_handle = match characters.texture_atlas(&named_atlases, &spawn.sprite) {
+            Some(handle) => handle,
+            None => {
+                warn!("NPC `{}` uses unknown sprite `{}`", spawn.name, spawn.sprite);
+                continue;
+            }
+        };
+        if !spawn.speed.is_finite() || spawn.speed <= 0. {
+            warn!("NPC `{}` has to have a speed above 0, not {}", spawn.name, spawn.speed);
+            continue;
+        }
+        if let Behaviour::Wander { radius } = spawn.behaviour {
+            if let Err(err) = check_wander_radius(radius) {
+                warn!("NPC `{}`: {err}", spawn.name);
+                continue;
+            }
+        }
+        let size = texture_atlases
+            .get(&atlas_handle)
+            .map_or(Vec2::splat(config.tile_size), |atlas| character_size(atlas, 0, config.tile_size));
+        let home = map.tile_to_world(spawn.position.0, spawn.position.1);
+        let waypoints = match &spawn.behaviour {
+            Behaviour::Patrol { waypoints } => waypoints
+                .iter()
+                .map(|&(x, y)| map.tile_to_world(x, y))
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        let mut npc = commands.spawn_bundle(SpriteSheetBundle {
+            sprite: TextureAtlasSprite {
+                custom_size: Some(size),
+                ..default()
+            },
+            texture_atlas: atlas_handle,
+            transform: Transform::from_translation(home.extend(1.)),
+            ..default()
+        });
+        npc.insert(Name::new(spawn.name.clone()))
+            .insert(Npc {
+                speed: spawn.speed,
+                home,
+                behaviour: spawn.behaviour.clone(),
+                waypoints,
+                next_waypoint: 0,
+                target: None,
+                wait: Timer::from_seconds(0., false),
+            })
+            .insert(Navigator::default())
+            .insert(Motion {
+                facing: spawn.facing,
+                ..default()
+            })
+            .insert(CharacterCollider)
+            .insert(AnimationTimer(Timer::from_seconds(config.animation_frame, true)));
+        if let Some(path) = &spawn.dialogue {
+            npc.insert(DialogueSource(asset_server.load(path.as_str())))
+                .insert(Interactable::new(InteractionKind::TalkTo));
+        }
+        if let Some(path) = &spawn.shop {
+            npc.insert(Vendor::new(asset_server.load(path.as_str())));
+        }
+    }
+}
+
+impl Npc {
+    fn next_target(&mut self, tile_size: f32) -> Option<Vec2> {
+        match self.behaviour {
+            Behaviour::Stand => None,
+            Behaviour::Wander { radius } => Some(wander_target(self.home, radius, tile_size)),
+            Behaviour::Patrol { .. } => self.waypoints.get(self.next_waypoint).copied(),
+        }
+    }
+
+    fn arrive(&mut self) {
+        self.target = None;
+        match self.behaviour {
+            Behaviour::Stand => {}
+            Behaviour::Wander { .. } => self.wait(rand::thread_rng().gen_range(1. ..3.)),
+            Behaviour::Patrol { .. } => {
+                self.next_waypoint = (self.next_waypoint + 1) % self.waypoints.len().max(1);
+                self.wait(0.5);
+            }
+        }
+    }
+
+    fn wait(&mut self, seconds: f32) {
+        self.wait = Timer::from_seconds(seconds, false);
+    }
+}
+
+fn npc_movement(
+    time: Res<Time>,
+    config: Res<GameConfig>,
+    navigation: Res<Navigation>,
+    mut npc_query: Query<(Entity, &mut Npc, &mut Navigator, &mut Motion, &mut Transform)>,
+    wall_query: Query<&GlobalTransform, With<TileCollider>>,
+    character_query: Query<(Entity, &GlobalTransform), With<CharacterCollider>>,
+) {
+    for (entity, mut npc, mut navigator, mut motion, mut transform) in &mut npc_query {
+        npc.wait.tick(time.delta());
+        if !npc.wait.finished() {
+            motion.update(Vec3::ZERO);
+            continue;
+        }
+        if npc.target.is_none() {
+            npc.target = npc.next_target(config.tile_size);
+        }
+        let target = match npc.target {
+            Some(target) => target,
+            None => {
+                motion.update(Vec3::ZERO);
+                continue;
+            }
+        };
+
+        let position = transform.translation.truncate();
+        let step = npc.speed * config.tile_size * time.delta_seconds();
+        if position.distance(target) <= step {
+            npc.arrive();
+            motion.update(Vec3::ZERO);
+            continue;
+        }
+        let waypoint = match navigator.next_step(position, target, &navigation) {
+            Step::Walk(waypoint) => waypoint,
+            Step::Wait => {
+                motion.update(Vec3::ZERO);
+                continue;
+            }
+            Step::Unreachable => {
+                // Skip the spot: a new wander target, or the next waypoint.
+                npc.arrive();
+                navigator.clear();
+                motion.update(Vec3::ZERO);
+                continue;
+            }
+        };
+        let to_waypoint = waypoint - position;
+        let mut movement = (to_waypoint.normalize_or_zero() * step.min(to_waypoint.length())).extend(0.);
+
+        let others = || {
+            character_query
+                .iter()
+                .filter(|(other, _)| *other != entity)
+                .map(|(_, transform)| transform.translation())
+        };
+        let walls = || wall_query.iter().map(GlobalTransform::translation);
+        if collides(transform.translation + Vec3::new(movement.x, 0., 0.), walls(), others(), config.tile_size) {
+            movement.x = 0.;
+        }
+        if collides(transform.translation + Vec3::new(0., movement.y, 0.), walls(), others(), config.tile_size) {
+            movement.y = 0.;
+        }
+        if movement == Vec3::ZERO {
+            // Stuck: wanderers pick somewhere else, patrols wait for the way to clear.
+            if let Behaviour::Wander { .. } = npc.behaviour {
+                npc.target = None;
+            }
+            npc.wait(0.5);
+        }
+
+        motion.update(movement);
+        transform.translation += movement;
+    }
+}
@@ -0,0 +1,311 @@
+//! Debug overlays drawn as lines over the game: F1 toggles colliders, F2 the
+//! tile grid, F3 the camera and F4 the paths mobs and NPCs are walking.
+//! Only built in debug builds.
+
+use bevy::{
+    prelude::*,
+    render::{mesh::PrimitiveTopology, view::NoFrustumCulling},
+    sprite::{MaterialMesh2dBundle, Mesh2dHandle},
+    transform::TransformSystem,
+};
+
+use crate::{
+    character::{CharacterCollider, CHARACTER_HITBOX},
+    pathfinding::Navigator,
+    player::Player,
+    tilemap::{MapData, TileCollider},
+    AppState, CameraProperties, GameConfig,
+};
+
+pub struct OverlayPlugin;
+
+impl Plugin for OverlayPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Overlays>()
+            .init_resource::<DebugLines>()
+            .add_system_set(SystemSet::on_enter(AppState::InGame).with_system(spawn_overlay))
+            .add_system(overlay_keys)
+            .add_system(update_cursor_label)
+            .add_system_set_to_stage(
+                CoreStage::PostUpdate,
+                SystemSet::new()
+                    .label("draw_overlays")
+                    .after(TransformSystem::TransformPropagate)
+                    .with_system(draw_colliders)
+                    .with_system(draw_grid)
+                    .with_system(draw_camera)
+                    .with_system(draw_paths),
+            )
+            .add_system_to_stage(CoreStage::PostUpdate, flush_lines.after("draw_overlays"));
+    }
+}
+
+/// Above the map, characters and props.
+const OVERLAY_Z: f32 = 100.;
+
+/// Which overlays are shown.
+#[derive(Default)]
+pub struct Overlays {
+    pub colliders: bool,
+    pub grid: bool,
+    pub camera: bool,
+    pub paths: bool,
+}
+
+/// Lines to draw this frame, in world space. Anything can add to them; they
+/// are drawn and cleared at the end of the frame.
+#[derive(Default)]
+pub struct DebugLines {
+    positions: Vec<[f32; 3]>,
+    colors: Vec<[f32; 4]>,
+}
+
+impl DebugLines {
+    pub fn line(&mut self, start: Vec2, end: Vec2, color: Color) {
+        self.positions.push(start.extend(0.).into());
+        self.positions.push(end.extend(0.).into());
+        self.colors.extend([color.as_linear_rgba_f32(); 2]);
+    }
+
+    /// The outline of a `size` box centred on `center`.
+    pub fn rect(&mut self, center: Vec2, size: Vec2, color: Color) {
+        let (min, max) = (center - size / 2., center + size / 2.);
+        let corners = [min, Vec2::new(max.x, min.y), max, Vec2::new(min.x, max.y)];
+        for (i, &corner) in corners.iter().enumerate() {
+            self.line(corner, corners[(i + 1) % 4], color);
+        }
+    }
+
+    pub fn cross(&mut self, center: Vec2, size: f32, color: Color) {
+        self.line(center - Vec2::splat(size / 2.), center + Vec2::splat(size / 2.), color);
+        self.line(
+            center + Vec2::new(-size, size) / 2.,
+            center + Vec2::new(size, -size) / 2.,
+            color,
+        );
+    }
+}
+
+#[derive(Component)]
+struct OverlayMesh;
+
+/// Coordinates of the tile under the cursor, while the grid is shown.
+#[derive(Component)]
+struct CursorLabel;
+
+fn spawn_overlay(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    commands
+        .spawn_bundle(MaterialMesh2dBundle {
+            mesh: meshes.add(Mesh::new(PrimitiveTopology::LineList)).into(),
+            material: materials.add(ColorMaterial::from(Color::WHITE)),
+            transform: Transform::from_xyz(0., 0., OVERLAY_Z),
+            visibility: Visibility { is_visible: false },
+            ..default()
+        })
+        .insert(Name::new("Debug Overlay"))
+        .insert(OverlayMesh)
+        // Bounds are only worked out once, for the mesh as first spawned.
+        .insert(NoFrustumCulling);
+
+    commands
+        .spawn_bundle(TextBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                ..default()
+            },
+            visibility: Visibility { is_visible: false },
+            ..TextBundle::from_section(
+                "",
+                TextStyle {
+                    font: asset_server.load("fonts/FiraSans-Bold.ttf"),
+                    font_size: 16.,
+                    color: Color::WHITE,
+                },
+            )
+        })
+        .insert(Name::new("Cursor Tile"))
+        .insert(CursorLabel);
+}
+
+fn overlay_keys(keyboard_input: Res<Input<KeyCode>>, mut overlays: ResMut<Overlays>) {
+    for key in keyboard_input.get_just_pressed() {
+        match key {
+            KeyCode::F1 => overlays.colliders = !overlays.colliders,
+            KeyCode::F2 => overlays.grid = !overlays.grid,
+            KeyCode::F3 => overlays.camera = !overlays.camera,
+            KeyCode::F4 => overlays.paths = !overlays.paths,
+            _ => {}
+        }
+    }
+}
+
+fn draw_colliders(
+    overlays: Res<Overlays>,
+    config: Res<GameConfig>,
+    mut lines: ResMut<DebugLines>,
+    tile_query: Query<&GlobalTransform, With<TileCollider>>,
+    character_query: Query<(&GlobalTransform, Option<&Player>), With<CharacterCollider>>,
+) {
+    if !overlays.colliders {
+        return;
+    }
+    for transform in &tile_query {
+        lines.rect(transform.translation().truncate(), Vec2::splat(config.tile_size), Color::RED);
+    }
+    for (transform, player) in &character_query {
+        let color = if player.is_some() { Color::GREEN } else { Color::ORANGE };
+        lines.rect(transform.translation().truncate(), Vec2::splat(CHARACTER_HITBOX * config.tile_size), color);
+    }
+}
+
+/// Where the cursor points in the world, if it is over the window.
+pub fn cursor_position(windows: &Windows, camera: &Camera, transform: &GlobalTransform) -> Option<Vec2> {
+    let window = windows.get_primary()?;
+    let cursor = window.cursor_position()?;
+    let size = Vec2::new(window.width(), window.height());
+    let ndc = (cursor / size) * 2. - Vec2::ONE;
+    let ndc_to_world = transform.compute_matrix() * camera.projection_matrix().inverse();
+    Some(ndc_to_world.project_point3(ndc.extend(0.)).truncate())
+}
+
+fn draw_grid(
+    overlays: Res<Overlays>,
+    mut lines: ResMut<DebugLines>,
+    map: Option<Res<MapData>>,
+    windows: Res<Windows>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+) {
+    let map = match map {
+        Some(map) if overlays.grid => map,
+        _ => return,
+    };
+    let color = Color::rgba(1., 1., 1., 0.25);
+    // Tile centres are on the origin, so the lines run half a tile off it.
+    let top_left = map.origin() + Vec2::new(-map.tile_size, map.tile_size) / 2.;
+    let (width, height) = (map.width() as f32 * map.tile_size, map.height() as f32 * map.tile_size);
+    for x in 0..=map.width() {
+        let start = top_left + Vec2::X * x as f32 * map.tile_size;
+        lines.line(start, start - Vec2::Y * height, color);
+    }
+    for y in 0..=map.height() {
+        let start = top_left - Vec2::Y * y as f32 * map.tile_size;
+        lines.line(start, start + Vec2::X * width, color);
+    }
+
+    let hovered = camera_query
+        .get_single()
+        .ok()
+        .and_then(|(camera, transform)| cursor_position(&windows, camera, transform))
+        .and_then(|cursor| map.world_to_tile(cursor));
+    if let Some((x, y)) = hovered {
+        lines.rect(map.tile_to_world(x, y), Vec2::splat(map.tile_size), Color::YELLOW);
+    }
+}
+
+fn update_cursor_label(
+    overlays: Res<Overlays>,
+    map: Option<Res<MapData>>,
+    windows: Res<Windows>,
+    camera_query: Query<(&Camera, &GlobalTransform)>,
+    mut label_query: Query<(&mut Text, &mut Style, &mut Visibility), With<CursorLabel>>,
+) {
+    let (mut text, mut style, mut visibility) = match label_query.get_single_mut() {
+        Ok(label) => label,
+        Err(_) => return,
+    };
+    let cursor = windows.get_primary().and_then(Window::cursor_position);
+    let tile = match (map, camera_query.get_single()) {
+        (Some(map), Ok((camera, transform))) if overlays.grid => {
+            cursor_position(&windows, camera, transform).and_then(|world| map.world_to_tile(world))
+        }
+        _ => None,
+    };
+    match (cursor, tile) {
+        (Some(cursor), Some((x, y))) => {
+            visibility.is_visible = true;
+            text.sections[0].value = format!("({x}, {y})");
+            style.position = UiRect {
+                left: Val::Px(cursor.x + 12.),
+                bottom: Val::Px(cursor.y + 12.),
+                ..default()
+            };
+        }
+        _ => visibility.is_visible = false,
+    }
+}
+
+/// The camera has no dead zone: every step it closes a `1 / follow_distance`
+/// share of the gap to the player, so a player moving at a steady speed ends up
+/// `speed * step * follow_distance` away from its centre. Draws those bounds for
+/// walking and running, the furthest the camera lets the player get ahead.
+fn draw_camera(
+    overlays: Res<Overlays>,
+    time: Res<Time>,
+    config: Res<GameConfig>,
+    mut lines: ResMut<DebugLines>,
+    camera_query: Query<(&GlobalTransform, &CameraProperties)>,
+    player_query: Query<&Player>,
+) {
+    if !overlays.camera {
+        return;
+    }
+    // The camera steps at most once a frame.
+    let step = config.camera_step.max(time.delta_seconds());
+    for (transform, properties) in &camera_query {
+        let center = transform.translation().truncate();
+        lines.cross(center, config.tile_size / 2., Color::CYAN);
+        for player in &player_query {
+            for speed in [player.speed, player.run_speed] {
+                let lag = speed * config.tile_size * step * properties.follow_distance;
+                lines.rect(center, Vec2::splat(lag * 2.), Color::CYAN);
+            }
+        }
+    }
+}
+
+fn draw_paths(
+    overlays: Res<Overlays>,
+    config: Res<GameConfig>,
+    mut lines: ResMut<DebugLines>,
+    query: Query<(&GlobalTransform, &Navigator)>,
+) {
+    if !overlays.paths {
+        return;
+    }
+    for (transform, navigator) in &query {
+        let mut from = transform.translation().truncate();
+        for waypoint in navigator.waypoints() {
+            lines.line(from, waypoint, Color::FUCHSIA);
+            lines.cross(waypoint, config.tile_size / 4., Color::FUCHSIA);
+            from = waypoint;
+        }
+    }
+}
+
+/// Moves this frame's lines into the overlay mesh.
+fn flush_lines(
+    mut lines: ResMut<DebugLines>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut query: Query<(&Mesh2dHandle, &mut Visibility), With<OverlayMesh>>,
+) {
+    let (handle, mut visibility) = match query.get_single_mut() {
+        Ok(overlay) => overlay,
+        Err(_) => return,
+    };
+    visibility.is_visible = !lines.positions.is_empty();
+    let mesh = match meshes.get_mut(&handle.0) {
+        Some(mesh) => mesh,
+        None => return,
+    };
+    let count = lines.positions.len();
+    mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, std::mem::take(&mut lines.positions));
+    mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, std::mem::take(&mut lines.colors));
+    // Unused, but the 2D mesh pipeline wants them.
+    mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, vec![[0., 0., 1.]; count]);
+    mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, vec![[0., 0.]; count]);
+}
@@ -0,0 +1,561 @@
+use std::{cmp::Ordering, collections::BinaryHeap, sync::Arc};
+
+use bevy::{
+    prelude::*,
+    tasks::{AsyncComputeTaskPool, Task},
+    utils::HashMap,
+};
+use bevy_inspector_egui::{egui, Context, Inspectable};
+use futures_lite::future;
+use serde::Deserialize;
+
+use crate::{
+    inspector::Fields,
+    tilemap::{MapData, TileCollider},
+};
+
+pub struct PathfindingPlugin;
+
+impl Plugin for PathfindingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Navigation>()
+            .add_system(update_nav_grid.label("update_nav_grid"))
+            .add_system(poll_path_tasks.after("update_nav_grid"));
+    }
+}
+
+/// Most paths kept around before the cache is emptied.
+const CACHE_SIZE: usize = 256;
+
+/// How close an agent has to get to a waypoint before heading for the next, in tiles.
+const WAYPOINT_RADIUS: f32 = 0.15;
+
+/// Cost of walking onto a tile, by background character. Unlisted tiles cost 1.
+fn tile_cost(tile: char) -> f32 {
+    match tile {
+        'g' => 1.5,
+        _ => 1.,
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+pub enum Connectivity {
+    /// Only up, down, left and right.
+    Four,
+    /// Diagonals too.
+    #[default]
+    Eight,
+}
+
+/// When a diagonal step may pass the corner of a blocked tile.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+pub enum CornerCutting {
+    /// Whenever the tile stepped onto is free.
+    Allow,
+    /// If at least one of the two tiles beside the step is free.
+    OneSide,
+    /// Only if both tiles beside the step are free. Characters are nearly a
+    /// tile wide, so anything else snags them on the corner.
+    #[default]
+    Never,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize)]
+pub struct PathOptions {
+    #[serde(default)]
+    pub connectivity: Connectivity,
+    #[serde(default)]
+    pub corners: CornerCutting,
+}
+
+/// The map as seen by the pathfinder: which tiles are blocked by a
+/// `TileCollider` and what the rest cost to walk on.
+#[derive(Debug, Clone, Default)]
+pub struct NavGrid {
+    pub width: usize,
+    pub height: usize,
+    /// World position of the centre of tile (0, 0).
+    origin: Vec2,
+    tile_size: f32,
+    blocked: Vec<bool>,
+    costs: Vec<f32>,
+    /// Cheapest tile on the grid, which keeps the heuristic admissible.
+    min_cost: f32,
+}
+
+impl NavGrid {
+    pub fn new(map: &MapData, colliders: impl IntoIterator<Item = Vec2>) -> NavGrid {
+        let (width, height) = (map.width(), map.height());
+        let mut grid = NavGrid {
+            width,
+            height,
+            origin: map.origin(),
+            tile_size: map.tile_size,
+            blocked: vec![false; width * height],
+            costs: vec![1.; width * height],
+            min_cost: 1.,
+        };
+        for y in 0..height {
+            for x in 0..width {
+                grid.costs[y * width + x] = map.background_at(x, y).map_or(1., tile_cost);
+            }
+        }
+        grid.min_cost = grid.costs.iter().copied().fold(f32::MAX, f32::min).min(1.);
+        for position in colliders {
+            if let Some((x, y)) = grid.tile_at(position) {
+                grid.blocked[y * width + x] = true;
+            }
+        }
+        grid
+    }
+
+    /// The tile covering `position`, if it is on the grid.
+    pub fn tile_at(&self, position: Vec2) -> Option<(usize, usize)> {
+        let offset = (position - self.origin) / self.tile_size;
+        let (x, y) = ((offset.x + 0.5).floor(), (-offset.y + 0.5).floor());
+        if x < 0. || y < 0. || x as usize >= self.width || y as usize >= self.height {
+            return None;
+        }
+        Some((x as usize, y as usize))
+    }
+
+    pub fn tile_center(&self, (x, y): (usize, usize)) -> Vec2 {
+        self.origin + Vec2::new(x as f32 * self.tile_size, -(y as f32) * self.tile_size)
+    }
+
+    pub fn is_walkable(&self, x: usize, y: usize) -> bool {
+        x < self.width && y < self.height && !self.blocked[y * self.width + x]
+    }
+
+    pub fn cost(&self, x: usize, y: usize) -> f32 {
+        self.costs[y * self.width + x]
+    }
+
+    /// Whether no blocked tile stands between `from` and `to`.
+    pub fn line_of_sight(&self, from: Vec2, to: Vec2) -> bool {
+        // No grid yet, and no tile size to step through it by.
+        if self.blocked.is_empty() {
+            return true;
+        }
+        let steps = ((to - from).length() / (self.tile_size / 4.)).ceil() as usize;
+        (1..steps).all(|step| {
+            let point = from.lerp(to, step as f32 / steps as f32);
+            self.tile_at(point).is_none_or(|(x, y)| self.is_walkable(x, y))
+        })
+    }
+
+    /// Tiles `(x, y)` can step to under `options`, with what each step costs.
+    fn neighbours(&self, (x, y): (usize, usize), options: PathOptions) -> Vec<((usize, usize), f32)> {
+        let (x, y) = (x as isize, y as isize);
+        let walkable = |x: isize, y: isize| x >= 0 && y >= 0 && self.is_walkable(x as usize, y as usize);
+        let mut neighbours = Vec::with_capacity(8);
+        for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            if walkable(x + dx, y + dy) {
+                let tile = ((x + dx) as usize, (y + dy) as usize);
+                neighbours.push((tile, self.cost(tile.0, tile.1)));
+            }
+        }
+        if options.connectivity == Connectivity::Eight {
+            for (dx, dy) in [(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+                if !walkable(x + dx, y + dy) {
+                    continue;
+                }
+                let sides = [walkable(x + dx, y), walkable(x, y + dy)];
+                let allowed = match options.corners {
+                    CornerCutting::Allow => true,
+                    CornerCutting::OneSide => sides[0] || sides[1],
+                    CornerCutting::Never => sides[0] && sides[1],
+                };
+                if allowed {
+                    let tile = ((x + dx) as usize, (y + dy) as usize);
+                    neighbours.push((tile, self.cost(tile.0, tile.1) * std::f32::consts::SQRT_2));
+                }
+            }
+        }
+        neighbours
+    }
+
+    /// Cheapest possible cost from `a` to `b`, ignoring walls.
+    fn heuristic(&self, a: (usize, usize), b: (usize, usize), options: PathOptions) -> f32 {
+        let dx = a.0.abs_diff(b.0) as f32;
+        let dy = a.1.abs_diff(b.1) as f32;
+        let distance = match options.connectivity {
+            Connectivity::Four => dx + dy,
+            Connectivity::Eight => dx.max(dy) + (std::f32::consts::SQRT_2 - 1.) * dx.min(dy),
+        };
+        distance * self.min_cost
+    }
+
+    /// The cheapest run of tiles from `start` to `goal`, both included. The
+    /// start tile counts as free, so agents squeezed against a wall can leave.
+    pub fn find_path(
+        &self,
+        start: (usize, usize),
+        goal: (usize, usize),
+        options: PathOptions,
+    ) -> Option<Vec<(usize, usize)>> {
+        if !self.is_walkable(goal.0, goal.1) {
+            return None;
+        }
+        let mut open = BinaryHeap::new();
+        let mut came_from = HashMap::default();
+        let mut best = HashMap::default();
+        best.insert(start, 0.);
+        open.push(OpenTile {
+            tile: start,
+            estimate: self.heuristic(start, goal, options),
+        });
+
+        while let Some(OpenTile { tile, .. }) = open.pop() {
+            if tile == goal {
+                let mut path = vec![goal];
+                let mut tile = goal;
+                while let Some(&previous) = came_from.get(&tile) {
+                    path.push(previous);
+                    tile = previous;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            let cost = best[&tile];
+            for (next, step) in self.neighbours(tile, options) {
+                let next_cost = cost + step;
+                if best.get(&next).is_some_and(|&known| known <= next_cost) {
+                    continue;
+                }
+                best.insert(next, next_cost);
+                came_from.insert(next, tile);
+                open.push(OpenTile {
+                    tile: next,
+                    estimate: next_cost + self.heuristic(next, goal, options),
+                });
+            }
+        }
+        None
+    }
+}
+
+/// A tile on the A* frontier, ordered so the heap pops the lowest estimate first.
+struct OpenTile {
+    tile: (usize, usize),
+    estimate: f32,
+}
+
+impl PartialEq for OpenTile {
+    fn eq(&self, other: &Self) -> bool {
+        self.estimate == other.estimate
+    }
+}
+
+impl Eq for OpenTile {}
+
+impl PartialOrd for OpenTile {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for OpenTile {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+type PathKey = ((usize, usize), (usize, usize), PathOptions);
+
+/// A found path, or `None` if the goal can't be reached.
+type CachedPath = Option<Arc<Vec<(usize, usize)>>>;
+
+/// The current navigation grid and the paths found on it. Both are thrown
+/// away whenever a tile or collider changes.
+#[derive(Default)]
+pub struct Navigation {
+    pub grid: Arc<NavGrid>,
+    /// Bumped on every rebuild, so paths found on an older grid can be told apart.
+    pub version: u32,
+    cache: HashMap<PathKey, CachedPath>,
+}
+
+impl Navigation {
+    fn rebuild(&mut self, grid: NavGrid) {
+        self.grid = Arc::new(grid);
+        self.version = self.version.wrapping_add(1);
+        self.cache.clear();
+    }
+}
+
+/// What an agent should do this frame to reach its goal.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    /// Head for this spot.
+    Walk(Vec2),
+    /// The path is still being worked out.
+    Wait,
+    /// No path leads to the goal.
+    Unreachable,
+}
+
+struct PathResult {
+    version: u32,
+    key: PathKey,
+    path: Option<Vec<(usize, usize)>>,
+}
+
+/// Finds paths for an entity off the main thread and walks it along them.
+#[derive(Component, Default)]
+pub struct Navigator {
+    pub options: PathOptions,
+    /// Goal tile of the current path, and the grid version it was found on.
+    goal: Option<((usize, usize), u32)>,
+    /// World positions still to visit, the next one last.
+    waypoints: Vec<Vec2>,
+    unreachable: bool,
+    task: Option<Task<PathResult>>,
+}
+
+impl Inspectable for Navigator {
+    type Attributes = ();
+
+    fn ui(&mut self, ui: &mut egui::Ui, _: Self::Attributes, context: &mut Context) -> bool {
+        Fields::show(ui, context, |fields| {
+            fields
+                .show_debug("options", &self.options)
+                .show_debug("goal", &self.goal.map(|(tile, _)| tile))
+                .show_debug("waypoints", &self.waypoints.len())
+                .show_debug("unreachable", &self.unreachable)
+                .show_debug("searching", &self.task.is_some());
+        })
+    }
+}
+
+impl Navigator {
+    pub fn new(options: PathOptions) -> Navigator {
+        Navigator {
+            options,
+            ..default()
+        }
+    }
+
+    /// World positions still to visit, the next one first.
+    pub fn waypoints(&self) -> impl Iterator<Item = Vec2> + '_ {
+        self.waypoints.iter().rev().copied()
+    }
+
+    /// Where to head next on the way to `goal`. A new path is asked for
+    /// whenever the goal moves to another tile or the grid changes, and the
+    /// old one is followed until it arrives.
+    pub fn next_step(&mut self, position: Vec2, goal: Vec2, navigation: &Navigation) -> Step {
+        let grid = &navigation.grid;
+        let (start_tile, goal_tile) = match (grid.tile_at(position), grid.tile_at(goal)) {
+            (Some(start), Some(goal)) => (start, goal),
+            // Off the grid, or no grid yet: nothing to steer around.
+            _ => return Step::Walk(goal),
+        };
+
+        if self.goal != Some((goal_tile, navigation.version)) {
+            self.goal = Some((goal_tile, navigation.version));
+            self.unreachable = false;
+            let key = (start_tile, goal_tile, self.options);
+            match navigation.cache.get(&key) {
+                Some(Some(path)) => self.follow(path, grid),
+                Some(None) => {
+                    self.waypoints.clear();
+                    self.unreachable = true;
+                }
+                None => {
+                    let (grid, version) = (grid.clone(), navigation.version);
+                    self.task = Some(AsyncComputeTaskPool::get().spawn(async move {
+                        let path = grid.find_path(start_tile, goal_tile, key.2);
+                        PathResult { version, key, path }
+                    }));
+                }
+            }
+        }
+
+        // Only the first path of all has to be waited for.
+        if self.task.is_some() && self.waypoints.is_empty() {
+            return Step::Wait;
+        }
+        if self.unreachable {
+            return Step::Unreachable;
+        }
+        // The last waypoint is the goal itself rather than its tile's centre.
+        if let Some(last) = self.waypoints.first_mut() {
+            *last = goal;
+        }
+        while self.waypoints.len() > 1
+            && self.waypoints[self.waypoints.len() - 1].distance(position) < WAYPOINT_RADIUS * grid.tile_size
+        {
+            self.waypoints.pop();
+        }
+        Step::Walk(self.waypoints.last().copied().unwrap_or(goal))
+    }
+
+    /// Forgets the current path, e.g. when the agent gives up on its goal.
+    pub fn clear(&mut self) {
+        self.goal = None;
+        self.waypoints.clear();
+        self.unreachable = false;
+        self.task = None;
+    }
+
+    fn follow(&mut self, path: &[(usize, usize)], grid: &NavGrid) {
+        // The agent is already on the first tile.
+        self.waypoints = path
+            .iter()
+            .skip(1)
+            .rev()
+            .map(|&tile| grid.tile_center(tile))
+            .collect();
+        if self.waypoints.is_empty() {
+            self.waypoints.push(grid.tile_center(path[0]));
+        }
+    }
+}
+
+/// Rebuilds the grid whenever the map or any tile collider changes.
+#[allow(clippy::type_complexity)]
+fn update_nav_grid(
+    mut navigation: ResMut<Navigation>,
+    map: Option<Res<MapData>>,
+    changed_query: Query<(), (With<TileCollider>, Or<(Added<TileCollider>, Changed<GlobalTransform>)>)>,
+    removed: RemovedComponents<TileCollider>,
+    collider_query: Query<&GlobalTransform, With<TileCollider>>,
+) {
+    let map = match map {
+        Some(map) => map,
+        None => return,
+    };
+    if !map.is_changed() && changed_query.is_empty() && removed.iter().next().is_none() {
+        return;
+    }
+    let colliders = collider_query.iter().map(|transform| transform.translation().truncate());
+    navigation.rebuild(NavGrid::new(&map, colliders));
+}
+
+/// Hands finished paths to their navigators and keeps them for the next agent
+/// going the same way.
+fn poll_path_tasks(mut navigation: ResMut<Navigation>, mut query: Query<&mut Navigator>) {
+    for mut navigator in &mut query {
+        let result = match navigator.task.as_mut().map(|task| future::block_on(future::poll_once(task))) {
+            Some(Some(result)) => result,
+            _ => continue,
+        };
+        navigator.task = None;
+        if result.version != navigation.version {
+            // Found on a grid that has since changed; ask again on the next step.
+            navigator.goal = None;
+            continue;
+        }
+
+        let path = result.path.map(Arc::new);
+        match &path {
+            Some(path) => navigator.follow(path, &navigation.grid.clone()),
+            None => {
+                navigator.waypoints.clear();
+                navigator.unreachable = true;
+            }
+        }
+        if navigation.cache.len() >= CACHE_SIZE {
+            navigation.cache.clear();
+        }
+        navigation.cache.insert(result.key, path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::tasks::TaskPool;
+
+    use super::*;
+
+    /// A one unit per tile grid from rows of background tiles, `#` for
+    /// blocked ones.
+    fn nav_grid(rows: &[&str]) -> NavGrid {
+        let map = MapData {
+            name: "test".to_string(),
+            background: rows.iter().map(|row| row.chars().collect()).collect(),
+            foreground: Vec::new(),
+            objects: Vec::new(),
+            tile_size: 1.,
+        };
+        let mut colliders = Vec::new();
+        for (y, row) in rows.iter().enumerate() {
+            for (x, tile) in row.chars().enumerate() {
+                if tile == '#' {
+                    colliders.push(map.tile_to_world(x, y));
+                }
+            }
+        }
+        NavGrid::new(&map, colliders)
+    }
+
+    #[test]
+    fn navigator_keeps_to_its_path_while_a_new_one_is_found() {
+        AsyncComputeTaskPool::init(TaskPool::new);
+        let mut navigation = Navigation::default();
+        navigation.rebuild(nav_grid(&["dddd", "dddd"]));
+        let grid = navigation.grid.clone();
+        let mut navigator = Navigator::default();
+        let key = ((0, 0), (3, 0), navigator.options);
+        let path = grid.find_path(key.0, key.1, key.2).map(Arc::new);
+        navigation.cache.insert(key, path);
+
+        let position = grid.tile_center((0, 0));
+        let first = grid.tile_center((1, 0));
+        assert_eq!(navigator.next_step(position, grid.tile_center((3, 0)), &navigation), Step::Walk(first));
+        // The goal moves to a tile no path is known to yet.
+        assert_eq!(navigator.next_step(position, grid.tile_center((3, 1)), &navigation), Step::Walk(first));
+        assert!(navigator.task.is_some());
+    }
+
+    fn options(connectivity: Connectivity, corners: CornerCutting) -> PathOptions {
+        PathOptions { connectivity, corners }
+    }
+
+    #[test]
+    fn four_connected_paths_only_step_straight() {
+        let grid = nav_grid(&["ddd", "ddd", "ddd"]);
+        let path = grid.find_path((0, 0), (2, 2), options(Connectivity::Four, CornerCutting::Allow)).unwrap();
+        assert_eq!(path.len(), 5);
+        assert!(path.windows(2).all(|step| step[0].0.abs_diff(step[1].0) + step[0].1.abs_diff(step[1].1) == 1));
+    }
+
+    #[test]
+    fn eight_connected_paths_go_diagonally() {
+        let grid = nav_grid(&["ddd", "ddd", "ddd"]);
+        let path = grid.find_path((0, 0), (2, 2), options(Connectivity::Eight, CornerCutting::Never));
+        assert_eq!(path, Some(vec![(0, 0), (1, 1), (2, 2)]));
+    }
+
+    #[test]
+    fn corner_cutting_past_one_blocked_side() {
+        let grid = nav_grid(&["d#", "dd"]);
+        let path = |corners| grid.find_path((0, 0), (1, 1), options(Connectivity::Eight, corners));
+        assert_eq!(path(CornerCutting::Allow), Some(vec![(0, 0), (1, 1)]));
+        assert_eq!(path(CornerCutting::OneSide), Some(vec![(0, 0), (1, 1)]));
+        assert_eq!(path(CornerCutting::Never), Some(vec![(0, 0), (0, 1), (1, 1)]));
+    }
+
+    #[test]
+    fn corner_cutting_between_two_blocked_sides() {
+        let grid = nav_grid(&["d#", "#d"]);
+        let path = |corners| grid.find_path((0, 0), (1, 1), options(Connectivity::Eight, corners));
+        assert_eq!(path(CornerCutting::Allow), Some(vec![(0, 0), (1, 1)]));
+        assert_eq!(path(CornerCutting::OneSide), None);
+        assert_eq!(path(CornerCutting::Never), None);
+    }
+
+    #[test]
+    fn paths_go_around_grass_when_dirt_is_cheaper() {
+        // Five grass tiles cost 7.5, the two extra dirt steps around them 7.
+        let grid = nav_grid(&["dgggggd", "ddddddd"]);
+        let path = grid.find_path((0, 0), (6, 0), options(Connectivity::Four, CornerCutting::Never)).unwrap();
+        assert!(path[1..path.len() - 1].iter().all(|&(_, y)| y == 1));
+
+        // Past a single grass tile the straight way is still cheaper.
+        let grid = nav_grid(&["dgd", "ddd"]);
+        let path = grid.find_path((0, 0), (2, 0), options(Connectivity::Four, CornerCutting::Never));
+        assert_eq!(path, Some(vec![(0, 0), (1, 0), (2, 0)]));
+    }
+}
@@ -1,93 +1,126 @@
 use std::time::Duration;
 
-use bevy::{prelude::*, sprite::collide_aabb::collide};
+use bevy::prelude::*;
 use bevy_inspector_egui::Inspectable;
 
-use crate::{CameraTimer, TILE_SIZE, CameraProperties, tilemap::TileCollider};
-
-// use crate::sprites::Characters;
-
-#[derive(Clone, Copy, Default, Eq, Inspectable, PartialEq)]
-enum Direction {
-    Down,
-    Left,
-    Up,
-    #[default]
-    Right,
+use crate::{
+    character::{collides, AnimationTimer, CharacterCollider, Motion, MoveStatus, CHARACTER_HITBOX},
+    combat::{AttackCooldown, Died, Health, Hurtbox, Knockback, Team},
+    inventory::spawn_toast,
+    inventory::{Inventory, INVENTORY_SLOTS},
+    shop::Wallet,
+    sprites::{Characters, NamedAtlas},
+    tilemap::TileCollider,
+    AppState, CameraProperties, CameraTimer, GameConfig,
+};
+
+#[derive(Component, Inspectable, Reflect)]
+#[reflect(Component)]
+pub struct Player {
+    running: bool,
+    pub speed: f32,
+    pub run_speed: f32,
 }
 
-#[derive(Clone, Copy, Eq, Inspectable, PartialEq)]
-enum MoveStatus {
-    Stopped,
-    Moving(Direction),
+impl Player {
+    pub fn new(config: &GameConfig) -> Player {
+        Player {
+            running: false,
+            speed: config.player_speed,
+            run_speed: config.player_run_speed,
+        }
+    }
 }
 
-#[derive(Component, Inspectable)]
-pub struct Player {
-    motion: MoveStatus,
-    running: bool,
-    speed: f32,
-    run_speed: f32,
+/// For scenes, which add components without a way to pass the config.
+impl FromWorld for Player {
+    fn from_world(world: &mut World) -> Self {
+        Player::new(&world.get_resource::<GameConfig>().cloned().unwrap_or_default())
+    }
 }
 
+/// Lets the player walk through walls and characters.
+#[derive(Component, Default)]
+pub struct Noclip;
+
 pub struct PlayerPlugin;
 
 impl Plugin for PlayerPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(spawn_player)
-            .add_system(player_movement.label("player_movement"))
-            .add_system(player_animation)
-            .add_system(camera_follow.after("player_movement"));
+        app.register_type::<Player>()
+            .add_system_set(SystemSet::on_enter(AppState::InGame).with_system(spawn_player))
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(player_movement.label("player_movement"))
+                    .with_system(respawn_player.after("apply_damage"))
+                    .with_system(camera_follow.after("player_movement")),
+            );
     }
 }
 
-pub struct PlayerAtlas(Handle<TextureAtlas>);
-
-const TIMER_DURATION: f32 = 0.1;
-
 fn spawn_player(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    config: Res<GameConfig>,
+    characters: Res<Characters>,
+    named_atlases: Res<Assets<NamedAtlas>>,
 ) {
-    let gabe_handle = asset_server.load("textures/rpg/chars/gabe/gabe-idle-run.png");
-    let gabe_atlas = TextureAtlas::from_grid(gabe_handle, Vec2::new(24.0, 24.0), 7, 1);
-    let gabe_atlas_handle = texture_atlases.add(gabe_atlas);
-
-    commands.insert_resource(PlayerAtlas(gabe_atlas_handle.clone()));
+    // Headless runs have no art, and still need a player to move around.
+    let gabe_atlas_handle = characters
+        .texture_atlas(&named_atlases, "gabe")
+        .unwrap_or_else(|| {
+            warn!("No `gabe` atlas in the sprite manifest, the player is invisible");
+            Handle::default()
+        });
 
     commands
         .spawn_bundle(SpriteSheetBundle {
             sprite: TextureAtlasSprite {
-                custom_size: Some(Vec2::splat(TILE_SIZE)),
+                custom_size: Some(Vec2::splat(config.tile_size)),
                 ..default()
             },
             texture_atlas: gabe_atlas_handle,
             transform: Transform {
-                translation: Vec3::Z,
+                translation: config.player_start.extend(1.),
                 ..default()
             },
             ..default()
         })
         .insert(Name::new("Player"))
-        .insert(Player {
-            motion: MoveStatus::Stopped,
-            running: false,
-            speed: 4.,
-            run_speed: 6.,
+        .insert(Player::new(&config))
+        .insert(Motion::default())
+        .insert(Wallet::default())
+        .insert(Inventory::new(INVENTORY_SLOTS))
+        .insert(Health::new(config.player_health))
+        .insert(Hurtbox {
+            size: Vec2::splat(CHARACTER_HITBOX * config.tile_size),
+            team: Team::Player,
         })
-        .insert(AnimationTimer(Timer::from_seconds(TIMER_DURATION, true)));
+        .insert(AttackCooldown::default())
+        .insert(CharacterCollider)
+        .insert(AnimationTimer(Timer::from_seconds(config.animation_frame, true)));
 }
 
+#[allow(clippy::type_complexity)]
 fn player_movement(
     time: Res<Time>,
+    config: Res<GameConfig>,
     keyboard_input: Res<Input<KeyCode>>,
-    mut query: Query<(&mut Player, &mut AnimationTimer, &mut Transform)>,
-    wall_query: Query<&GlobalTransform, (With<TileCollider>, Without<Player>)>
+    mut query: Query<(
+        &mut Player,
+        &mut Motion,
+        &mut AnimationTimer,
+        &mut Transform,
+        Option<&Knockback>,
+        Option<&Noclip>,
+    )>,
+    wall_query: Query<&GlobalTransform, (With<TileCollider>, Without<Player>)>,
+    character_query: Query<&GlobalTransform, (With<CharacterCollider>, Without<Player>)>,
 ) {
-    let (mut player, mut timer, mut transform) = query.single_mut();
+    let (mut player, mut motion, mut timer, mut transform, knockback, noclip) = query.single_mut();
     let mut movement = Vec3::ZERO;
-    for code in keyboard_input.get_pressed() {
+    // No walking while being knocked back.
+    let pressed = keyboard_input.get_pressed().filter(|_| knockback.is_none());
+    for code in pressed {
         movement += match code {
             KeyCode::W => Vec3::Y,
             KeyCode::S => Vec3::NEG_Y,
@@ -98,57 +131,68 @@ fn player_movement(
             player.run_speed
         } else {
             player.speed
-        } * TILE_SIZE
+        } * config.tile_size
             * time.delta_seconds();
     }
     
-    if !wall_collision_check(transform.translation + Vec3::new(movement.x, 0., 0.), &wall_query) {
-        movement.x = 0.;
-    }
-    if !wall_collision_check(transform.translation + Vec3::new(0., movement.y, 0.), &wall_query) {
-        movement.y = 0.;
+    if noclip.is_none() {
+        if !wall_collision_check(transform.translation + Vec3::new(movement.x, 0., 0.), config.tile_size, &wall_query, &character_query) {
+            movement.x = 0.;
+        }
+        if !wall_collision_check(transform.translation + Vec3::new(0., movement.y, 0.), config.tile_size, &wall_query, &character_query) {
+            movement.y = 0.;
+        }
     }
 
-    player.motion = if movement.x > 0. {
-        MoveStatus::Moving(Direction::Right)
-    } else if movement.x < 0. {
-        MoveStatus::Moving(Direction::Left)
-    } else if movement.y > 0. {
-        MoveStatus::Moving(Direction::Up)
-    } else if movement.y < 0. {
-        MoveStatus::Moving(Direction::Down)
+    motion.update(movement);
+    player.running = motion.status != MoveStatus::Stopped && keyboard_input.pressed(KeyCode::LShift);
+    let frame = if player.running {
+        config.animation_frame * player.speed / player.run_speed
     } else {
-        MoveStatus::Stopped
+        config.animation_frame
     };
-    player.running = player.motion != MoveStatus::Stopped && keyboard_input.pressed(KeyCode::LShift);
-    if player.running {
-        timer.set_duration(Duration::from_millis(
-            (TIMER_DURATION * player.speed / player.run_speed * 1000.) as u64,
-        ));
-    } else {
-        timer.set_duration(Duration::from_millis((TIMER_DURATION * 1000.) as u64));
+    // A zero or broken speed, e.g. typed into the inspector, keeps the old pace.
+    if let Ok(frame) = Duration::try_from_secs_f32(frame) {
+        timer.set_duration(frame);
     }
     transform.translation += movement;
 }
 
+/// Puts a defeated player back at the start with full health.
+fn respawn_player(
+    mut commands: Commands,
+    mut died_events: EventReader<Died>,
+    asset_server: Res<AssetServer>,
+    config: Res<GameConfig>,
+    mut player_query: Query<(Entity, &mut Health, &mut Transform), With<Player>>,
+) {
+    for died in died_events.iter() {
+        let (entity, mut health, mut transform) = match player_query.get_mut(died.entity) {
+            Ok(player) => player,
+            Err(_) => continue,
+        };
+        health.current = health.max;
+        transform.translation = config.player_start.extend(1.);
+        commands.entity(entity).remove::<Knockback>();
+        spawn_toast(&mut commands, &asset_server, "You were knocked out!".to_string(), 0);
+    }
+}
+
 fn wall_collision_check(
     target_player_pos: Vec3,
+    tile_size: f32,
     wall_query: &Query<&GlobalTransform, (With<TileCollider>, Without<Player>)>,
+    character_query: &Query<&GlobalTransform, (With<CharacterCollider>, Without<Player>)>,
 ) -> bool {
-    for wall_transform in wall_query.iter() {
-        let collision = collide(
-            target_player_pos,
-            Vec2::splat(TILE_SIZE * 0.75),
-            wall_transform.translation(),
-            Vec2::splat(TILE_SIZE),
-        );
-        if collision.is_some() {
-            return false;
-        }
-    }
-    true
+    !collides(
+        target_player_pos,
+        wall_query.iter().map(GlobalTransform::translation),
+        character_query.iter().map(GlobalTransform::translation),
+        tile_size,
+    )
 }
 
+#[allow(clippy::type_complexity)]
 fn camera_follow(
     time: Res<Time>,
     player_query: Query<&Transform, With<Player>>,
@@ -172,32 +216,3 @@ fn camera_follow(
         }
     }
 }
-#[derive(Component, Deref, DerefMut)]
-struct AnimationTimer(Timer);
-
-fn player_animation(
-    time: Res<Time>,
-    texture_atlases: Res<Assets<TextureAtlas>>,
-    player_sheet: Res<PlayerAtlas>,
-    mut query: Query<(&Player, &mut AnimationTimer, &mut TextureAtlasSprite)>,
-) {
-    for (player, mut timer, mut sprite) in &mut query {
-        timer.tick(time.delta());
-        if timer.just_finished() {
-            let texture_atlas = texture_atlases.get(&player_sheet.0).unwrap();
-            sprite.index = if player.motion != MoveStatus::Stopped {
-                sprite.index + 1
-            } else {
-                0
-            } % texture_atlas.textures.len();
-            sprite.flip_x = match player.motion {
-                MoveStatus::Stopped => sprite.flip_x,
-                MoveStatus::Moving(direction) => match direction {
-                    Direction::Left => true,
-                    Direction::Right => false,
-                    _ => sprite.flip_x,
-                },
-            }
-        }
-    }
-}
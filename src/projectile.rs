@@ -0,0 +1,309 @@
+use bevy::{prelude::*, sprite::collide_aabb::collide};
+use bevy_inspector_egui::{egui, Context, Inspectable};
+use serde::Deserialize;
+
+use crate::{
+    combat::{Damage, Health, Hurtbox, Invulnerable, Team},
+    inspector::Fields,
+    tilemap::TileCollider,
+    AppState, GameConfig,
+};
+
+pub struct ProjectilePlugin;
+
+impl Plugin for ProjectilePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Shoot>()
+            .init_resource::<ProjectilePool>()
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(
+                        fire_projectiles
+                            .label("fire_projectiles")
+                            .after("mob_shoot")
+                            .after("boss_scripts"),
+                    )
+                    .with_system(
+                        move_projectiles
+                            .after("fire_projectiles")
+                            .before("apply_damage"),
+                    ),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::InGame).with_system(clear_projectiles));
+    }
+}
+
+/// What a projectile looks like and does, as written in the game's data files.
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProjectileDef {
+    /// Image under `assets`, tinted by `color`. Without one the projectile is a
+    /// plain square of `color`.
+    #[serde(default)]
+    pub image: Option<String>,
+    #[serde(default = "default_color")]
+    pub color: Color,
+    /// Width and height in tiles.
+    #[serde(default = "default_size")]
+    pub size: f32,
+    /// In tiles per second.
+    pub speed: f32,
+    /// Seconds the projectile flies before it drops.
+    #[serde(default = "default_lifetime")]
+    pub lifetime: f32,
+    /// How many hurtboxes it goes through before it stops at one.
+    #[serde(default)]
+    pub pierce: u32,
+    #[serde(default = "default_damage")]
+    pub damage: u32,
+    /// How far a hit pushes, in tiles.
+    #[serde(default = "default_knockback")]
+    pub knockback: f32,
+}
+
+impl ProjectileDef {
+    /// Catches values the projectile's timer and movement can't work with.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.speed.is_finite() || self.speed <= 0. {
+            return Err(format!("`speed` has to be above 0, not {}", self.speed));
+        }
+        if !self.lifetime.is_finite() || self.lifetime < 0. {
+            return Err(format!("`lifetime` can't be {}", self.lifetime));
+        }
+        Ok(())
+    }
+}
+
+fn default_color() -> Color {
+    Color::WHITE
+}
+
+fn default_size() -> f32 {
+    0.2
+}
+
+fn default_lifetime() -> f32 {
+    3.
+}
+
+fn default_damage() -> u32 {
+    1
+}
+
+fn default_knockback() -> f32 {
+    0.5
+}
+
+/// The directions a `Shoot` sends its projectiles, around where it aims.
+#[derive(Debug, Clone, Deserialize)]
+pub enum Pattern {
+    /// One projectile straight at the aim.
+    Aimed,
+    /// `count` projectiles fanned evenly over `degrees`, centred on the aim.
+    Spread { count: u32, degrees: f32 },
+    /// `count` projectiles all the way round, the first one at the aim.
+    Ring { count: u32 },
+}
+
+impl Pattern {
+    pub fn directions(&self, aim: Vec2) -> Vec<Vec2> {
+        let aim = aim.try_normalize().unwrap_or(Vec2::X);
+        let (count, first, step) = match *self {
+            Pattern::Aimed => (1, 0., 0.),
+            Pattern::Spread { count, degrees } if count > 1 => {
+                let degrees = degrees.to_radians();
+                (count, -degrees / 2., degrees / (count - 1) as f32)
+            }
+            Pattern::Spread { count, .. } => (count, 0., 0.),
+            Pattern::Ring { count } => (count, 0., std::f32::consts::TAU / count.max(1) as f32),
+        };
+        (0..count)
+            .map(|i| Vec2::from_angle(first + step * i as f32).rotate(aim))
+            .collect()
+    }
+}
+
+/// Fires projectiles from `position`, laid out by `pattern` around `aim`.
+pub struct Shoot {
+    pub position: Vec2,
+    pub aim: Vec2,
+    pub pattern: Pattern,
+    pub projectile: ProjectileDef,
+    /// Projectiles only hurt the other team.
+    pub team: Team,
+}
+
+/// A flying projectile. Spent ones are hidden and kept in the `ProjectilePool`
+/// rather than despawned.
+#[derive(Component)]
+pub struct Projectile {
+    pub team: Team,
+    pub velocity: Vec2,
+    pub size: Vec2,
+    pub damage: u32,
+    pub knockback: f32,
+    /// Hurtboxes left to go through.
+    pub pierce: u32,
+    lifetime: Timer,
+    /// Already hit, so piercing doesn't hit them again on the next frame.
+    hits: Vec<Entity>,
+    active: bool,
+}
+
+impl Inspectable for Projectile {
+    type Attributes = ();
+
+    fn ui(&mut self, ui: &mut egui::Ui, _: Self::Attributes, context: &mut Context) -> bool {
+        Fields::show(ui, context, |fields| {
+            fields
+                .edit("team", &mut self.team)
+                .edit("velocity", &mut self.velocity)
+                .edit("size", &mut self.size)
+                .edit("damage", &mut self.damage)
+                .edit("knockback", &mut self.knockback)
+                .edit("pierce", &mut self.pierce)
+                .timer("lifetime", &mut self.lifetime)
+                .show_debug("hits", &self.hits)
+                .edit("active", &mut self.active);
+        })
+    }
+}
+
+/// Spent projectile entities, waiting to be fired again.
+#[derive(Default)]
+pub struct ProjectilePool(Vec<Entity>);
+
+fn fire_projectiles(
+    mut commands: Commands,
+    mut shots: EventReader<Shoot>,
+    mut pool: ResMut<ProjectilePool>,
+    asset_server: Res<AssetServer>,
+    config: Res<GameConfig>,
+    mut query: Query<(
+        &mut Projectile,
+        &mut Transform,
+        &mut Sprite,
+        &mut Handle<Image>,
+        &mut Visibility,
+    )>,
+) {
+    for shot in shots.iter() {
+        let def = &shot.projectile;
+        let texture = def
+            .image
+            .as_ref()
+            .map_or_else(Handle::default, |path| asset_server.load(path.as_str()));
+        let size = Vec2::splat(def.size * config.tile_size);
+        for direction in shot.pattern.directions(shot.aim) {
+            let projectile = Projectile {
+                team: shot.team,
+                velocity: direction * def.speed * config.tile_size,
+                size,
+                damage: def.damage,
+                knockback: def.knockback,
+                pierce: def.pierce,
+                lifetime: Timer::from_seconds(def.lifetime, false),
+                hits: Vec::new(),
+                active: true,
+            };
+            let sprite = Sprite {
+                color: def.color,
+                custom_size: Some(size),
+                ..default()
+            };
+            let transform = Transform::from_translation(shot.position.extend(2.))
+                .with_rotation(Quat::from_rotation_z(Vec2::X.angle_between(direction)));
+
+            // Projectiles despawned by someone else may still be in the pool.
+            let reused = std::iter::from_fn(|| pool.0.pop()).find(|&entity| query.contains(entity));
+            match reused.and_then(|entity| query.get_mut(entity).ok()) {
+                Some((mut old, mut old_transform, mut old_sprite, mut old_texture, mut visibility)) => {
+                    *old = projectile;
+                    *old_transform = transform;
+                    *old_sprite = sprite;
+                    *old_texture = texture.clone();
+                    visibility.is_visible = true;
+                }
+                None => {
+                    commands
+                        .spawn_bundle(SpriteBundle {
+                            sprite,
+                            texture: texture.clone(),
+                            transform,
+                            ..default()
+                        })
+                        .insert(Name::new("Projectile"))
+                        .insert(projectile);
+                }
+            }
+        }
+    }
+}
+
+/// Flies projectiles, hurting the first hurtboxes they meet and stopping at
+/// walls.
+#[allow(clippy::type_complexity)]
+fn move_projectiles(
+    time: Res<Time>,
+    config: Res<GameConfig>,
+    mut damage_events: EventWriter<Damage>,
+    mut pool: ResMut<ProjectilePool>,
+    mut query: Query<(Entity, &mut Projectile, &mut Transform, &mut Visibility)>,
+    wall_query: Query<&GlobalTransform, With<TileCollider>>,
+    hurtbox_query: Query<(Entity, &GlobalTransform, &Hurtbox), (With<Health>, Without<Invulnerable>)>,
+) {
+    for (entity, mut projectile, mut transform, mut visibility) in &mut query {
+        if !projectile.active {
+            continue;
+        }
+        transform.translation += (projectile.velocity * time.delta_seconds()).extend(0.);
+        let position = transform.translation;
+        let size = projectile.size;
+
+        let mut spent = projectile.lifetime.tick(time.delta()).finished()
+            || wall_query
+                .iter()
+                .any(|wall| collide(position, size, wall.translation(), Vec2::splat(config.tile_size)).is_some());
+
+        for (target, target_transform, hurtbox) in &hurtbox_query {
+            if spent {
+                break;
+            }
+            if hurtbox.team == projectile.team
+                || projectile.hits.contains(&target)
+                || collide(position, size, target_transform.translation(), hurtbox.size).is_none()
+            {
+                continue;
+            }
+            damage_events.send(Damage {
+                target,
+                amount: projectile.damage,
+                knockback: projectile.velocity.normalize_or_zero() * projectile.knockback,
+            });
+            projectile.hits.push(target);
+            match projectile.pierce.checked_sub(1) {
+                Some(pierce) => projectile.pierce = pierce,
+                None => spent = true,
+            }
+        }
+
+        if spent {
+            projectile.active = false;
+            visibility.is_visible = false;
+            pool.0.push(entity);
+        }
+    }
+}
+
+/// Drops every projectile in flight when the game is left.
+fn clear_projectiles(
+    mut pool: ResMut<ProjectilePool>,
+    mut query: Query<(Entity, &mut Projectile, &mut Visibility)>,
+) {
+    for (entity, mut projectile, mut visibility) in &mut query {
+        if projectile.active {
+            projectile.active = false;
+            visibility.is_visible = false;
+            pool.0.push(entity);
+        }
+    }
+}
@@ -0,0 +1,513 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    boss::Boss,
+    chest::OpenedChests,
+    combat::Health,
+    dialogue::GameFlags,
+    inventory::{spawn_toast, Inventory, ItemStack},
+    player::Player,
+    projectile::Projectile,
+    shop::Wallet,
+    tilemap::{LoadMap, MapData, MapLayer, TileEdit},
+    AppState, GameConfig,
+};
+
+pub struct SavePlugin;
+
+impl Plugin for SavePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SaveSlot>()
+            .add_system_set(SystemSet::on_update(AppState::MainMenu).with_system(load_from_menu))
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(save_game.after("player_movement"))
+                    .with_system(load_maps)
+                    .with_system(restore_player),
+            );
+    }
+}
+
+/// Saves the game to the current slot.
+pub const SAVE_KEY: KeyCode = KeyCode::F5;
+
+/// Keys loading each slot from the main menu. With Shift held in game they
+/// save to that slot, which becomes the current one.
+pub const SLOT_KEYS: [KeyCode; 3] = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3];
+
+const SAVE_DIR: &str = "saves";
+
+/// Written on the first line of every save, before its version and checksum.
+const SAVE_MAGIC: &str = "RPGSAVE";
+
+/// The version `SaveGame` is written as. Bump it, and add a migration, when
+/// a change to `SaveGame` would make older saves fail to load.
+pub const SAVE_VERSION: u32 = 1;
+
+/// Upgrades the RON of a save by one version; `MIGRATIONS[0]` takes a version
+/// 1 save to version 2, and so on.
+type Migration = fn(ron::Value) -> Result<ron::Value, SaveError>;
+
+const MIGRATIONS: &[Migration] = &[];
+
+// A version bump without its migration would fail to read every older save.
+const _: () = assert!(MIGRATIONS.len() as u32 + 1 == SAVE_VERSION);
+
+/// The slot F5 saves to: the one last loaded or saved to, or slot 0 for a
+/// new game.
+#[derive(Debug, Default)]
+pub struct SaveSlot(pub usize);
+
+/// Everything that outlives a session.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SaveGame {
+    pub map: String,
+    pub player: PlayerSave,
+    pub flags: GameFlags,
+    pub opened_chests: OpenedChests,
+    /// Tiles that differ from the map's files, by layer. Kept free of enums,
+    /// which `ron::Value` can't carry through migrations.
+    pub background: Vec<SavedTile>,
+    pub foreground: Vec<SavedTile>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SavedTile {
+    pub position: (usize, usize),
+    pub tile: char,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerSave {
+    pub position: (f32, f32),
+    pub health: u32,
+    pub max_health: u32,
+    pub speed: f32,
+    pub run_speed: f32,
+    pub coins: u32,
+    pub inventory: Vec<Option<ItemStack>>,
+}
+
+impl PlayerSave {
+    pub fn new(
+        transform: &Transform,
+        player: &Player,
+        health: &Health,
+        wallet: &Wallet,
+        inventory: &Inventory,
+    ) -> PlayerSave {
+        PlayerSave {
+            position: (transform.translation.x, transform.translation.y),
+            health: health.current,
+            max_health: health.max,
+            speed: player.speed,
+            run_speed: player.run_speed,
+            coins: wallet.coins,
+            inventory: inventory.slots().to_vec(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    /// The file isn't a save, or was changed since it was written.
+    Corrupted(String),
+    /// Written by a newer version of the game.
+    TooNew(u32),
+    /// The map the save was made on is gone from `assets/maps`.
+    MissingMap(String),
+    Ron(ron::Error),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Io(err) => write!(f, "Could not access the save: {err}"),
+            SaveError::Corrupted(reason) => write!(f, "The save is corrupted: {reason}"),
+            SaveError::TooNew(version) => write!(
+                f,
+                "The save is from a newer version of the game ({version}, this one reads up to {SAVE_VERSION})"
+            ),
+            SaveError::MissingMap(map) => write!(f, "The save is on map `{map}`, which no longer exists"),
+            SaveError::Ron(err) => write!(f, "The save could not be read: {err}"),
+        }
+    }
+}
+
+impl From<io::Error> for SaveError {
+    fn from(err: io::Error) -> Self {
+        SaveError::Io(err)
+    }
+}
+
+impl From<ron::Error> for SaveError {
+    fn from(err: ron::Error) -> Self {
+        SaveError::Ron(err)
+    }
+}
+
+pub fn slot_path(slot: usize) -> PathBuf {
+    Path::new(SAVE_DIR).join(format!("slot{}.ron", slot + 1))
+}
+
+/// FNV-1a, to notice saves that were cut short or edited by hand.
+fn checksum(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Runs the migrations taking a `version` save to the latest version, the
+/// last of `migrations` producing it.
+fn migrate(mut value: ron::Value, version: u32, migrations: &[Migration]) -> Result<ron::Value, SaveError> {
+    for migration in &migrations[version as usize - 1..] {
+        value = migration(value)?;
+    }
+    Ok(value)
+}
+
+impl SaveGame {
+    /// The save as written to disk: a header line with the version and the
+    /// checksum of the RON body that follows.
+    pub fn encode(&self) -> Result<String, SaveError> {
+        let body = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        Ok(format!(
+            "{SAVE_MAGIC} {SAVE_VERSION} {:016x}\n{body}",
+            checksum(body.as_bytes())
+        ))
+    }
+
+    /// Reads a save written by `encode`, migrating it from older versions.
+    pub fn decode(text: &str) -> Result<SaveGame, SaveError> {
+        let (header, body) = text
+            .split_once('\n')
+            .ok_or_else(|| SaveError::Corrupted("no header".to_string()))?;
+        let fields: Vec<&str> = header.split(' ').collect();
+        let (version, sum) = match fields[..] {
+            [SAVE_MAGIC, version, sum] => (version, sum),
+            _ => return Err(SaveError::Corrupted("not a save file".to_string())),
+        };
+        let version: u32 = version
+            .parse()
+            .map_err(|_| SaveError::Corrupted(format!("bad version `{version}`")))?;
+        let sum = u64::from_str_radix(sum, 16)
+            .map_err(|_| SaveError::Corrupted(format!("bad checksum `{sum}`")))?;
+        if sum != checksum(body.as_bytes()) {
+            return Err(SaveError::Corrupted("checksum mismatch".to_string()));
+        }
+        if version == 0 {
+            return Err(SaveError::Corrupted("bad version `0`".to_string()));
+        }
+        if version > SAVE_VERSION {
+            return Err(SaveError::TooNew(version));
+        }
+
+        let value = migrate(ron::from_str(body)?, version, MIGRATIONS)?;
+        let save: SaveGame = value.into_rust()?;
+        if !MapData::exists(&save.map) {
+            return Err(SaveError::MissingMap(save.map));
+        }
+        Ok(save)
+    }
+
+    pub fn write(&self, slot: usize) -> Result<(), SaveError> {
+        fs::create_dir_all(SAVE_DIR)?;
+        // Written aside first, so a crash mid-write leaves the old save intact.
+        let path = slot_path(slot);
+        let temp = path.with_extension("ron.tmp");
+        fs::write(&temp, self.encode()?)?;
+        fs::rename(temp, path)?;
+        Ok(())
+    }
+
+    pub fn read(slot: usize) -> Result<SaveGame, SaveError> {
+        SaveGame::decode(&fs::read_to_string(slot_path(slot))?)
+    }
+}
+
+/// A loaded save's player, applied once the player has spawned.
+struct PendingPlayer(PlayerSave);
+
+#[allow(clippy::too_many_arguments)]
+fn save_game(
+    mut commands: Commands,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    asset_server: Res<AssetServer>,
+    mut current_slot: ResMut<SaveSlot>,
+    map: Res<MapData>,
+    flags: Res<GameFlags>,
+    opened_chests: Res<OpenedChests>,
+    player_query: Query<(&Transform, &Player, &Health, &Wallet, &Inventory)>,
+    boss_query: Query<&Boss>,
+) {
+    let shift = keyboard_input.any_pressed([KeyCode::LShift, KeyCode::RShift]);
+    let picked = SLOT_KEYS.iter().position(|key| keyboard_input.just_pressed(*key));
+    let slot = match picked {
+        Some(slot) if shift => {
+            keyboard_input.reset(SLOT_KEYS[slot]);
+            slot
+        }
+        _ if keyboard_input.just_pressed(SAVE_KEY) => {
+            keyboard_input.reset(SAVE_KEY);
+            current_slot.0
+        }
+        _ => return,
+    };
+    // The arena's closed exits would be saved as part of the map.
+    if boss_query.iter().any(Boss::is_engaged) {
+        spawn_toast(&mut commands, &asset_server, "You can't save during a fight.".to_string(), 0);
+        return;
+    }
+    let (transform, player, health, wallet, inventory) = match player_query.get_single() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+
+    let changes = map.changes_from(&MapData::load(&map.name, map.tile_size));
+    let save = SaveGame {
+        map: map.name.clone(),
+        player: PlayerSave::new(transform, player, health, wallet, inventory),
+        flags: GameFlags(flags.0.clone()),
+        opened_chests: OpenedChests(opened_chests.0.clone()),
+        background: saved_tiles(&changes, MapLayer::Background),
+        foreground: saved_tiles(&changes, MapLayer::Foreground),
+    };
+    let text = match save.write(slot) {
+        Ok(()) => {
+            current_slot.0 = slot;
+            format!("Saved to slot {}.", slot + 1)
+        }
+        Err(err) => {
+            error!("Could not save to `{}`: {err}", slot_path(slot).display());
+            err.to_string()
+        }
+    };
+    spawn_toast(&mut commands, &asset_server, text, 0);
+}
+
+fn saved_tiles(changes: &[TileEdit], layer: MapLayer) -> Vec<SavedTile> {
+    changes
+        .iter()
+        .filter(|edit| edit.layer == layer)
+        .map(|edit| SavedTile {
+            position: edit.position,
+            tile: edit.tile,
+        })
+        .collect()
+}
+
+/// Loads a slot picked from the main menu into the world before the game
+/// starts, so everything spawns the way it was saved.
+fn load_from_menu(
+    mut commands: Commands,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    mut state: ResMut<State<AppState>>,
+    asset_server: Res<AssetServer>,
+    config: Res<GameConfig>,
+) {
+    let slot = match SLOT_KEYS.iter().position(|key| keyboard_input.just_pressed(*key)) {
+        Some(slot) => slot,
+        None => return,
+    };
+    keyboard_input.reset(SLOT_KEYS[slot]);
+    let save = match SaveGame::read(slot) {
+        Ok(save) => save,
+        Err(err) => {
+            error!("Could not load `{}`: {err}", slot_path(slot).display());
+            spawn_toast(&mut commands, &asset_server, err.to_string(), 0);
+            return;
+        }
+    };
+
+    let mut map = MapData::load(&save.map, config.tile_size);
+    for (layer, tiles) in [
+        (MapLayer::Background, &save.background),
+        (MapLayer::Foreground, &save.foreground),
+    ] {
+        for saved in tiles {
+            map.set(layer, saved.position.0, saved.position.1, saved.tile);
+        }
+    }
+    commands.insert_resource(map);
+    commands.insert_resource(save.flags);
+    commands.insert_resource(save.opened_chests);
+    commands.insert_resource(PendingPlayer(save.player));
+    commands.insert_resource(SaveSlot(slot));
+    state.set(AppState::InGame).unwrap();
+}
+
+/// Tears the world down and starts the game over on another map, the way a
+/// save is loaded, with the player carried over to the map's start.
+#[allow(clippy::type_complexity)]
+fn load_maps(
+    mut commands: Commands,
+    mut events: EventReader<LoadMap>,
+    mut state: ResMut<State<AppState>>,
+    config: Res<GameConfig>,
+    player_query: Query<(&Transform, &Player, &Health, &Wallet, &Inventory)>,
+    // Pooled projectiles are kept, they are cleared when leaving the game.
+    root_query: Query<Entity, (Without<Parent>, Without<Camera>, Without<Projectile>)>,
+) {
+    let name = match events.iter().last() {
+        Some(LoadMap(name)) => name,
+        None => return,
+    };
+    if !MapData::exists(name) {
+        error!("No map `{name}` in `assets/maps`");
+        return;
+    }
+    if let Err(err) = state.restart() {
+        error!("Could not load map `{name}`: {err:?}");
+        return;
+    }
+    if let Ok((transform, player, health, wallet, inventory)) = player_query.get_single() {
+        let mut save = PlayerSave::new(transform, player, health, wallet, inventory);
+        save.position = config.player_start.into();
+        commands.insert_resource(PendingPlayer(save));
+    }
+    for entity in &root_query {
+        commands.entity(entity).despawn_recursive();
+    }
+    // The restart runs every `on_enter(AppState::InGame)` spawner again.
+    commands.insert_resource(MapData::load(name, config.tile_size));
+}
+
+fn restore_player(
+    mut commands: Commands,
+    pending: Option<Res<PendingPlayer>>,
+    mut player_query: Query<(&mut Transform, &mut Player, &mut Health, &mut Wallet, &mut Inventory)>,
+) {
+    let pending = match pending {
+        Some(pending) => pending,
+        None => return,
+    };
+    let (mut transform, mut player, mut health, mut wallet, mut inventory) = match player_query.get_single_mut() {
+        Ok(player) => player,
+        Err(_) => return,
+    };
+    let save = &pending.0;
+    transform.translation.x = save.position.0;
+    transform.translation.y = save.position.1;
+    player.speed = save.speed;
+    player.run_speed = save.run_speed;
+    *health = Health {
+        current: save.health,
+        max: save.max_health,
+    };
+    wallet.coins = save.coins;
+    *inventory = Inventory::from_slots(save.inventory.clone());
+    commands.remove_resource::<PendingPlayer>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn save() -> SaveGame {
+        SaveGame {
+            map: "path".to_string(),
+            player: PlayerSave {
+                position: (0.4, -1.2),
+                health: 3,
+                max_health: 6,
+                speed: 4.,
+                run_speed: 6.,
+                coins: 25,
+                inventory: vec![
+                    Some(ItemStack {
+                        item: "potion".to_string(),
+                        count: 2,
+                    }),
+                    None,
+                ],
+            },
+            flags: GameFlags(["met_guard".to_string()].into_iter().collect()),
+            opened_chests: OpenedChests::default(),
+            background: vec![SavedTile {
+                position: (3, 4),
+                tile: 'd',
+            }],
+            foreground: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn encode_writes_the_header() {
+        let text = save().encode().unwrap();
+        let (header, body) = text.split_once('\n').unwrap();
+        assert_eq!(
+            header,
+            format!("{SAVE_MAGIC} {SAVE_VERSION} {:016x}", checksum(body.as_bytes()))
+        );
+    }
+
+    #[test]
+    fn decode_reads_what_encode_wrote() {
+        let decoded = SaveGame::decode(&save().encode().unwrap()).unwrap();
+        assert_eq!(decoded.map, "path");
+        assert_eq!(decoded.player.position, (0.4, -1.2));
+        assert_eq!(decoded.player.coins, 25);
+        assert_eq!(decoded.player.inventory, save().player.inventory);
+        assert!(decoded.flags.contains("met_guard"));
+        assert_eq!(decoded.background[0].position, (3, 4));
+        assert_eq!(decoded.background[0].tile, 'd');
+    }
+
+    #[test]
+    fn decode_rejects_edited_saves() {
+        let text = save().encode().unwrap().replace("coins: 25", "coins: 9999");
+        assert!(matches!(SaveGame::decode(&text), Err(SaveError::Corrupted(_))));
+    }
+
+    #[test]
+    fn decode_rejects_bad_headers() {
+        let body = save().encode().unwrap().split_once('\n').unwrap().1.to_string();
+        let sum = checksum(body.as_bytes());
+        let with_header = |header: String| format!("{header}\n{body}");
+
+        assert!(matches!(SaveGame::decode(&body), Err(SaveError::Corrupted(_))));
+        let text = with_header(format!("NOTASAVE {SAVE_VERSION} {sum:016x}"));
+        assert!(matches!(SaveGame::decode(&text), Err(SaveError::Corrupted(_))));
+        let text = with_header(format!("{SAVE_MAGIC} 0 {sum:016x}"));
+        assert!(matches!(SaveGame::decode(&text), Err(SaveError::Corrupted(_))));
+        let text = with_header(format!("{SAVE_MAGIC} {} {sum:016x}", SAVE_VERSION + 1));
+        assert!(matches!(SaveGame::decode(&text), Err(SaveError::TooNew(_))));
+    }
+
+    #[test]
+    fn decode_rejects_saves_on_missing_maps() {
+        let mut save = save();
+        save.map = "no-such-map".to_string();
+        let text = save.encode().unwrap();
+        assert!(matches!(SaveGame::decode(&text), Err(SaveError::MissingMap(map)) if map == "no-such-map"));
+    }
+
+    fn append(value: ron::Value, step: &str) -> ron::Value {
+        match value {
+            ron::Value::Seq(mut steps) => {
+                steps.push(ron::Value::String(step.to_string()));
+                ron::Value::Seq(steps)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn migrate_runs_the_migrations_from_the_save_version_on() {
+        let migrations: &[Migration] = &[|value| Ok(append(value, "1 to 2")), |value| Ok(append(value, "2 to 3"))];
+        let steps = |version| match migrate(ron::Value::Seq(Vec::new()), version, migrations).unwrap() {
+            ron::Value::Seq(steps) => steps.len(),
+            _ => unreachable!(),
+        };
+        assert_eq!(steps(1), 2);
+        assert_eq!(steps(2), 1);
+        assert_eq!(steps(3), 0);
+    }
+}
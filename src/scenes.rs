@@ -0,0 +1,115 @@
+use std::{any::TypeId, fs, path::Path};
+
+use bevy::{prelude::*, reflect::TypeRegistryArc, scene::DynamicEntity};
+
+/// Writes the world, or parts of it, out as `.scn.ron` scenes and spawns
+/// them back, for layouts tweaked by hand in the inspector.
+pub struct SceneExportPlugin;
+
+impl Plugin for SceneExportPlugin {
+    fn build(&self, app: &mut App) {
+        // Bevy doesn't register atlas sprites as reflected components, which
+        // would leave exported maps invisible.
+        app.register_type::<Option<Vec2>>()
+            .register_type::<TextureAtlasSprite>()
+            .register_type_data::<TextureAtlasSprite, ReflectComponent>()
+            .add_event::<ExportScene>()
+            .add_event::<SpawnScene>()
+            .add_system(export_scenes.exclusive_system())
+            .add_system(spawn_scenes);
+    }
+}
+
+/// Scenes live under `assets/` so the asset server can load them back.
+pub const SCENES_DIR: &str = "scenes";
+
+/// Exports `root` and everything under it, or the whole world without a
+/// root, to `assets/scenes/<name>.scn.ron`.
+pub struct ExportScene {
+    pub root: Option<Entity>,
+    pub name: String,
+}
+
+/// Spawns `assets/scenes/<name>.scn.ron` into the world.
+pub struct SpawnScene {
+    pub name: String,
+}
+
+/// Path of a scene, relative to `assets`.
+pub fn scene_path(name: &str) -> String {
+    format!("{SCENES_DIR}/{name}.scn.ron")
+}
+
+/// Every reflected component of `root` and its descendants, or of the whole
+/// world without a root. The root's `Parent` is left out, so the subtree
+/// spawns back on its own.
+pub fn dynamic_scene(world: &World, root: Option<Entity>) -> DynamicScene {
+    let registry = world.resource::<TypeRegistryArc>();
+    let root = match root {
+        Some(root) => root,
+        None => return DynamicScene::from_world(world, registry),
+    };
+    let registry = registry.read();
+
+    let mut scene = DynamicScene::default();
+    let mut stack = vec![root];
+    while let Some(entity) = stack.pop() {
+        if let Some(children) = world.get::<Children>(entity) {
+            stack.extend(children.iter().copied());
+        }
+        let archetype = match world.entities().get(entity) {
+            Some(location) => &world.archetypes()[location.archetype_id],
+            None => continue,
+        };
+        let components = archetype
+            .components()
+            .filter_map(|id| {
+                let type_id = world.components().get_info(id)?.type_id()?;
+                if entity == root && type_id == TypeId::of::<Parent>() {
+                    return None;
+                }
+                let reflect_component = registry.get(type_id)?.data::<ReflectComponent>()?;
+                Some(reflect_component.reflect(world, entity)?.clone_value())
+            })
+            .collect();
+        scene.entities.push(DynamicEntity {
+            entity: entity.id(),
+            components,
+        });
+    }
+    scene
+}
+
+fn export_scenes(world: &mut World) {
+    let exports: Vec<ExportScene> = world.resource_mut::<Events<ExportScene>>().drain().collect();
+    for export in exports {
+        let path = scene_path(&export.name);
+        let scene = dynamic_scene(world, export.root);
+        let written = scene
+            .serialize_ron(world.resource::<TypeRegistryArc>())
+            .map_err(|err| err.to_string())
+            .and_then(|ron| {
+                let file = Path::new("assets").join(&path);
+                fs::create_dir_all(file.parent().unwrap_or(Path::new("."))).map_err(|err| err.to_string())?;
+                fs::write(file, ron).map_err(|err| err.to_string())
+            });
+        match written {
+            Ok(()) => {
+                info!("Exported {} entities to `assets/{path}`", scene.entities.len());
+                // Picks up the new file if the scene was spawned before.
+                world.resource::<AssetServer>().reload_asset(path.as_str());
+            }
+            Err(err) => error!("Could not export `assets/{path}`: {err}"),
+        }
+    }
+}
+
+fn spawn_scenes(
+    mut events: EventReader<SpawnScene>,
+    asset_server: Res<AssetServer>,
+    mut scene_spawner: ResMut<SceneSpawner>,
+) {
+    for event in events.iter() {
+        scene_spawner.spawn_dynamic(asset_server.load(&scene_path(&event.name)));
+    }
+}
@@ -0,0 +1,194 @@
+use std::{fs, time::Duration};
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    utils::BoxedFuture,
+};
+
+use crate::{
+    player::Player,
+    tilemap::{LoadMap, MapData},
+    CameraProperties, CameraTimer, GameConfig,
+};
+
+/// Reads `GameConfig` from `assets/game.settings.ron`, keeps it in sync with
+/// the file in debug builds and writes it back on `SaveSettings`.
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        // Read right away rather than through the asset server, so startup
+        // systems like the camera already see the file's values.
+        if !app.world.contains_resource::<GameConfig>() {
+            let config = match GameConfig::read(SETTINGS_PATH) {
+                Ok(config) => config,
+                Err(err) => {
+                    warn!("Could not read `assets/{SETTINGS_PATH}`, using the defaults: {err}");
+                    GameConfig::default()
+                }
+            };
+            app.insert_resource(config);
+        }
+        app.add_event::<SaveSettings>()
+            .add_system(apply_settings.label("apply_settings"))
+            .add_system(save_settings);
+        if cfg!(debug_assertions) {
+            app.add_asset::<GameConfig>()
+                .init_asset_loader::<SettingsLoader>()
+                .add_startup_system(watch_settings)
+                .add_system(reload_settings.before("apply_settings"));
+        }
+    }
+}
+
+/// Relative to `assets`.
+pub const SETTINGS_PATH: &str = "game.settings.ron";
+
+/// Writes the current `GameConfig` to `assets/game.settings.ron`.
+pub struct SaveSettings;
+
+impl GameConfig {
+    pub fn read(path: &str) -> Result<GameConfig, String> {
+        let text = fs::read_to_string(format!("assets/{path}")).map_err(|err| err.to_string())?;
+        let config: GameConfig = ron::from_str(&text).map_err(|err| err.to_string())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Rejects values the game can't run with, like a zero speed, a negative
+    /// time or a missing map, naming the first bad field.
+    pub fn validate(&self) -> Result<(), String> {
+        let positive = [
+            ("resolution", self.resolution),
+            ("tile_size", self.tile_size),
+            ("player_speed", self.player_speed),
+            ("player_run_speed", self.player_run_speed),
+            ("animation_frame", self.animation_frame),
+            ("camera_step", self.camera_step),
+        ];
+        for (name, value) in positive {
+            if !value.is_finite() || value <= 0. {
+                return Err(format!("`{name}` has to be above 0, not {value}"));
+            }
+        }
+        if !self.camera_follow_distance.is_finite() || self.camera_follow_distance < 0. {
+            return Err(format!(
+                "`camera_follow_distance` can't be negative, not {}",
+                self.camera_follow_distance
+            ));
+        }
+        if !self.player_start.is_finite() {
+            return Err(format!("`player_start` has to be finite, not {}", self.player_start));
+        }
+        if !MapData::exists(&self.map) {
+            return Err(format!("`map` has to be a map under `assets/maps`, not `{}`", self.map));
+        }
+        Ok(())
+    }
+
+    pub fn write(&self, path: &str) -> Result<(), String> {
+        let text = ron::ser::to_string_pretty(self, default()).map_err(|err| err.to_string())?;
+        fs::write(format!("assets/{path}"), text + "\n").map_err(|err| err.to_string())
+    }
+}
+
+#[derive(Default)]
+pub struct SettingsLoader;
+
+impl AssetLoader for SettingsLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let config: GameConfig = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(config));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["settings.ron"]
+    }
+}
+
+/// Keeps the settings file loaded so the asset server notices it changing.
+struct SettingsHandle(Handle<GameConfig>);
+
+fn watch_settings(mut commands: Commands, asset_server: Res<AssetServer>) {
+    commands.insert_resource(SettingsHandle(asset_server.load(SETTINGS_PATH)));
+}
+
+fn reload_settings(
+    mut events: EventReader<AssetEvent<GameConfig>>,
+    handle: Res<SettingsHandle>,
+    assets: Res<Assets<GameConfig>>,
+    mut config: ResMut<GameConfig>,
+) {
+    for event in events.iter() {
+        match event {
+            AssetEvent::Modified { handle: modified } if *modified == handle.0 => {}
+            _ => continue,
+        }
+        let reloaded = match assets.get(&handle.0) {
+            Some(reloaded) => reloaded,
+            None => continue,
+        };
+        match reloaded.validate() {
+            Ok(()) => {
+                info!("Reloaded `assets/{SETTINGS_PATH}`");
+                *config = reloaded.clone();
+            }
+            Err(err) => warn!("Keeping the old settings, `assets/{SETTINGS_PATH}` is invalid: {err}"),
+        }
+    }
+}
+
+/// Pushes changed settings to what was spawned with the old ones. The map,
+/// start position and health only apply from the next game on, and a new tile
+/// size reloads the current map at that scale.
+fn apply_settings(
+    config: Res<GameConfig>,
+    map: Option<Res<MapData>>,
+    mut load_map: EventWriter<LoadMap>,
+    mut player_query: Query<&mut Player>,
+    mut camera_query: Query<(
+        &mut OrthographicProjection,
+        &mut CameraProperties,
+        &mut CameraTimer,
+    )>,
+) {
+    if !config.is_changed() || config.is_added() {
+        return;
+    }
+    if let Some(map) = map.filter(|map| map.tile_size != config.tile_size) {
+        load_map.send(LoadMap(map.name.clone()));
+    }
+    for mut player in &mut player_query {
+        player.speed = config.player_speed;
+        player.run_speed = config.player_run_speed;
+    }
+    for (mut projection, mut properties, mut timer) in &mut camera_query {
+        projection.left = -config.resolution;
+        projection.right = config.resolution;
+        properties.follow_distance = config.camera_follow_distance;
+        if let Ok(step) = Duration::try_from_secs_f32(config.camera_step) {
+            timer.set_duration(step);
+        }
+    }
+}
+
+fn save_settings(mut events: EventReader<SaveSettings>, config: Res<GameConfig>) {
+    for _ in events.iter() {
+        if let Err(err) = config.validate() {
+            error!("Not saving `assets/{SETTINGS_PATH}`, the settings are invalid: {err}");
+            continue;
+        }
+        match config.write(SETTINGS_PATH) {
+            Ok(()) => info!("Saved settings to `assets/{SETTINGS_PATH}`"),
+            Err(err) => error!("Could not save `assets/{SETTINGS_PATH}`: {err}"),
+        }
+    }
+}
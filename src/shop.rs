@@ -0,0 +1,652 @@
+use std::fmt;
+
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    utils::BoxedFuture,
+};
+use bevy_inspector_egui::{egui, Context, Inspectable};
+use serde::Deserialize;
+
+use crate::{
+    dialogue::DialogueEvent,
+    inspector::Fields,
+    inventory::{Inventory, InventoryError},
+    items::{ItemIcons, Items},
+    loading::LoadingAssets,
+    player::Player,
+    tilemap::{MapData, MapObject},
+    AppState,
+};
+
+pub struct ShopPlugin;
+
+impl Plugin for ShopPlugin {
+    fn build(&self, app: &mut App) {
+        app.register_type::<Wallet>()
+            .add_asset::<Shop>()
+            .init_asset_loader::<ShopLoader>()
+            .add_system_set(SystemSet::on_enter(AppState::Loading).with_system(load_shops))
+            .add_system_set(
+                SystemSet::on_update(AppState::InGame)
+                    .with_system(open_shop.after("player_movement")),
+            )
+            .add_system_set(SystemSet::on_enter(AppState::Shop).with_system(spawn_shop_menu))
+            .add_system_set(
+                SystemSet::on_update(AppState::Shop)
+                    .with_system(shop_input.label("shop_input"))
+                    .with_system(update_shop_menu.after("shop_input")),
+            )
+            .add_system_set(SystemSet::on_exit(AppState::Shop).with_system(despawn_shop_menu));
+    }
+}
+
+/// Dialogue event that opens the speaking NPC's shop.
+pub const OPEN_SHOP_EVENT: &str = "open_shop";
+
+const STARTING_COINS: u32 = 50;
+const MAX_QUANTITY: u32 = 99;
+
+/// What a vendor trades, read from a `.shop.ron` file.
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "5b0e3f0c-52a4-4d86-9a39-4e0f3c6b7d21"]
+pub struct Shop {
+    pub name: String,
+    pub stock: Vec<StockEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StockEntry {
+    /// Id of the item in the item database.
+    pub item: String,
+    /// What the player pays for one.
+    pub buy_price: u32,
+    /// What the vendor pays the player for one.
+    pub sell_price: u32,
+    /// How many the vendor has, `None` if they never run out.
+    #[serde(default)]
+    pub quantity: Option<u32>,
+}
+
+#[derive(Default)]
+pub struct ShopLoader;
+
+impl AssetLoader for ShopLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let shop: Shop = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(shop));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["shop.ron"]
+    }
+}
+
+/// An NPC the player can trade with.
+#[derive(Component)]
+pub struct Vendor {
+    pub shop: Handle<Shop>,
+    /// What's left of each stock entry, filled in the first time the shop opens.
+    remaining: Option<Vec<Option<u32>>>,
+}
+
+impl Inspectable for Vendor {
+    type Attributes = ();
+
+    fn ui(&mut self, ui: &mut egui::Ui, _: Self::Attributes, context: &mut Context) -> bool {
+        Fields::show(ui, context, |fields| {
+            fields.asset("shop", &self.shop).show_debug("remaining", &self.remaining);
+        })
+    }
+}
+
+impl Vendor {
+    pub fn new(shop: Handle<Shop>) -> Vendor {
+        Vendor {
+            shop,
+            remaining: None,
+        }
+    }
+}
+
+/// The player's money.
+#[derive(Component, Inspectable, Reflect)]
+#[reflect(Component)]
+pub struct Wallet {
+    pub coins: u32,
+}
+
+impl Default for Wallet {
+    fn default() -> Self {
+        Wallet {
+            coins: STARTING_COINS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TradeError {
+    NotEnoughCoins,
+    OutOfStock,
+    UnknownItem,
+    InventoryFull,
+    NothingToSell,
+    TooManyCoins,
+}
+
+impl fmt::Display for TradeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            TradeError::NotEnoughCoins => "You can't afford that.",
+            TradeError::OutOfStock => "I don't have that many.",
+            TradeError::UnknownItem => "I don't deal in that.",
+            TradeError::InventoryFull => "You can't carry that many.",
+            TradeError::NothingToSell => "You don't have that many.",
+            TradeError::TooManyCoins => "Your wallet can't hold that much.",
+        })
+    }
+}
+
+/// Buys `quantity` of `entry`, returning what it cost. Nothing changes unless
+/// the whole trade goes through.
+pub fn buy(
+    entry: &StockEntry,
+    remaining: &mut Option<u32>,
+    quantity: u32,
+    wallet: &mut Wallet,
+    inventory: &mut Inventory,
+    items: &Items,
+) -> Result<u32, TradeError> {
+    let cost = entry
+        .buy_price
+        .checked_mul(quantity)
+        .ok_or(TradeError::NotEnoughCoins)?;
+    let coins = wallet
+        .coins
+        .checked_sub(cost)
+        .ok_or(TradeError::NotEnoughCoins)?;
+    let left = match *remaining {
+        Some(left) => Some(left.checked_sub(quantity).ok_or(TradeError::OutOfStock)?),
+        None => None,
+    };
+    inventory
+        .add(&entry.item, quantity, items)
+        .map_err(|err| match err {
+            InventoryError::UnknownItem => TradeError::UnknownItem,
+            _ => TradeError::InventoryFull,
+        })?;
+
+    wallet.coins = coins;
+    *remaining = left;
+    Ok(cost)
+}
+
+/// Sells `quantity` of `entry` back to the vendor, returning what it earned.
+/// Nothing changes unless the whole trade goes through.
+pub fn sell(
+    entry: &StockEntry,
+    remaining: &mut Option<u32>,
+    quantity: u32,
+    wallet: &mut Wallet,
+    inventory: &mut Inventory,
+) -> Result<u32, TradeError> {
+    if inventory.count(&entry.item) < quantity {
+        return Err(TradeError::NothingToSell);
+    }
+    let earned = entry
+        .sell_price
+        .checked_mul(quantity)
+        .ok_or(TradeError::TooManyCoins)?;
+    let coins = wallet
+        .coins
+        .checked_add(earned)
+        .ok_or(TradeError::TooManyCoins)?;
+
+    inventory
+        .remove(&entry.item, quantity)
+        .map_err(|_| TradeError::NothingToSell)?;
+
+    wallet.coins = coins;
+    *remaining = remaining.map(|left| left.saturating_add(quantity));
+    Ok(earned)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TradeMode {
+    Buy,
+    Sell,
+}
+
+/// The shop currently on screen.
+struct ActiveShop {
+    vendor: Entity,
+    mode: TradeMode,
+    selected: usize,
+    quantity: u32,
+    message: String,
+}
+
+#[derive(Component)]
+struct ShopMenu;
+
+#[derive(Component)]
+struct ShopHeader;
+
+#[derive(Component)]
+struct ShopRow(usize);
+
+#[derive(Component)]
+struct ShopRowText(usize);
+
+#[derive(Component)]
+struct ShopFooter;
+
+fn load_shops(mut loading: ResMut<LoadingAssets>, map: Res<MapData>, asset_server: Res<AssetServer>) {
+    for object in &map.objects {
+        let spawn = match object {
+            MapObject::Npc(spawn) => spawn,
+            _ => continue,
+        };
+        if let Some(path) = &spawn.shop {
+            let handle: Handle<Shop> = asset_server.load(path.as_str());
+            loading.add(path.as_str(), &handle);
+        }
+    }
+}
+
+/// Opens a vendor's shop when their dialogue emits `open_shop`.
+fn open_shop(
+    mut commands: Commands,
+    mut state: ResMut<State<AppState>>,
+    mut events: EventReader<DialogueEvent>,
+    shops: Res<Assets<Shop>>,
+    mut vendor_query: Query<&mut Vendor>,
+) {
+    for event in events.iter() {
+        if event.name != OPEN_SHOP_EVENT {
+            continue;
+        }
+        let mut vendor = match vendor_query.get_mut(event.speaker) {
+            Ok(vendor) => vendor,
+            Err(_) => {
+                warn!("`{OPEN_SHOP_EVENT}` emitted by an NPC without a shop");
+                continue;
+            }
+        };
+        let shop = match shops.get(&vendor.shop) {
+            Some(shop) => shop,
+            None => continue,
+        };
+        vendor
+            .remaining
+            .get_or_insert_with(|| shop.stock.iter().map(|entry| entry.quantity).collect());
+        commands.insert_resource(ActiveShop {
+            vendor: event.speaker,
+            mode: TradeMode::Buy,
+            selected: 0,
+            quantity: 1,
+            message: String::new(),
+        });
+        state.push(AppState::Shop).unwrap();
+        return;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn shop_input(
+    mut commands: Commands,
+    mut state: ResMut<State<AppState>>,
+    mut keyboard_input: ResMut<Input<KeyCode>>,
+    shops: Res<Assets<Shop>>,
+    items: Res<Items>,
+    mut active: ResMut<ActiveShop>,
+    mut vendor_query: Query<&mut Vendor>,
+    mut player_query: Query<(&mut Wallet, &mut Inventory), With<Player>>,
+) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        keyboard_input.reset(KeyCode::Escape);
+        commands.remove_resource::<ActiveShop>();
+        state.pop().unwrap();
+        return;
+    }
+    let mut vendor = match vendor_query.get_mut(active.vendor) {
+        Ok(vendor) => vendor,
+        Err(_) => return,
+    };
+    let shop = match shops.get(&vendor.shop) {
+        Some(shop) => shop,
+        None => return,
+    };
+    if shop.stock.is_empty() {
+        return;
+    }
+
+    if keyboard_input.just_pressed(KeyCode::Tab) {
+        active.mode = match active.mode {
+            TradeMode::Buy => TradeMode::Sell,
+            TradeMode::Sell => TradeMode::Buy,
+        };
+        active.quantity = 1;
+        active.message.clear();
+    }
+    if keyboard_input.just_pressed(KeyCode::W) {
+        active.selected = (active.selected + shop.stock.len() - 1) % shop.stock.len();
+        active.quantity = 1;
+    }
+    if keyboard_input.just_pressed(KeyCode::S) {
+        active.selected = (active.selected + 1) % shop.stock.len();
+        active.quantity = 1;
+    }
+    if keyboard_input.just_pressed(KeyCode::A) {
+        active.quantity = active.quantity.saturating_sub(1).max(1);
+    }
+    if keyboard_input.just_pressed(KeyCode::D) {
+        active.quantity = (active.quantity + 1).min(MAX_QUANTITY);
+    }
+    if !keyboard_input.just_pressed(KeyCode::E) {
+        return;
+    }
+    keyboard_input.reset(KeyCode::E);
+
+    let (mut wallet, mut inventory) = player_query.single_mut();
+    let entry = &shop.stock[active.selected];
+    let name = items.get(&entry.item).map_or(&entry.item, |def| &def.name);
+    let remaining = match vendor.remaining.as_mut().and_then(|r| r.get_mut(active.selected)) {
+        Some(remaining) => remaining,
+        None => return,
+    };
+    let quantity = active.quantity;
+    active.message = match active.mode {
+        TradeMode::Buy => match buy(entry, remaining, quantity, &mut wallet, &mut inventory, &items) {
+            Ok(cost) => format!("Bought {quantity} {name} for {cost} coins."),
+            Err(err) => err.to_string(),
+        },
+        TradeMode::Sell => match sell(entry, remaining, quantity, &mut wallet, &mut inventory) {
+            Ok(earned) => format!("Sold {quantity} {name} for {earned} coins."),
+            Err(err) => err.to_string(),
+        },
+    };
+}
+
+fn spawn_shop_menu(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    active: Res<ActiveShop>,
+    shops: Res<Assets<Shop>>,
+    vendor_query: Query<&Vendor>,
+    icons: Res<ItemIcons>,
+) {
+    let shop = match vendor_query
+        .get(active.vendor)
+        .ok()
+        .and_then(|vendor| shops.get(&vendor.shop))
+    {
+        Some(shop) => shop,
+        None => return,
+    };
+    let font = asset_server.load("fonts/FiraSans-Bold.ttf");
+    let style = |font_size, color| TextStyle {
+        font: font.clone(),
+        font_size,
+        color,
+    };
+    commands
+        .spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.), Val::Percent(100.)),
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..default()
+            },
+            color: Color::NONE.into(),
+            ..default()
+        })
+        .insert(Name::new("Shop Menu"))
+        .insert(ShopMenu)
+        .with_children(|parent| {
+            parent
+                .spawn_bundle(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(60.), Val::Auto),
+                        padding: UiRect::all(Val::Px(16.)),
+                        flex_direction: FlexDirection::ColumnReverse,
+                        ..default()
+                    },
+                    color: Color::rgba(0.1, 0.08, 0.06, 0.9).into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent
+                        .spawn_bundle(TextBundle::from_section(
+                            shop.name.clone(),
+                            style(28., Color::GOLD),
+                        ))
+                        .insert(ShopHeader);
+                    for (i, entry) in shop.stock.iter().enumerate() {
+                        parent
+                            .spawn_bundle(NodeBundle {
+                                style: Style {
+                                    align_items: AlignItems::Center,
+                                    padding: UiRect::all(Val::Px(4.)),
+                                    ..default()
+                                },
+                                color: Color::NONE.into(),
+                                ..default()
+                            })
+                            .insert(ShopRow(i))
+                            .with_children(|parent| {
+                                parent.spawn_bundle(ImageBundle {
+                                    style: Style {
+                                        size: Size::new(Val::Px(32.), Val::Px(32.)),
+                                        margin: UiRect::new(
+                                            Val::Px(0.),
+                                            Val::Px(12.),
+                                            Val::Px(0.),
+                                            Val::Px(0.),
+                                        ),
+                                        ..default()
+                                    },
+                                    image: icons
+                                        .get(&entry.item)
+                                        .cloned()
+                                        .unwrap_or_default()
+                                        .into(),
+                                    ..default()
+                                });
+                                parent
+                                    .spawn_bundle(TextBundle::from_section(
+                                        "",
+                                        style(20., Color::WHITE),
+                                    ))
+                                    .insert(ShopRowText(i));
+                            });
+                    }
+                    parent
+                        .spawn_bundle(TextBundle::from_section("", style(18., Color::GRAY)))
+                        .insert(ShopFooter);
+                });
+        });
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn update_shop_menu(
+    active: Option<Res<ActiveShop>>,
+    shops: Res<Assets<Shop>>,
+    items: Res<Items>,
+    vendor_query: Query<&Vendor>,
+    player_query: Query<(&Wallet, &Inventory), With<Player>>,
+    mut row_query: Query<(&ShopRow, &mut UiColor)>,
+    mut header_query: Query<&mut Text, (With<ShopHeader>, Without<ShopRowText>, Without<ShopFooter>)>,
+    mut row_text_query: Query<(&ShopRowText, &mut Text), Without<ShopFooter>>,
+    mut footer_query: Query<&mut Text, With<ShopFooter>>,
+) {
+    let active = match active {
+        Some(active) => active,
+        None => return,
+    };
+    let vendor = match vendor_query.get(active.vendor) {
+        Ok(vendor) => vendor,
+        Err(_) => return,
+    };
+    let shop = match shops.get(&vendor.shop) {
+        Some(shop) => shop,
+        None => return,
+    };
+    let (wallet, inventory) = player_query.single();
+
+    for mut text in &mut header_query {
+        let mode = match active.mode {
+            TradeMode::Buy => "Buying",
+            TradeMode::Sell => "Selling",
+        };
+        text.sections[0].value = format!("{} - {mode} - {} coins", shop.name, wallet.coins);
+    }
+    for (row, mut color) in &mut row_query {
+        *color = if row.0 == active.selected {
+            Color::rgba(1., 1., 1., 0.15).into()
+        } else {
+            Color::NONE.into()
+        };
+    }
+    for (row, mut text) in &mut row_text_query {
+        let entry = match shop.stock.get(row.0) {
+            Some(entry) => entry,
+            None => continue,
+        };
+        let name = items.get(&entry.item).map_or(&entry.item, |def| &def.name);
+        let held = inventory.count(&entry.item);
+        let remaining = vendor
+            .remaining
+            .as_ref()
+            .and_then(|remaining| remaining.get(row.0).copied())
+            .flatten();
+        text.sections[0].value = match active.mode {
+            TradeMode::Buy => {
+                let stock = remaining.map_or("-".to_string(), |left| left.to_string());
+                format!("{name}  {}c  (stock {stock}, have {held})", entry.buy_price)
+            }
+            TradeMode::Sell => format!("{name}  {}c  (have {held})", entry.sell_price),
+        };
+    }
+    for mut text in &mut footer_query {
+        let price = shop.stock.get(active.selected).map_or(0, |entry| match active.mode {
+            TradeMode::Buy => entry.buy_price,
+            TradeMode::Sell => entry.sell_price,
+        });
+        text.sections[0].value = format!(
+            "Quantity: {} ({} coins)\n{}\nW/S select  A/D quantity  E confirm  Tab buy/sell  Esc leave",
+            active.quantity,
+            price.saturating_mul(active.quantity),
+            active.message,
+        );
+    }
+}
+
+fn despawn_shop_menu(mut commands: Commands, query: Query<Entity, With<ShopMenu>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::inventory::ItemStack;
+
+    fn potions(buy_price: u32, sell_price: u32) -> StockEntry {
+        StockEntry {
+            item: "potion".to_string(),
+            buy_price,
+            sell_price,
+            quantity: None,
+        }
+    }
+
+    #[test]
+    fn buy_pays_and_takes_from_stock() {
+        let (mut remaining, mut wallet, mut inventory) = (Some(3), Wallet { coins: 50 }, Inventory::new(2));
+        assert_eq!(buy(&potions(10, 5), &mut remaining, 2, &mut wallet, &mut inventory, &Items::fixture()), Ok(20));
+        assert_eq!(wallet.coins, 30);
+        assert_eq!(remaining, Some(1));
+        assert_eq!(inventory.count("potion"), 2);
+    }
+
+    #[test]
+    fn buy_without_enough_coins_changes_nothing() {
+        let (mut remaining, mut wallet, mut inventory) = (Some(3), Wallet { coins: 15 }, Inventory::new(2));
+        let result = buy(&potions(10, 5), &mut remaining, 2, &mut wallet, &mut inventory, &Items::fixture());
+        assert_eq!(result, Err(TradeError::NotEnoughCoins));
+        assert_eq!(wallet.coins, 15);
+        assert_eq!(remaining, Some(3));
+        assert_eq!(inventory.count("potion"), 0);
+    }
+
+    #[test]
+    fn buy_past_the_stock_changes_nothing() {
+        let (mut remaining, mut wallet, mut inventory) = (Some(1), Wallet { coins: 50 }, Inventory::new(2));
+        let result = buy(&potions(10, 5), &mut remaining, 2, &mut wallet, &mut inventory, &Items::fixture());
+        assert_eq!(result, Err(TradeError::OutOfStock));
+        assert_eq!(wallet.coins, 50);
+        assert_eq!(remaining, Some(1));
+        assert_eq!(inventory.count("potion"), 0);
+    }
+
+    #[test]
+    fn buy_into_a_full_inventory_changes_nothing() {
+        // Room for one more potion only.
+        let slots = vec![Some(ItemStack {
+            item: "potion".to_string(),
+            count: 4,
+        })];
+        let mut inventory = Inventory::from_slots(slots.clone());
+        let (mut remaining, mut wallet) = (Some(3), Wallet { coins: 50 });
+        let result = buy(&potions(10, 5), &mut remaining, 2, &mut wallet, &mut inventory, &Items::fixture());
+        assert_eq!(result, Err(TradeError::InventoryFull));
+        assert_eq!(wallet.coins, 50);
+        assert_eq!(remaining, Some(3));
+        assert_eq!(inventory.slots(), slots);
+    }
+
+    #[test]
+    fn sell_pays_and_restocks() {
+        let mut inventory = Inventory::new(2);
+        inventory.add("potion", 3, &Items::fixture()).unwrap();
+        let (mut remaining, mut wallet) = (Some(0), Wallet { coins: 0 });
+        assert_eq!(sell(&potions(10, 5), &mut remaining, 2, &mut wallet, &mut inventory), Ok(10));
+        assert_eq!(wallet.coins, 10);
+        assert_eq!(remaining, Some(2));
+        assert_eq!(inventory.count("potion"), 1);
+    }
+
+    #[test]
+    fn sell_more_than_held_changes_nothing() {
+        let mut inventory = Inventory::new(2);
+        inventory.add("potion", 1, &Items::fixture()).unwrap();
+        let (mut remaining, mut wallet) = (Some(0), Wallet { coins: 0 });
+        let result = sell(&potions(10, 5), &mut remaining, 2, &mut wallet, &mut inventory);
+        assert_eq!(result, Err(TradeError::NothingToSell));
+        assert_eq!(wallet.coins, 0);
+        assert_eq!(remaining, Some(0));
+        assert_eq!(inventory.count("potion"), 1);
+    }
+
+    #[test]
+    fn sell_past_a_full_wallet_changes_nothing() {
+        let mut inventory = Inventory::new(2);
+        inventory.add("potion", 2, &Items::fixture()).unwrap();
+        let (mut remaining, mut wallet) = (Some(0), Wallet { coins: u32::MAX - 5 });
+        let result = sell(&potions(10, 5), &mut remaining, 2, &mut wallet, &mut inventory);
+        assert_eq!(result, Err(TradeError::TooManyCoins));
+        assert_eq!(wallet.coins, u32::MAX - 5);
+        assert_eq!(remaining, Some(0));
+        assert_eq!(inventory.count("potion"), 2);
+    }
+}
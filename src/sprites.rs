@@ -1,228 +1,608 @@
-use std::ops::Range;
+use std::{collections::BTreeMap, path::Path};
 
-use bevy::{prelude::*, asset::LoadState};
+use bevy::{
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    prelude::*,
+    reflect::TypeUuid,
+    render::render_resource::{Extent3d, TextureDimension},
+    utils::{BoxedFuture, HashMap, HashSet},
+};
+use serde::Deserialize;
+
+use crate::{loading::LoadingAssets, AppState};
 
 pub struct SpritePlugin;
 
 impl Plugin for SpritePlugin {
     fn build(&self, app: &mut App) {
         app
+        .add_asset::<SpriteManifest>()
+        .add_asset::<PackedAtlas>()
+        .add_asset::<NamedAtlas>()
+        .init_asset_loader::<SpriteManifestLoader>()
+        .init_asset_loader::<PackedAtlasLoader>()
         .init_resource::<RpgSpriteHandles>()
         .init_resource::<Characters>()
+        .init_resource::<Mobs>()
         .init_resource::<Tiles>()
         .init_resource::<Props>()
-        .add_state(AppState::Setup)
-        .add_system_set(SystemSet::on_enter(AppState::Setup).with_system(load_textures))
-        .add_system_set(SystemSet::on_update(AppState::Setup).with_system(check_textures))
-        .add_system_set(SystemSet::on_enter(AppState::Finished).with_system(setup));
+        .init_resource::<MissingSprites>()
+        .add_system_set(SystemSet::on_enter(AppState::Loading).with_system(load_textures))
+        .add_system_set(
+            SystemSet::on_update(AppState::Loading)
+                .with_system(queue_textures.before("check_loading")),
+        )
+        .add_system_set(SystemSet::on_exit(AppState::Loading).with_system(setup));
     }
 }
 
-#[derive(Debug, Default)]
-pub struct Characters{
-    pub gabe: Handle<TextureAtlas>,
-    pub hat_guy: Handle<Image>,
-    pub mani: Handle<TextureAtlas>,
-    pub sensei: Handle<Image>,
+pub const MANIFEST_PATH: &str = "sprites.manifest.ron";
+
+/// Every sprite the game uses, grouped by collection and keyed by logical name.
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "a3ec9d85-c946-47c8-9fdf-daf21b1cba4f"]
+pub struct SpriteManifest {
+    /// Atlases made by the `pack_atlases` tool. Entries whose files were packed
+    /// are cut from these instead of being stitched together at runtime.
+    #[serde(default)]
+    pub packed: Vec<String>,
+    #[serde(default)]
+    pub characters: HashMap<String, SpriteEntry>,
+    #[serde(default)]
+    pub mobs: HashMap<String, SpriteEntry>,
+    #[serde(default)]
+    pub tiles: HashMap<String, SpriteEntry>,
+    #[serde(default)]
+    pub props: HashMap<String, SpriteEntry>,
 }
 
-#[derive(Debug, Default)]
-pub struct Tiles{
-    pub slice: Handle<Image>,
-    pub waterfall_atlas: Handle<TextureAtlas>,
-    pub misc_atlas: Handle<TextureAtlas>
+#[derive(Debug, Deserialize)]
+pub enum SpriteEntry {
+    /// A single image, used as is.
+    Image(String),
+    /// A sprite sheet cut into equally sized cells, named `<entry>-01`, `<entry>-02`...
+    /// row by row.
+    Grid {
+        path: String,
+        tile_size: Vec2,
+        columns: usize,
+        rows: usize,
+        #[serde(default)]
+        aliases: HashMap<String, String>,
+    },
+    /// An atlas stitched together from the listed images, each frame named
+    /// after its file without the `generic-rpg-` prefix.
+    Folder {
+        files: Vec<String>,
+        #[serde(default)]
+        aliases: HashMap<String, String>,
+    },
+    /// An atlas stitched together from numbered images, `{idx}` in the pattern
+    /// being replaced by `first..=last` padded to `digits` digits. Frames are
+    /// named `<entry>-<idx>`.
+    Sequence {
+        pattern: String,
+        first: u32,
+        last: u32,
+        #[serde(default = "default_digits")]
+        digits: usize,
+        #[serde(default)]
+        aliases: HashMap<String, String>,
+    },
 }
 
-#[derive(Debug, Default)]
-pub struct Props{
-    pub barrel_atlas: Handle<TextureAtlas>,
-    pub board_atlas: Handle<TextureAtlas>,
-    pub bridge: Handle<Image>,
-    pub crate_atlas: Handle<TextureAtlas>,
-    pub fence_complete: Handle<Image>,
-    pub fence_raw_atlas: Handle<TextureAtlas>,
-    pub fence_atlas: Handle<TextureAtlas>,
-    pub fish_atlas: Handle<TextureAtlas>,
-    pub flower_atlas: Handle<TextureAtlas>,
-    pub flowers: Handle<Image>,
-    pub grass_atlas: Handle<TextureAtlas>,
-    pub house_inn: Handle<Image>,
-    pub loot_atlas: Handle<TextureAtlas>,
-    pub mini_lake: Handle<Image>,
-    pub rock_atlas: Handle<TextureAtlas>,
-    pub rod: Handle<Image>,
-    pub treasure_atlas: Handle<TextureAtlas>,
-    pub tree_atlas: Handle<TextureAtlas>
-}
-
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
-enum AppState {
-    Setup,
-    Finished,
+fn default_digits() -> usize {
+    2
 }
 
-#[derive(Default)]
-struct RpgSpriteHandles {
-    handles: Vec<HandleUntyped>,
-}
+impl SpriteEntry {
+    pub fn paths(&self) -> Vec<String> {
+        match self {
+            SpriteEntry::Image(path) | SpriteEntry::Grid { path, .. } => vec![path.clone()],
+            SpriteEntry::Folder { files, .. } => files.clone(),
+            SpriteEntry::Sequence {
+                pattern,
+                first,
+                last,
+                digits,
+                ..
+            } => (*first..=*last)
+                .map(|idx| pattern.replace("{idx}", &format!("{idx:0digits$}")))
+                .collect(),
+        }
+    }
 
-fn load_textures(mut rpg_sprite_handles: ResMut<RpgSpriteHandles>, asset_server: Res<AssetServer>) {
-    rpg_sprite_handles.handles = asset_server.load_folder("textures/rpg").unwrap();
-}
+    /// Frame names in entry order, for an entry called `name`. A single image
+    /// is a frame named after its entry.
+    pub fn frame_names(&self, name: &str) -> Vec<String> {
+        match self {
+            SpriteEntry::Image(_) => vec![name.to_string()],
+            SpriteEntry::Grid { columns, rows, .. } => (1..=columns * rows)
+                .map(|idx| format!("{name}-{idx:02}"))
+                .collect(),
+            SpriteEntry::Folder { files, .. } => files
+                .iter()
+                .map(|file| {
+                    let stem = Path::new(file).file_stem().unwrap_or_default().to_string_lossy();
+                    stem.trim_start_matches("generic-rpg-").to_string()
+                })
+                .collect(),
+            SpriteEntry::Sequence {
+                first,
+                last,
+                digits,
+                ..
+            } => (*first..=*last)
+                .map(|idx| format!("{name}-{idx:0digits$}"))
+                .collect(),
+        }
+    }
 
-fn check_textures(
-    mut state: ResMut<State<AppState>>,
-    rpg_sprite_handles: ResMut<RpgSpriteHandles>,
-    asset_server: Res<AssetServer>,
-) {
-    if let LoadState::Loaded =
-        asset_server.get_group_load_state(rpg_sprite_handles.handles.iter().map(|handle| handle.id))
-    {
-        state.set(AppState::Finished).unwrap();
+    fn aliases(&self) -> Option<&HashMap<String, String>> {
+        match self {
+            SpriteEntry::Image(_) => None,
+            SpriteEntry::Grid { aliases, .. }
+            | SpriteEntry::Folder { aliases, .. }
+            | SpriteEntry::Sequence { aliases, .. } => Some(aliases),
+        }
     }
 }
 
-fn setup(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
-    mut textures: ResMut<Assets<Image>>
-) {
-    commands.insert_resource(load_characters(&asset_server, &mut texture_atlases));
-    commands.insert_resource(load_tiles(&asset_server, &mut texture_atlases, &mut textures));
-    commands.insert_resource(load_props(&asset_server, &mut texture_atlases, &mut textures));
+#[derive(Default)]
+pub struct SpriteManifestLoader;
+
+impl AssetLoader for SpriteManifestLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let manifest: SpriteManifest = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(manifest));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["manifest.ron"]
+    }
 }
 
-fn load_characters(
-    asset_server: &AssetServer,
-    texture_atlases: &mut Assets<TextureAtlas>
-) -> Characters {
-    let gabe_handle = asset_server.get_handle("textures/rpg/chars/gabe/gabe-idle-run.png");
-    let gabe_atlas = TextureAtlas::from_grid(gabe_handle, Vec2::new(24.0, 24.0), 7, 1);
-    let gabe_atlas_handle = texture_atlases.add(gabe_atlas);
+/// An atlas image packed ahead of time, with the rect every source file was copied to.
+#[derive(Debug, Deserialize, TypeUuid)]
+#[uuid = "72772848-ebd4-42e3-a538-590fc8647eb8"]
+pub struct PackedAtlas {
+    pub image: String,
+    pub size: Vec2,
+    pub frames: Vec<PackedFrame>,
+}
 
-    let hat_guy_handle = asset_server.get_handle("textures/rpg/chars/hat-guy/hat-guy.png");
+#[derive(Debug, Deserialize)]
+pub struct PackedFrame {
+    /// Asset path of the image this frame was packed from.
+    pub source: String,
+    pub position: Vec2,
+    pub size: Vec2,
+}
 
-    let mani_handle = asset_server.get_handle("textures/rpg/chars/mani/mani-idle-run.png");
-    let mani_atlas = TextureAtlas::from_grid(mani_handle, Vec2::new(24.0, 24.0), 7, 1);
-    let mani_atlas_handle = texture_atlases.add(mani_atlas);
+impl PackedAtlas {
+    pub fn frame(&self, source: &str) -> Option<&PackedFrame> {
+        self.frames.iter().find(|frame| frame.source == source)
+    }
+}
 
-    let sensei_handle = asset_server.get_handle("textures/rpg/chars/mani/mani-idle-run.png");
+#[derive(Default)]
+pub struct PackedAtlasLoader;
+
+impl AssetLoader for PackedAtlasLoader {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let atlas: PackedAtlas = ron::de::from_bytes(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(atlas));
+            Ok(())
+        })
+    }
 
-    Characters{
-        gabe: gabe_atlas_handle,
-        hat_guy: hat_guy_handle,
-        mani: mani_atlas_handle,
-        sensei: sensei_handle
+    fn extensions(&self) -> &[&str] {
+        &["atlas.ron"]
     }
 }
 
-fn load_tiles(
-    asset_server: &AssetServer,
-    mut texture_atlases: &mut Assets<TextureAtlas>,
-    mut textures: &mut Assets<Image>
-) -> Tiles {
-    let slice_handle = asset_server.get_handle("textures/rpg/tiles/generic-rpg-Slice.png");
+/// A `TextureAtlas` whose frames can be looked up by name, e.g. `"rock-03"`.
+#[derive(Debug, TypeUuid)]
+#[uuid = "37de8756-abda-4598-a8d6-292cb838f598"]
+pub struct NamedAtlas {
+    pub atlas: Handle<TextureAtlas>,
+    pub frames: HashMap<String, usize>,
+}
 
-    let waterfall_atlas_handle = load_atlas(&asset_server, &mut texture_atlases, &mut textures, 1..8, "textures/rpg/tiles/generic-rpg-tile-waterfall{idx}.png");
+impl NamedAtlas {
+    pub fn index(&self, frame: &str) -> Option<usize> {
+        self.frames.get(frame).copied()
+    }
+}
 
-    let misc_atlas_handle = load_atlas(&asset_server, &mut texture_atlases, &mut textures, 1..72, "textures/rpg/tiles/generic-rpg-tile{idx}.png");
+/// Handles produced from one section of the manifest.
+#[derive(Debug, Default)]
+pub struct SpriteCollection {
+    /// Sorted by name, so frames are looked up in the same order every run.
+    pub atlases: BTreeMap<String, Handle<NamedAtlas>>,
+    pub images: HashMap<String, Handle<Image>>,
+}
+
+impl SpriteCollection {
+    /// The texture atlas behind the named atlas entry `name`.
+    pub fn texture_atlas(
+        &self,
+        named_atlases: &Assets<NamedAtlas>,
+        name: &str,
+    ) -> Option<Handle<TextureAtlas>> {
+        let named_atlas = named_atlases.get(self.atlases.get(name)?)?;
+        Some(named_atlas.atlas.clone())
+    }
 
-    Tiles{
-        slice: slice_handle,
-        waterfall_atlas: waterfall_atlas_handle,
-        misc_atlas: misc_atlas_handle
+    /// Looks `frame` up in every atlas of the collection. A frame name found
+    /// in several atlases comes from the first of them by atlas name.
+    pub fn frame(
+        &self,
+        named_atlases: &Assets<NamedAtlas>,
+        frame: &str,
+    ) -> Option<(Handle<TextureAtlas>, usize)> {
+        self.atlases
+            .values()
+            .filter_map(|handle| named_atlases.get(handle))
+            .find_map(|named_atlas| Some((named_atlas.atlas.clone(), named_atlas.index(frame)?)))
     }
 }
 
-fn load_props(
-    asset_server: &AssetServer,
-    mut texture_atlases: &mut Assets<TextureAtlas>,
-    mut textures: &mut Assets<Image>
-) -> Props {
-    let barrel_atlas_handle = load_atlas(&asset_server, &mut texture_atlases, &mut textures, 1..4, "textures/rpg/props/generic-rpg-barrel{idx}.png");
-    
-    let board_atlas_handle = load_atlas(&asset_server, &mut texture_atlases, &mut textures, 1..5, "textures/rpg/props/generic-rpg-board{idx}.png");
+#[derive(Debug, Default, Deref)]
+pub struct Characters(pub SpriteCollection);
 
-    let bridge_handle = asset_server.get_handle("textures/rpg/props/generic-rpg-bridge.png");
+#[derive(Debug, Default, Deref)]
+pub struct Mobs(pub SpriteCollection);
 
-    let crate_atlas_handle = load_atlas(&asset_server, &mut texture_atlases, &mut textures, 1..4, "textures/rpg/props/generic-rpg-crate{idx}.png");
+#[derive(Debug, Default, Deref)]
+pub struct Tiles(pub SpriteCollection);
 
-    let fence_complete_handle = asset_server.get_handle("textures/rpg/props/generic-rpg-fence-complete.png");
+#[derive(Debug, Default, Deref)]
+pub struct Props(pub SpriteCollection);
 
-    let fence_atlas_raw_handle = load_atlas(&asset_server, &mut texture_atlases, &mut textures, 1..26, "textures/rpg/props/generic-rpg-fence-raw{idx}.png");
+/// A file the manifest asked for that could not be loaded.
+#[derive(Debug, Clone)]
+pub struct MissingSprite {
+    pub name: String,
+    pub path: String,
+}
 
-    let fence_atlas_handle = load_atlas(&asset_server, &mut texture_atlases, &mut textures, 1..15, "textures/rpg/props/generic-rpg-fence{idx}.png");
+#[derive(Debug, Default, Deref)]
+pub struct MissingSprites(pub Vec<MissingSprite>);
 
-    let fish_atlas_handle = load_atlas(&asset_server, &mut texture_atlases, &mut textures, 1..5, "textures/rpg/props/generic-rpg-fish{idx}.png");
+#[derive(Default)]
+struct RpgSpriteHandles {
+    manifest: Handle<SpriteManifest>,
+    packed: Vec<Handle<PackedAtlas>>,
+    images: HashMap<String, Handle<Image>>,
+    packed_queued: bool,
+    queued: bool,
+}
 
-    let flower_atlas_handle = load_atlas(&asset_server, &mut texture_atlases, &mut textures, 1..4, "textures/rpg/props/generic-rpg-flower{idx}.png");
+fn load_textures(
+    mut rpg_sprite_handles: ResMut<RpgSpriteHandles>,
+    mut loading: ResMut<LoadingAssets>,
+    asset_server: Res<AssetServer>,
+) {
+    rpg_sprite_handles.manifest = asset_server.load(MANIFEST_PATH);
+    loading.add(MANIFEST_PATH, &rpg_sprite_handles.manifest);
+}
 
-    let flowers_handle = asset_server.get_handle("textures/rpg/props/generic-rpg-flowers.png");
+/// Queues what the manifest names onto the loading screen: first the packed
+/// atlases, then, once those are in, the images entries are cut or built from.
+fn queue_textures(
+    mut rpg_sprite_handles: ResMut<RpgSpriteHandles>,
+    mut loading: ResMut<LoadingAssets>,
+    manifests: Res<Assets<SpriteManifest>>,
+    packed_atlases: Res<Assets<PackedAtlas>>,
+    asset_server: Res<AssetServer>,
+) {
+    if rpg_sprite_handles.queued {
+        return;
+    }
+    let manifest = match manifests.get(&rpg_sprite_handles.manifest) {
+        Some(manifest) => manifest,
+        None => return,
+    };
+
+    if !rpg_sprite_handles.packed_queued {
+        for path in &manifest.packed {
+            let handle = asset_server.load(path.as_str());
+            loading.add(path.as_str(), &handle);
+            rpg_sprite_handles.packed.push(handle);
+        }
+        rpg_sprite_handles.packed_queued = true;
+    }
 
-    let grass_atlas_handle = load_atlas(&asset_server, &mut texture_atlases, &mut textures, 1..3, "textures/rpg/props/generic-rpg-grass{idx}.png");
+    let packed: Option<Vec<&PackedAtlas>> = rpg_sprite_handles
+        .packed
+        .iter()
+        .map(|handle| packed_atlases.get(handle))
+        .collect();
+    let packed = match packed {
+        Some(packed) => packed,
+        None => return,
+    };
+
+    let mut paths = HashSet::new();
+    for entry in manifest
+        .characters
+        .values()
+        .chain(manifest.mobs.values())
+        .chain(manifest.tiles.values())
+        .chain(manifest.props.values())
+    {
+        match find_packed(entry, &packed) {
+            Some((atlas, _)) => {
+                paths.insert(atlas.image.clone());
+            }
+            None => paths.extend(entry.paths()),
+        }
+    }
+    for path in paths {
+        let handle = asset_server.load(path.as_str());
+        loading.add(path.as_str(), &handle);
+        rpg_sprite_handles.images.insert(path, handle);
+    }
+    rpg_sprite_handles.queued = true;
+}
 
-    let house_inn_handle = asset_server.get_handle("textures/rpg/props/generic-rpg-house-inn.png");
+/// The packed atlas holding every file of an atlas entry, along with the frames
+/// in entry order. Plain images are never cut out of a packed atlas.
+fn find_packed<'a>(
+    entry: &SpriteEntry,
+    packed: &[&'a PackedAtlas],
+) -> Option<(&'a PackedAtlas, Vec<&'a PackedFrame>)> {
+    if let SpriteEntry::Image(_) = entry {
+        return None;
+    }
+    let paths = entry.paths();
+    packed.iter().find_map(|atlas| {
+        let frames: Option<Vec<_>> = paths.iter().map(|path| atlas.frame(path)).collect();
+        frames.map(|frames| (*atlas, frames))
+    })
+}
 
-    let loot_atlas_handle = load_atlas(&asset_server, &mut texture_atlases, &mut textures, 1..6, "textures/rpg/props/generic-rpg-loot{idx}.png");
+fn setup(
+    mut commands: Commands,
+    rpg_sprite_handles: Res<RpgSpriteHandles>,
+    manifests: Res<Assets<SpriteManifest>>,
+    packed_atlases: Res<Assets<PackedAtlas>>,
+    mut named_atlases: ResMut<Assets<NamedAtlas>>,
+    mut texture_atlases: ResMut<Assets<TextureAtlas>>,
+    mut textures: ResMut<Assets<Image>>,
+) {
+    let manifest = match manifests.get(&rpg_sprite_handles.manifest) {
+        Some(manifest) => manifest,
+        None => return,
+    };
+    let packed: Vec<&PackedAtlas> = rpg_sprite_handles
+        .packed
+        .iter()
+        .filter_map(|handle| packed_atlases.get(handle))
+        .collect();
+
+    let mut missing = Vec::new();
+    let mut build = |entries: &HashMap<String, SpriteEntry>| {
+        build_collection(
+            entries,
+            &packed,
+            &rpg_sprite_handles.images,
+            &mut named_atlases,
+            &mut texture_atlases,
+            &mut textures,
+            &mut missing,
+        )
+    };
+    let characters = build(&manifest.characters);
+    let mobs = build(&manifest.mobs);
+    let tiles = build(&manifest.tiles);
+    let props = build(&manifest.props);
+
+    for MissingSprite { name, path } in &missing {
+        error!("Sprite `{name}` is missing `{path}`");
+    }
 
-    let mini_lake_handle = asset_server.get_handle("textures/rpg/props/generic-rpg-mini-lake.png");
+    commands.insert_resource(Characters(characters));
+    commands.insert_resource(Mobs(mobs));
+    commands.insert_resource(Tiles(tiles));
+    commands.insert_resource(Props(props));
+    commands.insert_resource(MissingSprites(missing));
+}
 
-    let rock_atlas_handle = load_atlas(&asset_server, &mut texture_atlases, &mut textures, 1..7, "textures/rpg/props/generic-rpg-rock{idx}.png");
+fn build_collection(
+    entries: &HashMap<String, SpriteEntry>,
+    packed: &[&PackedAtlas],
+    images: &HashMap<String, Handle<Image>>,
+    named_atlases: &mut Assets<NamedAtlas>,
+    texture_atlases: &mut Assets<TextureAtlas>,
+    textures: &mut Assets<Image>,
+    missing: &mut Vec<MissingSprite>,
+) -> SpriteCollection {
+    let mut collection = SpriteCollection::default();
+    'entries: for (name, entry) in entries {
+        let built = if let Some((atlas, frames)) = find_packed(entry, packed) {
+            match images.get(&atlas.image).filter(|handle| textures.contains(*handle)) {
+                Some(handle) => Some(cut_packed_atlas(entry, handle.clone(), atlas.size, &frames)),
+                None => {
+                    missing.push(MissingSprite {
+                        name: name.clone(),
+                        path: atlas.image.clone(),
+                    });
+                    continue;
+                }
+            }
+        } else {
+            let mut handles = Vec::new();
+            for path in entry.paths() {
+                match images.get(&path).filter(|handle| textures.contains(*handle)) {
+                    Some(handle) => handles.push(handle.clone()),
+                    None => {
+                        missing.push(MissingSprite {
+                            name: name.clone(),
+                            path,
+                        });
+                        continue 'entries;
+                    }
+                }
+            }
+
+            match entry {
+                SpriteEntry::Image(_) => {
+                    collection.images.insert(name.clone(), handles.remove(0));
+                    continue;
+                }
+                SpriteEntry::Grid {
+                    tile_size,
+                    columns,
+                    rows,
+                    ..
+                } => {
+                    let atlas = TextureAtlas::from_grid(handles.remove(0), *tile_size, *columns, *rows);
+                    let indices = (0..atlas.len()).collect();
+                    Some((atlas, indices))
+                }
+                SpriteEntry::Folder { .. } | SpriteEntry::Sequence { .. } => {
+                    load_atlas(name, &handles, textures)
+                }
+            }
+        };
+
+        if let Some((atlas, indices)) = built {
+            let named_atlas = name_atlas(name, entry, texture_atlases.add(atlas), &indices);
+            collection.atlases.insert(name.clone(), named_atlases.add(named_atlas));
+        }
+    }
+    collection
+}
 
-    let rod_handle = asset_server.get_handle("textures/rpg/props/generic-rpg-rod.png");
+/// Pairs the entry's frame names, and then its aliases, with atlas indices.
+/// `indices` holds the atlas index of each frame in entry order.
+fn name_atlas(
+    name: &str,
+    entry: &SpriteEntry,
+    atlas: Handle<TextureAtlas>,
+    indices: &[usize],
+) -> NamedAtlas {
+    let mut frames: HashMap<String, usize> = entry
+        .frame_names(name)
+        .into_iter()
+        .zip(indices.iter().copied())
+        .collect();
+    for (alias, frame) in entry.aliases().into_iter().flatten() {
+        match frames.get(frame) {
+            Some(&index) => {
+                frames.insert(alias.clone(), index);
+            }
+            None => warn!("Alias `{alias}` of atlas `{name}` points at unknown frame `{frame}`"),
+        }
+    }
+    NamedAtlas { atlas, frames }
+}
 
-    let mut texture_atlas_builder = TextureAtlasBuilder::default();
-    let handle = asset_server.get_handle("textures/rpg/props/generic-rpg-trasure-open.png");
-    let texture = textures.get(&handle).expect("Textures folder contained a file which way matched by a loader which did not create an `Image` asset");
-    texture_atlas_builder.add_texture(handle, texture);
-    let handle = asset_server.get_handle("textures/rpg/props/generic-rpg-treasure-closed.png");
-    let texture = textures.get(&handle).expect("Textures folder contained a file which way matched by a loader which did not create an `Image` asset");
-    texture_atlas_builder.add_texture(handle, texture);
-    let texture_atlas = texture_atlas_builder.finish(&mut textures).unwrap();
-    let treasure_atlas_handle = texture_atlases.add(texture_atlas);
-
-    let tree_atlas_handle = load_atlas(&asset_server, &mut texture_atlases, &mut textures, 1..3, "textures/rpg/props/generic-rpg-tree{idx}.png");
-
-    Props{
-        barrel_atlas: barrel_atlas_handle,
-        board_atlas: board_atlas_handle,
-        bridge: bridge_handle,
-        crate_atlas: crate_atlas_handle,
-        fence_complete: fence_complete_handle,
-        fence_raw_atlas: fence_atlas_raw_handle,
-        fence_atlas: fence_atlas_handle,
-        fish_atlas: fish_atlas_handle,
-        flower_atlas: flower_atlas_handle,
-        flowers: flowers_handle,
-        grass_atlas: grass_atlas_handle,
-        house_inn: house_inn_handle,
-        loot_atlas: loot_atlas_handle,
-        mini_lake: mini_lake_handle,
-        rock_atlas: rock_atlas_handle,
-        rod: rod_handle,
-        treasure_atlas: treasure_atlas_handle,
-        tree_atlas: tree_atlas_handle,
+/// Builds an atlas over a packed image: grid sheets are cut up inside their
+/// frame, other entries get one texture per frame, in entry order.
+fn cut_packed_atlas(
+    entry: &SpriteEntry,
+    texture: Handle<Image>,
+    size: Vec2,
+    frames: &[&PackedFrame],
+) -> (TextureAtlas, Vec<usize>) {
+    let mut atlas = TextureAtlas::new_empty(texture, size);
+    match entry {
+        SpriteEntry::Grid {
+            tile_size,
+            columns,
+            rows,
+            ..
+        } => {
+            let origin = frames[0].position;
+            for y in 0..*rows {
+                for x in 0..*columns {
+                    let min = origin + *tile_size * Vec2::new(x as f32, y as f32);
+                    atlas.add_texture(bevy::sprite::Rect {
+                        min,
+                        max: min + *tile_size,
+                    });
+                }
+            }
+        }
+        _ => {
+            for frame in frames {
+                atlas.add_texture(bevy::sprite::Rect {
+                    min: frame.position,
+                    max: frame.position + frame.size,
+                });
+            }
+        }
     }
+    let indices = (0..atlas.len()).collect();
+    (atlas, indices)
 }
 
+/// Stitches an atlas together at runtime, for entries no packed atlas covers.
+/// The builder packs frames in its own order, so the index of every handle is
+/// returned alongside.
 pub fn load_atlas(
-    asset_server: &AssetServer,
-    texture_atlases: &mut Assets<TextureAtlas>,
-    mut textures: &mut Assets<Image>,
-    range: Range<i32>,
-    path: &str
-) -> Handle<TextureAtlas> {
+    name: &str,
+    handles: &[Handle<Image>],
+    textures: &mut Assets<Image>,
+) -> Option<(TextureAtlas, Vec<usize>)> {
     let mut texture_atlas_builder = TextureAtlasBuilder::default();
-    for idx in range {
-        // FIXME: only supports 2 digits
-        let idx = format!("{:02}", idx);
-        let path = path.replace("{idx}", idx.as_str());
-        println!("{path}");
-        let handle = asset_server.get_handle(path);
-        let texture = textures.get(&handle).expect("Textures folder contained a file which way matched by a loader which did not create an `Image` asset");
-        texture_atlas_builder.add_texture(handle, texture);
-    }
-    let texture_atlas = texture_atlas_builder.finish(&mut textures).unwrap();
-    texture_atlases.add(texture_atlas)
-}
\ No newline at end of file
+    for handle in handles {
+        let texture = textures.get(handle)?;
+        texture_atlas_builder.add_texture(handle.clone(), texture);
+    }
+    match texture_atlas_builder.finish(textures) {
+        Ok(texture_atlas) => {
+            let indices = handles
+                .iter()
+                .map(|handle| texture_atlas.get_texture_index(handle))
+                .collect::<Option<_>>()?;
+            Some((texture_atlas, indices))
+        }
+        Err(err) => {
+            error!("Could not build atlas `{name}`: {err:?}");
+            None
+        }
+    }
+}
+
+/// Copies frame `index` of `atlas` out into an image of its own, for UI nodes,
+/// which can't draw from an atlas.
+pub fn frame_image(atlas: &TextureAtlas, index: usize, images: &Assets<Image>) -> Option<Image> {
+    let rect = atlas.textures.get(index)?;
+    let texture = images.get(&atlas.texture)?;
+    let pixel_size = texture.texture_descriptor.format.describe().block_size as usize;
+    let texture_width = texture.texture_descriptor.size.width as usize;
+    let (x, y) = (rect.min.x as usize, rect.min.y as usize);
+    let (width, height) = (rect.width() as usize, rect.height() as usize);
+
+    let mut data = Vec::with_capacity(width * height * pixel_size);
+    for row in y..y + height {
+        let start = (row * texture_width + x) * pixel_size;
+        data.extend_from_slice(texture.data.get(start..start + width * pixel_size)?);
+    }
+    Some(Image::new(
+        Extent3d {
+            width: width as u32,
+            height: height as u32,
+            depth_or_array_layers: 1,
+        },
+        TextureDimension::D2,
+        data,
+        texture.texture_descriptor.format,
+    ))
+}
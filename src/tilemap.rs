@@ -1,136 +1,302 @@
 use std::{
-    fs::File,
-    io::{BufRead, BufReader},
+    fs::{self, File},
+    io::{self, BufRead, BufReader},
+    path::Path,
 };
 
 use bevy::prelude::*;
+use bevy_inspector_egui::Inspectable;
+use serde::{Deserialize, Serialize};
 
-use crate::TILE_SIZE;
+use crate::{
+    boss::BossSpawn,
+    chest::ChestSpawn,
+    dialogue::SignSpawn,
+    fishing::WaterSpawn,
+    items::ItemSpawn,
+    mob::MobSpawn,
+    npc::NpcSpawn,
+    sprites::{NamedAtlas, Props, Tiles},
+    AppState, GameConfig,
+};
 
 pub struct TileMapPlugin;
 
 impl Plugin for TileMapPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(create_simple_map);
+        app.register_type::<TileCollider>()
+            .register_type::<MapLayer>()
+            .register_type::<Tile>()
+            .register_type::<MapRoot>()
+            .add_event::<TileEdit>()
+            .add_event::<LoadMap>()
+            .add_startup_system(load_map)
+            .add_system_set(SystemSet::on_enter(AppState::InGame).with_system(create_simple_map))
+            .add_system(apply_tile_edits);
     }
 }
 
-#[derive(Component)]
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
 pub struct TileCollider;
 
-fn create_simple_map(mut commands: Commands, asset_server: Res<AssetServer>) {
-    let grass = asset_server.load("textures/rpg/tiles/generic-rpg-tile70.png");
-    let dirt = asset_server.load("textures/rpg/tiles/generic-rpg-tile71.png");
-    let fence = asset_server.load("textures/rpg/props/generic-rpg-fence03.png");
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Inspectable, Reflect, Serialize, Deserialize)]
+#[reflect_value(PartialEq, Serialize, Deserialize)]
+pub enum MapLayer {
+    #[default]
+    Background,
+    Foreground,
+}
 
-    let mut tiles = Vec::new();
+/// An entity spawned for one character of a map layer.
+/// Read only in the inspector: changing it wouldn't move the tile, send a
+/// `TileEdit` instead.
+#[derive(Component, Debug, Clone, Copy, Default, Inspectable, Reflect)]
+#[reflect(Component)]
+pub struct Tile {
+    #[inspectable(read_only)]
+    pub layer: MapLayer,
+    #[inspectable(read_only)]
+    pub x: usize,
+    #[inspectable(read_only)]
+    pub y: usize,
+}
+
+/// Parent of every tile entity.
+#[derive(Component, Default, Reflect)]
+#[reflect(Component)]
+pub struct MapRoot;
+
+/// Swaps the current map for another one under `assets/maps`, respawning
+/// everything on it. The player keeps what they carry and starts over.
+pub struct LoadMap(pub String);
+
+/// Changes one character of the current map, e.g. `'f'` to put up a fence or
+/// `' '` to clear one, respawning the tile.
+#[derive(Debug, Clone, Copy)]
+pub struct TileEdit {
+    pub layer: MapLayer,
+    pub position: (usize, usize),
+    pub tile: char,
+}
+
+/// Something placed on the map by `<map>.objects.ron` rather than by a tile character.
+#[derive(Debug, Clone, Deserialize)]
+pub enum MapObject {
+    Npc(NpcSpawn),
+    Sign(SignSpawn),
+    Item(ItemSpawn),
+    Chest(ChestSpawn),
+    Water(WaterSpawn),
+    Mob(MobSpawn),
+    Boss(BossSpawn),
+}
+
+/// How many pixels of a tile or prop image make up one tile.
+pub const TILE_PIXELS: f32 = 16.;
+
+/// Size of a prop sprite showing `index` of `atlas`.
+pub fn prop_size(atlas: &TextureAtlas, index: usize, tile_size: f32) -> Vec2 {
+    atlas
+        .textures
+        .get(index)
+        .map_or(Vec2::splat(tile_size), |rect| rect.size() / TILE_PIXELS * tile_size)
+}
+
+/// The layers and objects of the current map, as read from `assets/maps`.
+pub struct MapData {
+    pub name: String,
+    pub background: Vec<Vec<char>>,
+    pub foreground: Vec<Vec<char>>,
+    pub objects: Vec<MapObject>,
+    /// `GameConfig::tile_size` when the map was loaded, which its tiles were
+    /// spawned at.
+    pub tile_size: f32,
+}
+
+impl MapData {
+    pub fn load(name: &str, tile_size: f32) -> MapData {
+        let objects_path = format!("assets/maps/{name}.objects.ron");
+        let objects = match fs::read(&objects_path) {
+            Ok(bytes) => ron::de::from_bytes(&bytes).unwrap_or_else(|err| {
+                error!("Could not read `{objects_path}`: {err}");
+                Vec::new()
+            }),
+            Err(_) => Vec::new(),
+        };
+        MapData {
+            name: name.to_string(),
+            background: read_layer(&format!("assets/maps/{name}.bg")),
+            foreground: read_layer(&format!("assets/maps/{name}.ob")),
+            objects,
+            tile_size,
+        }
+    }
 
-    let file = File::open("assets/maps/path.bg").expect("No map found");
-
-    let mut bg_line_count = 0_usize;
-    let mut bg_char_count = 0_usize;
-    for (y, line) in BufReader::new(file).lines().enumerate() {
-        if let Ok(line) = line {
-            let mut current_char_count = 0_usize;
-            bg_line_count += 1;
-            for (x, c) in line.chars().enumerate() {
-                current_char_count += 1;
-                if c != 'g' && c != 'd' {
-                    continue;
+    /// Writes both layers back to `assets/maps`, one row per line.
+    pub fn save(&self) -> io::Result<()> {
+        for (rows, extension) in [(&self.background, "bg"), (&self.foreground, "ob")] {
+            let lines: Vec<String> = rows.iter().map(|row| row.iter().collect()).collect();
+            fs::write(format!("assets/maps/{}.{extension}", self.name), lines.join("\n"))?;
+        }
+        Ok(())
+    }
+
+    /// Whether `assets/maps` has both layers of a map called `name`.
+    pub fn exists(name: &str) -> bool {
+        ["bg", "ob"]
+            .iter()
+            .all(|extension| Path::new(&format!("assets/maps/{name}.{extension}")).is_file())
+    }
+
+    /// Every map under `assets/maps`, by name.
+    pub fn names() -> Vec<String> {
+        let entries = match fs::read_dir("assets/maps") {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+        entries
+            .filter_map(|entry| {
+                let path = entry.ok()?.path();
+                let name = path.file_name()?.to_str()?.strip_suffix(".bg")?.to_string();
+                MapData::exists(&name).then_some(name)
+            })
+            .collect()
+    }
+
+    pub fn width(&self) -> usize {
+        self.background
+            .iter()
+            .chain(&self.foreground)
+            .map(Vec::len)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn height(&self) -> usize {
+        usize::max(self.background.len(), self.foreground.len())
+    }
+
+    /// World position of the map's top left corner tile.
+    pub fn origin(&self) -> Vec2 {
+        Vec2::new(
+            -(self.width() as f32 * self.tile_size) / 2.,
+            (self.height() as f32 * self.tile_size) / 2.,
+        )
+    }
+
+    /// World position of the centre of tile (`x`, `y`), `y` counting rows down.
+    pub fn tile_to_world(&self, x: usize, y: usize) -> Vec2 {
+        self.origin() + Vec2::new(x as f32 * self.tile_size, -(y as f32) * self.tile_size)
+    }
+
+    /// The tile covering `position`, if it is on the map.
+    pub fn world_to_tile(&self, position: Vec2) -> Option<(usize, usize)> {
+        let offset = (position - self.origin()) / self.tile_size;
+        let (x, y) = ((offset.x + 0.5).floor(), (-offset.y + 0.5).floor());
+        if x < 0. || y < 0. || x as usize >= self.width() || y as usize >= self.height() {
+            return None;
+        }
+        Some((x as usize, y as usize))
+    }
+
+    pub fn background_at(&self, x: usize, y: usize) -> Option<char> {
+        self.tile_at(MapLayer::Background, x, y)
+    }
+
+    pub fn tile_at(&self, layer: MapLayer, x: usize, y: usize) -> Option<char> {
+        let rows = match layer {
+            MapLayer::Background => &self.background,
+            MapLayer::Foreground => &self.foreground,
+        };
+        rows.get(y)?.get(x).copied()
+    }
+
+    /// Sets a character of a layer, padding the layer with spaces to reach it.
+    pub fn set(&mut self, layer: MapLayer, x: usize, y: usize, tile: char) {
+        let rows = match layer {
+            MapLayer::Background => &mut self.background,
+            MapLayer::Foreground => &mut self.foreground,
+        };
+        if rows.len() <= y {
+            rows.resize(y + 1, Vec::new());
+        }
+        if rows[y].len() <= x {
+            rows[y].resize(x + 1, ' ');
+        }
+        rows[y][x] = tile;
+    }
+
+    /// Every tile that differs from `original`, as the edits that would turn
+    /// `original` into this map.
+    pub fn changes_from(&self, original: &MapData) -> Vec<TileEdit> {
+        let mut changes = Vec::new();
+        for layer in [MapLayer::Background, MapLayer::Foreground] {
+            for y in 0..self.height().max(original.height()) {
+                for x in 0..self.width().max(original.width()) {
+                    let tile = self.tile_at(layer, x, y).unwrap_or(' ');
+                    if tile != original.tile_at(layer, x, y).unwrap_or(' ') {
+                        changes.push(TileEdit {
+                            layer,
+                            position: (x, y),
+                            tile,
+                        });
+                    }
                 }
-                tiles.push(
-                    commands
-                        .spawn_bundle(SpriteBundle {
-                            sprite: Sprite {
-                                custom_size: Some(Vec2::splat(TILE_SIZE)),
-                                ..default()
-                            },
-                            texture: match c {
-                                'g' => grass.clone(),
-                                'd' => dirt.clone(),
-                                _ => grass.clone(),
-                            },
-                            transform: Transform {
-                                translation: Vec3::new(
-                                    x as f32 * TILE_SIZE,
-                                    -(y as f32) * TILE_SIZE,
-                                    0.,
-                                ),
-                                ..default()
-                            },
-                            ..default()
-                        })
-                        .insert(Name::new(match c {
-                            'g' => "Grass",
-                            'd' => "Dirt",
-                            _ => "Background"
-                        }))
-                        .id(),
-                );
-            }
-            if current_char_count > bg_char_count {
-                bg_char_count = current_char_count;
             }
         }
+        changes
     }
+}
 
-    let file = File::open("assets/maps/path.ob").expect("No map found");
+fn read_layer(path: &str) -> Vec<Vec<char>> {
+    let file = File::open(path).expect("No map found");
+    BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .map(|line| line.chars().collect())
+        .collect()
+}
 
+fn load_map(mut commands: Commands, config: Res<GameConfig>) {
+    commands.insert_resource(MapData::load(&config.map, config.tile_size));
+}
 
-    let mut ob_line_count = 0_usize;
-    let mut ob_char_count = 0_usize;
-    for (y, line) in BufReader::new(file).lines().enumerate() {
-        if let Ok(line) = line {
-            let mut current_char_count = 0_usize;
-            ob_line_count += 1;
-            for (x, c) in line.chars().enumerate() {
-                current_char_count += 1;
-                if c != 'f' {
-                    continue;
-                }
-                tiles.push(
-                    commands
-                        .spawn_bundle(SpriteBundle {
-                            sprite: Sprite {
-                                custom_size: Some(Vec2::splat(TILE_SIZE)),
-                                ..default()
-                            },
-                            texture: match c {
-                                'f' => fence.clone(),
-                                _ => fence.clone(),
-                            },
-                            transform: Transform {
-                                translation: Vec3::new(
-                                    x as f32 * TILE_SIZE,
-                                    -(y as f32) * TILE_SIZE,
-                                    1.,
-                                ),
-                                ..default()
-                            },
-                            ..default()
-                        })
-                        .insert(Name::new(match c {
-                            'f' => "Fence",
-                            _ => "Foreground"
-                        }))
-                        .insert(TileCollider)
-                        .id(),
+fn create_simple_map(
+    mut commands: Commands,
+    map: Res<MapData>,
+    tiles_sprites: Res<Tiles>,
+    props_sprites: Res<Props>,
+    named_atlases: Res<Assets<NamedAtlas>>,
+) {
+    let mut tiles = Vec::new();
+    for (layer, rows) in [
+        (MapLayer::Background, &map.background),
+        (MapLayer::Foreground, &map.foreground),
+    ] {
+        for (y, line) in rows.iter().enumerate() {
+            for (x, c) in line.iter().enumerate() {
+                let tile = Tile { layer, x, y };
+                let entity = spawn_tile(
+                    &mut commands,
+                    tile,
+                    *c,
+                    map.tile_size,
+                    &tiles_sprites,
+                    &props_sprites,
+                    &named_atlases,
                 );
-            }
-            if current_char_count > ob_char_count {
-                ob_char_count = current_char_count;
+                tiles.extend(entity);
             }
         }
     }
 
-    let line_count = usize::max(bg_line_count, ob_line_count);
-    let char_count = usize::max(bg_char_count, ob_char_count);
-
     commands
         .spawn()
         .insert(Name::new("Map"))
+        .insert(MapRoot)
         .insert(Transform {
-            translation: Vec3::new(-(char_count as f32 * TILE_SIZE) / 2., (line_count as f32 * TILE_SIZE) / 2., 0.),
+            translation: map.origin().extend(0.),
             ..default()
         })
         .insert(GlobalTransform::default())
@@ -138,3 +304,132 @@ fn create_simple_map(mut commands: Commands, asset_server: Res<AssetServer>) {
         .insert(ComputedVisibility::default())
         .push_children(&tiles);
 }
+
+/// What a map character is drawn as on one layer.
+#[derive(Debug, Clone, Copy)]
+pub struct TileKind {
+    pub layer: MapLayer,
+    pub c: char,
+    pub name: &'static str,
+    /// Frame in the `Tiles` atlases for the background, `Props` for the foreground.
+    pub frame: &'static str,
+    pub collides: bool,
+}
+
+/// Every character a map can use; others are left empty.
+pub const TILE_KINDS: &[TileKind] = &[
+    TileKind {
+        layer: MapLayer::Background,
+        c: 'g',
+        name: "Grass",
+        frame: "grass",
+        collides: false,
+    },
+    TileKind {
+        layer: MapLayer::Background,
+        c: 'd',
+        name: "Dirt",
+        frame: "dirt",
+        collides: false,
+    },
+    // Water bodies are drawn over the grass by the fishing module.
+    TileKind {
+        layer: MapLayer::Background,
+        c: 'w',
+        name: "Water",
+        frame: "grass",
+        collides: true,
+    },
+    TileKind {
+        layer: MapLayer::Foreground,
+        c: 'f',
+        name: "Fence",
+        frame: "fence-top",
+        collides: true,
+    },
+];
+
+pub fn tile_kind(layer: MapLayer, c: char) -> Option<&'static TileKind> {
+    TILE_KINDS.iter().find(|kind| kind.layer == layer && kind.c == c)
+}
+
+/// Spawns the entity for character `c` at `tile`, positioned relative to the
+/// map root. Characters without a sprite spawn nothing.
+fn spawn_tile(
+    commands: &mut Commands,
+    tile: Tile,
+    c: char,
+    tile_size: f32,
+    tiles_sprites: &Tiles,
+    props_sprites: &Props,
+    named_atlases: &Assets<NamedAtlas>,
+) -> Option<Entity> {
+    let kind = tile_kind(tile.layer, c)?;
+    let (sprites, z) = match tile.layer {
+        MapLayer::Background => (&tiles_sprites.0, 0.),
+        MapLayer::Foreground => (&props_sprites.0, 1.),
+    };
+    // Tiles without art, as in headless runs, are invisible but still collide.
+    let (texture_atlas, index) = sprites.frame(named_atlases, kind.frame).unwrap_or_else(|| {
+        warn!("No `{}` frame in the tile or prop atlases", kind.frame);
+        (Handle::default(), 0)
+    });
+    let mut entity = commands.spawn_bundle(SpriteSheetBundle {
+        sprite: TextureAtlasSprite {
+            index,
+            custom_size: Some(Vec2::splat(tile_size)),
+            ..default()
+        },
+        texture_atlas,
+        transform: Transform {
+            translation: Vec3::new(tile.x as f32 * tile_size, -(tile.y as f32) * tile_size, z),
+            ..default()
+        },
+        ..default()
+    });
+    entity.insert(Name::new(kind.name)).insert(tile);
+    if kind.collides {
+        entity.insert(TileCollider);
+    }
+    Some(entity.id())
+}
+
+/// Writes tile edits into `MapData` and swaps the edited tiles' entities.
+#[allow(clippy::too_many_arguments)]
+fn apply_tile_edits(
+    mut commands: Commands,
+    mut edits: EventReader<TileEdit>,
+    mut map: ResMut<MapData>,
+    tiles_sprites: Res<Tiles>,
+    props_sprites: Res<Props>,
+    named_atlases: Res<Assets<NamedAtlas>>,
+    root_query: Query<Entity, With<MapRoot>>,
+    tile_query: Query<(Entity, &Tile)>,
+) {
+    for edit in edits.iter() {
+        let (x, y) = edit.position;
+        map.set(edit.layer, x, y, edit.tile);
+        for (entity, tile) in &tile_query {
+            if (tile.layer, tile.x, tile.y) == (edit.layer, x, y) {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+        let tile = Tile {
+            layer: edit.layer,
+            x,
+            y,
+        };
+        let entity = spawn_tile(
+            &mut commands,
+            tile,
+            edit.tile,
+            map.tile_size,
+            &tiles_sprites,
+            &props_sprites,
+            &named_atlases,
+        );
+        if let (Some(entity), Ok(root)) = (entity, root_query.get_single()) {
+            commands.entity(root).add_child(entity);
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use bevy::prelude::*;
+use my_bevy_game::{
+    headless::Simulation,
+    tilemap::{MapData, MapLayer},
+};
+
+#[test]
+fn player_spawns_at_the_centre() {
+    let mut simulation = Simulation::new();
+    assert_eq!(simulation.player_position(), Vec2::ZERO);
+}
+
+#[test]
+fn holding_d_stops_at_the_east_fence() {
+    let mut simulation = Simulation::new();
+    simulation.press(KeyCode::D);
+    simulation.run_for(1.5);
+    let stopped = simulation.player_position();
+    simulation.run_for(0.5);
+    assert_eq!(simulation.player_position(), stopped);
+
+    // Four tiles east of the start there's a fence on the player's row.
+    let map = simulation.app.world.resource::<MapData>();
+    let (x, y) = map.world_to_tile(stopped).unwrap();
+    assert_eq!(map.tile_at(MapLayer::Foreground, x + 1, y), Some('f'));
+    assert!(stopped.x > 3. * map.tile_size);
+}
+
+#[test]
+fn releasing_stops_the_player() {
+    let mut simulation = Simulation::new();
+    simulation.press(KeyCode::W);
+    simulation.run_for(0.25);
+    simulation.release(KeyCode::W);
+    simulation.step(1);
+    let position = simulation.player_position();
+    assert!(position.y > 0.);
+    simulation.run_for(0.5);
+    assert_eq!(simulation.player_position(), position);
+}